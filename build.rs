@@ -2,6 +2,12 @@ use anyhow::*;
 use fs_extra::copy_items;
 use fs_extra::dir::CopyOptions;
 use std::env;
+use std::path::Path;
+
+// shared with the crate so the embedded archive is written by the same code that reads it
+#[path = "src/archive.rs"]
+#[allow(dead_code)]
+mod archive;
 
 fn main() -> Result<()> {
     // This tells cargo to rerun this script if something in /res/ changes.
@@ -10,9 +16,13 @@ fn main() -> Result<()> {
     let out_dir = env::var("OUT_DIR")?;
     let mut copy_options = CopyOptions::new();
     copy_options.overwrite = true;
-    let mut paths_to_copy = Vec::new();
-    paths_to_copy.push("res/");
-    copy_items(&paths_to_copy, out_dir, &copy_options)?;
+    let paths_to_copy = vec!["res/"];
+    copy_items(&paths_to_copy, &out_dir, &copy_options)?;
+
+    if env::var_os("CARGO_FEATURE_EMBED_ASSETS").is_some() {
+        let archive = archive::pack_dir(Path::new("res"), archive::Compression::Deflate)?;
+        std::fs::write(Path::new(&out_dir).join("res.pak"), archive.finish())?;
+    }
 
     Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,95 @@
+// Assigns point lights to view frustum clusters.
+// One invocation per cluster, the grid is CLUSTER_X * CLUSTER_Y tiles on screen
+// and CLUSTER_Z exponentially distributed depth slices between near and far.
+
+struct ClusterUniform {
+    view: mat4x4<f32>,
+    inv_projection: mat4x4<f32>,
+    screen_size: vec2<f32>,
+    near: f32,
+    far: f32,
+    // xyz: cluster grid dimensions, w: number of point lights
+    grid: vec4<u32>,
+}
+
+struct PointLight {
+    position: vec3<f32>,
+    radius: f32,
+    color: vec3<f32>,
+    intensity: f32,
+}
+
+// must match MAX_LIGHTS_PER_CLUSTER in clustered.rs
+const MAX_LIGHTS_PER_CLUSTER: u32 = 128u;
+
+@group(0) @binding(0)
+var<uniform> cluster: ClusterUniform;
+@group(0) @binding(1)
+var<storage, read> lights: array<PointLight>;
+// per cluster: [light count, light indices...]
+@group(0) @binding(2)
+var<storage, read_write> cluster_lights: array<u32>;
+
+fn slice_depth(slice: u32) -> f32 {
+    return cluster.near * pow(cluster.far / cluster.near, f32(slice) / f32(cluster.grid.z));
+}
+
+// view space point on the near plane for a screen uv with top left origin
+fn screen_to_view(uv: vec2<f32>) -> vec3<f32> {
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    let view = cluster.inv_projection * ndc;
+    return view.xyz / view.w;
+}
+
+// moves a view space point along its ray from the eye to the given distance
+fn at_depth(point: vec3<f32>, depth: f32) -> vec3<f32> {
+    return point * (depth / -point.z);
+}
+
+fn sphere_intersects_aabb(center: vec3<f32>, radius: f32, aabb_min: vec3<f32>, aabb_max: vec3<f32>) -> bool {
+    let closest = clamp(center, aabb_min, aabb_max);
+    let d = closest - center;
+    return dot(d, d) <= radius * radius;
+}
+
+@compute @workgroup_size(4, 4, 4)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (id.x >= cluster.grid.x || id.y >= cluster.grid.y || id.z >= cluster.grid.z) {
+        return;
+    }
+
+    let tile_size = vec2<f32>(1.0 / f32(cluster.grid.x), 1.0 / f32(cluster.grid.y));
+    let uv_min = vec2<f32>(f32(id.x), f32(id.y)) * tile_size;
+    let uv_max = uv_min + tile_size;
+
+    let near_depth = slice_depth(id.z);
+    let far_depth = slice_depth(id.z + 1u);
+
+    let corner_min = screen_to_view(uv_min);
+    let corner_max = screen_to_view(uv_max);
+
+    let p0 = at_depth(corner_min, near_depth);
+    let p1 = at_depth(corner_min, far_depth);
+    let p2 = at_depth(corner_max, near_depth);
+    let p3 = at_depth(corner_max, far_depth);
+
+    let aabb_min = min(min(p0, p1), min(p2, p3));
+    let aabb_max = max(max(p0, p1), max(p2, p3));
+
+    let cluster_index = id.x + id.y * cluster.grid.x + id.z * cluster.grid.x * cluster.grid.y;
+    let offset = cluster_index * (MAX_LIGHTS_PER_CLUSTER + 1u);
+
+    var count = 0u;
+    for (var i = 0u; i < cluster.grid.w; i = i + 1u) {
+        let light = lights[i];
+        let center = (cluster.view * vec4<f32>(light.position, 1.0)).xyz;
+        if (sphere_intersects_aabb(center, light.radius, aabb_min, aabb_max)) {
+            cluster_lights[offset + 1u + count] = i;
+            count = count + 1u;
+            if (count == MAX_LIGHTS_PER_CLUSTER) {
+                break;
+            }
+        }
+    }
+    cluster_lights[offset] = count;
+}
@@ -0,0 +1,160 @@
+// Vertex shader
+
+struct VertexInput {
+    @location(0) pos: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) normal: vec3<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) world_position: vec3<f32>,
+};
+
+struct ModelMatrix {
+    @location(3) model_matrix_0: vec4<f32>,
+    @location(4) model_matrix_1: vec4<f32>,
+    @location(5) model_matrix_2: vec4<f32>,
+    @location(6) model_matrix_3: vec4<f32>,
+
+    @location(7) normal_matrix_0: vec3<f32>,
+    @location(8) normal_matrix_1: vec3<f32>,
+    @location(9) normal_matrix_2: vec3<f32>,
+}
+
+struct CameraUniform {
+    view_projection: mat4x4<f32>,
+}
+
+@group(1) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct Light {
+    position: vec3<f32>,
+    color: vec3<f32>,
+}
+
+@group(2) @binding(0)
+var<uniform> light: Light;
+
+@vertex
+fn vs_main(
+    vertex_input: VertexInput,
+    model: ModelMatrix,
+) -> VertexOutput {
+
+    // MODEL MATRIX
+    let model_matrix = mat4x4<f32>(
+        model.model_matrix_0,
+        model.model_matrix_1,
+        model.model_matrix_2,
+        model.model_matrix_3,
+    );
+    // NORMAL MATRIX
+    let normal_matrix = mat3x3<f32>(
+        model.normal_matrix_0,
+        model.normal_matrix_1,
+        model.normal_matrix_2,
+    );
+
+    var out: VertexOutput;
+    out.tex_coords = vertex_input.tex_coords;
+    out.world_normal = normal_matrix * vertex_input.normal;
+    var world_position: vec4<f32> = model_matrix * vec4<f32>(vertex_input.pos, 1.0);
+    out.world_position = world_position.xyz;
+    out.clip_position = camera.view_projection * world_position;
+    return out;
+}
+
+// Fragment shader
+
+// bind group nr. 0
+@group(0) @binding(0)
+var t_diffuse: texture_2d<f32>;
+@group(0)@binding(1)
+var s_diffuse: sampler;
+
+struct MaterialUniform {
+    opacity: f32,
+    // 0 unless the material uses alpha cutout
+    alpha_cutoff: f32,
+}
+
+@group(0) @binding(2)
+var<uniform> material: MaterialUniform;
+
+struct ClusterUniform {
+    view: mat4x4<f32>,
+    inv_projection: mat4x4<f32>,
+    screen_size: vec2<f32>,
+    near: f32,
+    far: f32,
+    // xyz: cluster grid dimensions, w: number of point lights
+    grid: vec4<u32>,
+}
+
+struct PointLight {
+    position: vec3<f32>,
+    radius: f32,
+    color: vec3<f32>,
+    intensity: f32,
+}
+
+// must match MAX_LIGHTS_PER_CLUSTER in clustered.rs
+const MAX_LIGHTS_PER_CLUSTER: u32 = 128u;
+
+@group(3) @binding(0)
+var<uniform> cluster: ClusterUniform;
+@group(3) @binding(1)
+var<storage, read> lights: array<PointLight>;
+@group(3) @binding(2)
+var<storage, read> cluster_lights: array<u32>;
+
+fn cluster_index(frag_coord: vec2<f32>, view_depth: f32) -> u32 {
+    let tile = vec2<u32>(clamp(
+        frag_coord / cluster.screen_size * vec2<f32>(cluster.grid.xy),
+        vec2<f32>(0.0),
+        vec2<f32>(cluster.grid.xy) - 1.0,
+    ));
+    let slice_f = log(view_depth / cluster.near) / log(cluster.far / cluster.near) * f32(cluster.grid.z);
+    let slice = u32(clamp(slice_f, 0.0, f32(cluster.grid.z) - 1.0));
+    return tile.x + tile.y * cluster.grid.x + slice * cluster.grid.x * cluster.grid.y;
+}
+
+fn attenuation(distance: f32, radius: f32) -> f32 {
+    let ratio = clamp(1.0 - pow(distance / radius, 4.0), 0.0, 1.0);
+    return ratio * ratio / (distance * distance + 1.0);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let a_s = 0.05;
+    let a = a_s * light.color;
+
+    let light_dir = normalize(light.position - in.world_position);
+
+    let d_s = max(dot(in.world_normal, light_dir), 0.0);
+    var d = d_s * light.color;
+
+    let view_depth = -(cluster.view * vec4<f32>(in.world_position, 1.0)).z;
+    let offset = cluster_index(in.clip_position.xy, view_depth) * (MAX_LIGHTS_PER_CLUSTER + 1u);
+    let count = cluster_lights[offset];
+    for (var i = 0u; i < count; i = i + 1u) {
+        let point_light = lights[cluster_lights[offset + 1u + i]];
+        let to_light = point_light.position - in.world_position;
+        let distance = length(to_light);
+        let n_dot_l = max(dot(in.world_normal, to_light / distance), 0.0);
+        d = d + n_dot_l * attenuation(distance, point_light.radius) * point_light.intensity * point_light.color;
+    }
+
+    let texture_col = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    let alpha = texture_col.a * material.opacity;
+    if (alpha < material.alpha_cutoff) {
+        discard;
+    }
+    let result = (a + d) * texture_col.xyz;
+
+    return vec4<f32>(result, alpha);
+}
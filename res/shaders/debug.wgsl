@@ -0,0 +1,119 @@
+// Debug visualizations replacing the lit shading
+
+struct VertexInput {
+    @location(0) pos: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) normal: vec3<f32>,
+}
+
+struct ModelMatrix {
+    @location(3) model_matrix_0: vec4<f32>,
+    @location(4) model_matrix_1: vec4<f32>,
+    @location(5) model_matrix_2: vec4<f32>,
+    @location(6) model_matrix_3: vec4<f32>,
+
+    @location(7) normal_matrix_0: vec3<f32>,
+    @location(8) normal_matrix_1: vec3<f32>,
+    @location(9) normal_matrix_2: vec3<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) world_normal: vec3<f32>,
+};
+
+struct CameraUniform {
+    view_projection: mat4x4<f32>,
+    inv_view_projection: mat4x4<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct DebugUniform {
+    // must match DebugView in debug_view.rs:
+    // 1 normals, 2 uv checker, 3 depth, 4 mesh colors
+    view: u32,
+    near: f32,
+    far: f32,
+}
+
+struct MeshUniform {
+    index: u32,
+}
+
+@group(1) @binding(0)
+var<uniform> debug: DebugUniform;
+// bound with a dynamic offset per mesh
+@group(1) @binding(1)
+var<uniform> mesh: MeshUniform;
+
+@vertex
+fn vs_main(
+    vertex_input: VertexInput,
+    model: ModelMatrix,
+) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        model.model_matrix_0,
+        model.model_matrix_1,
+        model.model_matrix_2,
+        model.model_matrix_3,
+    );
+    let normal_matrix = mat3x3<f32>(
+        model.normal_matrix_0,
+        model.normal_matrix_1,
+        model.normal_matrix_2,
+    );
+
+    var out: VertexOutput;
+    out.tex_coords = vertex_input.tex_coords;
+    out.world_normal = normal_matrix * vertex_input.normal;
+    out.clip_position = camera.view_projection * model_matrix * vec4<f32>(vertex_input.pos, 1.0);
+    return out;
+}
+
+fn hash(value: u32) -> u32 {
+    var x = value;
+    x = ((x >> 16u) ^ x) * 0x45d9f3bu;
+    x = ((x >> 16u) ^ x) * 0x45d9f3bu;
+    x = (x >> 16u) ^ x;
+    return x;
+}
+
+fn mesh_color(index: u32) -> vec3<f32> {
+    let h = hash(index + 1u);
+    return vec3<f32>(
+        f32(h & 0xffu),
+        f32((h >> 8u) & 0xffu),
+        f32((h >> 16u) & 0xffu),
+    ) / 255.0;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    switch debug.view {
+        case 1u: {
+            return vec4<f32>(normalize(in.world_normal) * 0.5 + 0.5, 1.0);
+        }
+        case 2u: {
+            let cell = vec2<i32>(floor(in.tex_coords * 8.0));
+            let checker = f32((cell.x + cell.y) & 1);
+            // red and green tint shows the uv direction
+            return vec4<f32>(mix(vec3<f32>(0.1), vec3<f32>(0.9), checker) * vec3<f32>(0.5 + 0.5 * fract(in.tex_coords), 1.0), 1.0);
+        }
+        case 3u: {
+            // clip_position.z is 0..1 depth, linearize it so the near geometry isn't all black
+            let z = in.clip_position.z;
+            let linear = debug.near * debug.far / (debug.far - z * (debug.far - debug.near));
+            let shade = 1.0 - clamp((linear - debug.near) / (debug.far - debug.near), 0.0, 1.0);
+            return vec4<f32>(vec3<f32>(pow(shade, 8.0)), 1.0);
+        }
+        case 4u: {
+            return vec4<f32>(mesh_color(mesh.index), 1.0);
+        }
+        default: {
+            return vec4<f32>(1.0, 0.0, 1.0, 1.0);
+        }
+    }
+}
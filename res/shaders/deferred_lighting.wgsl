@@ -0,0 +1,73 @@
+// Fullscreen lighting pass reading the G-buffer
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+// single triangle covering the whole screen
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.uv = uv;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    return out;
+}
+
+@group(0) @binding(0)
+var t_albedo: texture_2d<f32>;
+@group(0) @binding(1)
+var t_normal: texture_2d<f32>;
+@group(0) @binding(2)
+var t_material: texture_2d<f32>;
+@group(0) @binding(3)
+var t_depth: texture_depth_2d;
+
+struct CameraUniform {
+    view_projection: mat4x4<f32>,
+    inv_view_projection: mat4x4<f32>,
+}
+
+@group(1) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct Light {
+    position: vec3<f32>,
+    color: vec3<f32>,
+}
+
+@group(2) @binding(0)
+var<uniform> light: Light;
+
+fn world_position_from_depth(uv: vec2<f32>, depth: f32) -> vec3<f32> {
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    let world = camera.inv_view_projection * ndc;
+    return world.xyz / world.w;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(floor(in.clip_position.xy));
+    let material = textureLoad(t_material, pixel, 0);
+    // nothing was drawn here, keep the clear color
+    if (material.y == 0.0) {
+        discard;
+    }
+
+    let albedo = textureLoad(t_albedo, pixel, 0);
+    let normal = textureLoad(t_normal, pixel, 0).xyz;
+    let depth = textureLoad(t_depth, pixel, 0);
+    let world_position = world_position_from_depth(in.uv, depth);
+
+    let a = material.x * light.color;
+
+    let light_dir = normalize(light.position - world_position);
+
+    let d_s = max(dot(normal, light_dir), 0.0);
+    let d = d_s * light.color;
+
+    let result = (a + d) * albedo.xyz;
+
+    return vec4<f32>(result, albedo.a);
+}
@@ -0,0 +1,93 @@
+// Vertex shader
+
+struct VertexInput {
+    @location(0) pos: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) normal: vec3<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) world_normal: vec3<f32>,
+};
+
+struct ModelMatrix {
+    @location(3) model_matrix_0: vec4<f32>,
+    @location(4) model_matrix_1: vec4<f32>,
+    @location(5) model_matrix_2: vec4<f32>,
+    @location(6) model_matrix_3: vec4<f32>,
+
+    @location(7) normal_matrix_0: vec3<f32>,
+    @location(8) normal_matrix_1: vec3<f32>,
+    @location(9) normal_matrix_2: vec3<f32>,
+}
+
+struct CameraUniform {
+    view_projection: mat4x4<f32>,
+    inv_view_projection: mat4x4<f32>,
+}
+
+@group(1) @binding(0)
+var<uniform> camera: CameraUniform;
+
+@vertex
+fn vs_main(
+    vertex_input: VertexInput,
+    model: ModelMatrix,
+) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        model.model_matrix_0,
+        model.model_matrix_1,
+        model.model_matrix_2,
+        model.model_matrix_3,
+    );
+    let normal_matrix = mat3x3<f32>(
+        model.normal_matrix_0,
+        model.normal_matrix_1,
+        model.normal_matrix_2,
+    );
+
+    var out: VertexOutput;
+    out.tex_coords = vertex_input.tex_coords;
+    out.world_normal = normal_matrix * vertex_input.normal;
+    out.clip_position = camera.view_projection * model_matrix * vec4<f32>(vertex_input.pos, 1.0);
+    return out;
+}
+
+// Fragment shader
+
+@group(0) @binding(0)
+var t_diffuse: texture_2d<f32>;
+@group(0)@binding(1)
+var s_diffuse: sampler;
+
+struct MaterialUniform {
+    opacity: f32,
+    // 0 unless the material uses alpha cutout
+    alpha_cutoff: f32,
+}
+
+@group(0) @binding(2)
+var<uniform> material: MaterialUniform;
+
+struct GBufferOutput {
+    @location(0) albedo: vec4<f32>,
+    @location(1) normal: vec4<f32>,
+    // x: ambient strength, y: 1.0 marks lit geometry
+    @location(2) material: vec4<f32>,
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> GBufferOutput {
+    var out: GBufferOutput;
+    out.albedo = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    out.albedo.a = out.albedo.a * material.opacity;
+    if (out.albedo.a < material.alpha_cutoff) {
+        discard;
+    }
+    // the forward shader uses the interpolated normal as is, so we store it without normalizing
+    out.normal = vec4<f32>(in.world_normal, 0.0);
+    out.material = vec4<f32>(0.05, 1.0, 0.0, 0.0);
+    return out;
+}
@@ -0,0 +1,152 @@
+// Per instance frustum and Hi-Z occlusion culling writing indirect draw arguments
+
+struct CullUniform {
+    view_proj: mat4x4<f32>,
+    // xyz: inward normal, w: distance
+    planes: array<vec4<f32>, 6>,
+    screen_size: vec2<f32>,
+    object_count: u32,
+    // mip count of the Hi-Z pyramid, 0 disables occlusion culling
+    hiz_levels: u32,
+    camera_position: vec3<f32>,
+    // 1 / tan(fovy / 2)
+    lod_scale: f32,
+}
+
+// one mesh of one instance
+struct Object {
+    // model space bounding sphere of the mesh
+    center: vec3<f32>,
+    radius: f32,
+    // draw arguments of the full detail mesh, followed by those of its coarser levels
+    batch: u32,
+    // matrix in `instances`
+    instance: u32,
+    // first slot of that batch in `visible`
+    base: u32,
+    // batches of the mesh, 1 without coarser levels
+    lod_count: u32,
+    // each coarser level is used below this fraction of the screen height
+    lod_screen_sizes: vec3<f32>,
+    // slots per batch in `visible`
+    level_slots: u32,
+}
+
+struct DrawArgs {
+    index_count: u32,
+    instance_count: atomic<u32>,
+    first_index: u32,
+    base_vertex: i32,
+    first_instance: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> cull: CullUniform;
+// RawModelMatrix entries as plain floats, their 100 byte stride doesn't fit a WGSL struct
+@group(0) @binding(1)
+var<storage, read> instances: array<f32>;
+@group(0) @binding(2)
+var<storage, read> objects: array<Object>;
+@group(0) @binding(3)
+var<storage, read_write> draws: array<DrawArgs>;
+@group(0) @binding(4)
+var<storage, read_write> visible: array<f32>;
+// max depth pyramid of the previous frame
+@group(0) @binding(5)
+var hiz: texture_2d<f32>;
+
+const MATRIX_FLOATS: u32 = 25u;
+
+fn model_matrix(instance: u32) -> mat4x4<f32> {
+    let b = instance * MATRIX_FLOATS;
+    return mat4x4<f32>(
+        vec4<f32>(instances[b], instances[b + 1u], instances[b + 2u], instances[b + 3u]),
+        vec4<f32>(instances[b + 4u], instances[b + 5u], instances[b + 6u], instances[b + 7u]),
+        vec4<f32>(instances[b + 8u], instances[b + 9u], instances[b + 10u], instances[b + 11u]),
+        vec4<f32>(instances[b + 12u], instances[b + 13u], instances[b + 14u], instances[b + 15u]),
+    );
+}
+
+fn occluded(center: vec3<f32>, radius: f32) -> bool {
+    // screen rectangle and nearest depth of the sphere's bounding box
+    var uv_min = vec2<f32>(1.0, 1.0);
+    var uv_max = vec2<f32>(0.0, 0.0);
+    var nearest = 1.0;
+    for (var i = 0u; i < 8u; i = i + 1u) {
+        let offset = vec3<f32>(
+            select(-1.0, 1.0, (i & 1u) != 0u),
+            select(-1.0, 1.0, (i & 2u) != 0u),
+            select(-1.0, 1.0, (i & 4u) != 0u),
+        );
+        let clip = cull.view_proj * vec4<f32>(center + offset * radius, 1.0);
+        // reaches behind the camera, can't be projected
+        if (clip.w <= 0.0) {
+            return false;
+        }
+        let ndc = clip.xyz / clip.w;
+        let uv = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+        uv_min = min(uv_min, uv);
+        uv_max = max(uv_max, uv);
+        nearest = min(nearest, ndc.z);
+    }
+    uv_min = clamp(uv_min, vec2<f32>(0.0, 0.0), vec2<f32>(1.0, 1.0));
+    uv_max = clamp(uv_max, vec2<f32>(0.0, 0.0), vec2<f32>(1.0, 1.0));
+
+    // the level where the rectangle spans at most two texels in each direction
+    let size = (uv_max - uv_min) * cull.screen_size;
+    let level = i32(min(u32(ceil(log2(max(max(size.x, size.y), 1.0)))), cull.hiz_levels - 1u));
+    let dims = vec2<i32>(textureDimensions(hiz, level));
+    let last = dims - vec2<i32>(1, 1);
+    let lo = clamp(vec2<i32>(uv_min * vec2<f32>(dims)), vec2<i32>(0, 0), last);
+    let hi = clamp(vec2<i32>(uv_max * vec2<f32>(dims)), vec2<i32>(0, 0), last);
+
+    let occluder = max(
+        max(textureLoad(hiz, lo, level).r, textureLoad(hiz, vec2<i32>(hi.x, lo.y), level).r),
+        max(textureLoad(hiz, vec2<i32>(lo.x, hi.y), level).r, textureLoad(hiz, hi, level).r),
+    );
+    return nearest > occluder;
+}
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if (index >= cull.object_count) {
+        return;
+    }
+    let object = objects[index];
+
+    let model = model_matrix(object.instance);
+    let center = (model * vec4<f32>(object.center, 1.0)).xyz;
+    let scale = max(length(model[0].xyz), max(length(model[1].xyz), length(model[2].xyz)));
+    let radius = object.radius * scale;
+
+    for (var i = 0; i < 6; i = i + 1) {
+        let plane = cull.planes[i];
+        if (dot(plane.xyz, center) + plane.w < -radius) {
+            return;
+        }
+    }
+    if (cull.hiz_levels > 0u && occluded(center, radius)) {
+        return;
+    }
+
+    // coarsest level whose threshold the instance is below, like `lod::select_lod` but
+    // without hysteresis since nothing is kept between frames
+    var level = 0u;
+    let distance = length(center - cull.camera_position);
+    if (distance > radius) {
+        let screen_size = radius * cull.lod_scale / distance;
+        for (var i = 1u; i < object.lod_count; i = i + 1u) {
+            if (screen_size < object.lod_screen_sizes[i - 1u]) {
+                level = i;
+            }
+        }
+    }
+
+    let slot = atomicAdd(&draws[object.batch + level].instance_count, 1u);
+    let src = object.instance * MATRIX_FLOATS;
+    let dst = (object.base + level * object.level_slots + slot) * MATRIX_FLOATS;
+    for (var i = 0u; i < MATRIX_FLOATS; i = i + 1u) {
+        visible[dst + i] = instances[src + i];
+    }
+}
@@ -0,0 +1,39 @@
+// Max depth pyramid for occlusion culling
+
+@group(0) @binding(0)
+var depth: texture_depth_2d;
+@group(0) @binding(1)
+var dst: texture_storage_2d<r32float, write>;
+@group(0) @binding(2)
+var src: texture_2d<f32>;
+
+// level 0 is a copy of the depth buffer
+@compute @workgroup_size(8, 8)
+fn cs_copy(@builtin(global_invocation_id) id: vec3<u32>) {
+    let dims = vec2<u32>(textureDimensions(dst));
+    if (id.x >= dims.x || id.y >= dims.y) {
+        return;
+    }
+    let coords = vec2<i32>(id.xy);
+    textureStore(dst, coords, vec4<f32>(textureLoad(depth, coords, 0), 0.0, 0.0, 0.0));
+}
+
+// every texel keeps the furthest depth of the texels it covers in the level above,
+// a 3x3 footprint so odd sizes don't drop the last row or column
+@compute @workgroup_size(8, 8)
+fn cs_downsample(@builtin(global_invocation_id) id: vec3<u32>) {
+    let dims = vec2<u32>(textureDimensions(dst));
+    if (id.x >= dims.x || id.y >= dims.y) {
+        return;
+    }
+    let last = vec2<i32>(textureDimensions(src)) - vec2<i32>(1, 1);
+    let base = vec2<i32>(id.xy) * 2;
+    var furthest = 0.0;
+    for (var y = 0; y < 3; y = y + 1) {
+        for (var x = 0; x < 3; x = x + 1) {
+            let coords = min(base + vec2<i32>(x, y), last);
+            furthest = max(furthest, textureLoad(src, coords, 0).r);
+        }
+    }
+    textureStore(dst, vec2<i32>(id.xy), vec4<f32>(furthest, 0.0, 0.0, 0.0));
+}
@@ -0,0 +1,33 @@
+// Resolves the weighted blended OIT targets over the opaque image
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+};
+
+// single triangle covering the whole screen
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    return out;
+}
+
+@group(0) @binding(0)
+var t_accum: texture_2d<f32>;
+@group(0) @binding(1)
+var t_revealage: texture_2d<f32>;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(floor(in.clip_position.xy));
+    let revealage = textureLoad(t_revealage, pixel, 0).r;
+    // no transparent fragment touched this pixel
+    if (revealage >= 1.0) {
+        discard;
+    }
+
+    let accum = textureLoad(t_accum, pixel, 0);
+    let average_color = accum.rgb / max(accum.a, 0.00001);
+    return vec4<f32>(average_color, 1.0 - revealage);
+}
@@ -4,6 +4,8 @@ struct VertexInput {
     @location(0) pos: vec3<f32>,
     @location(1) tex_coords: vec2<f32>,
     @location(2) normal: vec3<f32>,
+    // w is -1 where the texture is mirrored
+    @location(10) tangent: vec4<f32>,
 }
 
 struct VertexOutput {
@@ -11,6 +13,7 @@ struct VertexOutput {
     @location(0) tex_coords: vec2<f32>,
     @location(1) world_normal: vec3<f32>,
     @location(2) world_position: vec3<f32>,
+    @location(3) world_tangent: vec4<f32>,
 };
 
 struct ModelMatrix {
@@ -26,6 +29,8 @@ struct ModelMatrix {
 
 struct CameraUniform {
     view_projection: mat4x4<f32>,
+    inv_view_projection: mat4x4<f32>,
+    view_position: vec4<f32>,
 }
 
 @group(1) @binding(0)
@@ -62,6 +67,10 @@ fn vs_main(
     var out: VertexOutput;
     out.tex_coords = vertex_input.tex_coords;
     out.world_normal = normal_matrix * vertex_input.normal;
+    // a mirroring model matrix flips the handedness of the tangent frame
+    let linear_part = mat3x3<f32>(model_matrix[0].xyz, model_matrix[1].xyz, model_matrix[2].xyz);
+    let handedness = vertex_input.tangent.w * sign(determinant(linear_part));
+    out.world_tangent = vec4<f32>(linear_part * vertex_input.tangent.xyz, handedness);
     var world_position: vec4<f32> = model_matrix * vec4<f32>(vertex_input.pos, 1.0);
     out.world_position = world_position.xyz;
     out.clip_position = camera.view_projection * world_position;
@@ -76,18 +85,152 @@ var t_diffuse: texture_2d<f32>;
 @group(0)@binding(1)
 var s_diffuse: sampler;
 
-@fragment
-fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+struct MaterialUniform {
+    opacity: f32,
+    // 0 unless the material uses alpha cutout
+    alpha_cutoff: f32,
+    // 0 unless the material has a height map
+    height_scale: f32,
+    parallax_steps: f32,
+    // 1 to trace the height map toward the light
+    parallax_shadows: f32,
+}
+
+@group(0) @binding(2)
+var<uniform> material: MaterialUniform;
+
+// white is the surface, black is height_scale below it
+@group(0) @binding(3)
+var t_height: texture_2d<f32>;
+
+// how dark a bump makes the texels behind it for each unit of depth it rises above the light ray
+const PARALLAX_SHADOW_SHARPNESS: f32 = 16.0;
+
+// Parallax occlusion mapping. Directions are in tangent space: x along +u, y up the
+// texture, which is -v, and z out of the surface. The march samples with the gradients
+// of the unshifted coordinates because its loops aren't uniform control flow.
+
+fn depth_at(uv: vec2<f32>, uv_dx: vec2<f32>, uv_dy: vec2<f32>) -> f32 {
+    return 1.0 - textureSampleGrad(t_height, s_diffuse, uv, uv_dx, uv_dy).r;
+}
+
+fn to_uv(tangent_offset: vec2<f32>) -> vec2<f32> {
+    return vec2<f32>(tangent_offset.x, -tangent_offset.y);
+}
+
+struct ParallaxHit {
+    uv: vec2<f32>,
+    depth: f32,
+}
+
+// Follows the view ray into the height field layer by layer and interpolates
+// between the last layers above and below it.
+fn parallax_march(uv: vec2<f32>, view_dir: vec3<f32>, uv_dx: vec2<f32>, uv_dy: vec2<f32>) -> ParallaxHit {
+    // looking straight at the surface the offset barely changes, so fewer layers do
+    let layers = mix(material.parallax_steps, max(material.parallax_steps * 0.25, 1.0), abs(view_dir.z));
+    let layer_depth = 1.0 / layers;
+    let delta = -to_uv(view_dir.xy / max(view_dir.z, 0.05)) * material.height_scale * layer_depth;
+
+    var current_uv = uv;
+    var current_depth = 0.0;
+    var surface_depth = depth_at(current_uv, uv_dx, uv_dy);
+    let count = u32(ceil(layers));
+    for (var i = 0u; i < count && current_depth < surface_depth; i = i + 1u) {
+        current_uv = current_uv + delta;
+        current_depth = current_depth + layer_depth;
+        surface_depth = depth_at(current_uv, uv_dx, uv_dy);
+    }
+
+    let previous_uv = current_uv - delta;
+    let previous_depth = max(current_depth - layer_depth, 0.0);
+    let below = surface_depth - current_depth;
+    let above = depth_at(previous_uv, uv_dx, uv_dy) - previous_depth;
+    let weight = clamp(below / min(below - above, -0.0001), 0.0, 1.0);
+
+    var hit: ParallaxHit;
+    hit.uv = mix(current_uv, previous_uv, weight);
+    hit.depth = mix(current_depth, previous_depth, weight);
+    return hit;
+}
+
+// 1 where the light reaches the hit point, less where the height field rises above
+// the ray toward the light, more so close to the hit point.
+fn parallax_shadow(hit: ParallaxHit, light_dir: vec3<f32>, uv_dx: vec2<f32>, uv_dy: vec2<f32>) -> f32 {
+    if (material.parallax_shadows == 0.0 || light_dir.z <= 0.0 || hit.depth <= 0.0) {
+        return 1.0;
+    }
+    let count = u32(max(ceil(material.parallax_steps * hit.depth), 1.0));
+    let layer_depth = hit.depth / f32(count);
+    let delta = to_uv(light_dir.xy / light_dir.z) * material.height_scale * layer_depth;
+
+    var occlusion = 0.0;
+    var current_uv = hit.uv + delta;
+    var current_depth = hit.depth - layer_depth;
+    for (var i = 1u; i < count; i = i + 1u) {
+        let rise = current_depth - depth_at(current_uv, uv_dx, uv_dy);
+        occlusion = max(occlusion, rise * (1.0 - f32(i) / f32(count)));
+        current_uv = current_uv + delta;
+        current_depth = current_depth - layer_depth;
+    }
+    return 1.0 - clamp(occlusion * PARALLAX_SHADOW_SHARPNESS, 0.0, 1.0);
+}
+
+fn shade(in: VertexOutput) -> vec4<f32> {
     let a_s = 0.05;
     let a = a_s * light.color;
 
     let light_dir = normalize(light.position - in.world_position);
 
-    let d_s = max(dot(in.world_normal, light_dir), 0.0);
+    var tex_coords = in.tex_coords;
+    var shadow = 1.0;
+    let uv_dx = dpdx(in.tex_coords);
+    let uv_dy = dpdy(in.tex_coords);
+    if (material.height_scale > 0.0) {
+        let n = normalize(in.world_normal);
+        let t = normalize(in.world_tangent.xyz - n * dot(n, in.world_tangent.xyz));
+        let b = cross(n, t) * in.world_tangent.w;
+        let to_tangent = transpose(mat3x3<f32>(t, b, n));
+
+        let view_dir = to_tangent * normalize(camera.view_position.xyz - in.world_position);
+        let hit = parallax_march(in.tex_coords, view_dir, uv_dx, uv_dy);
+        tex_coords = hit.uv;
+        shadow = parallax_shadow(hit, to_tangent * light_dir, uv_dx, uv_dy);
+    }
+
+    let d_s = max(dot(in.world_normal, light_dir), 0.0) * shadow;
     let d = d_s * light.color;
 
-    let texture_col = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    let texture_col = textureSampleGrad(t_diffuse, s_diffuse, tex_coords, uv_dx, uv_dy);
+    let alpha = texture_col.a * material.opacity;
+    if (alpha < material.alpha_cutoff) {
+        discard;
+    }
     let result = (a + d) * texture_col.xyz;
 
-    return vec4<f32>(result, texture_col.a);
-}
\ No newline at end of file
+    return vec4<f32>(result, alpha);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return shade(in);
+}
+
+// Weighted blended order independent transparency (McGuire and Bavoil 2013)
+
+struct OitOutput {
+    @location(0) accum: vec4<f32>,
+    @location(1) revealage: f32,
+}
+
+@fragment
+fn fs_oit(in: VertexOutput) -> OitOutput {
+    let color = shade(in);
+    // favours fragments close to the camera, clip_position.z is the 0..1 depth
+    let z = in.clip_position.z;
+    let weight = clamp(color.a * max(0.01, 3000.0 * pow(1.0 - z, 3.0)), 0.01, 3000.0);
+
+    var out: OitOutput;
+    out.accum = vec4<f32>(color.rgb * color.a, color.a) * weight;
+    out.revealage = color.a;
+    return out;
+}
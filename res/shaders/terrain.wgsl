@@ -0,0 +1,88 @@
+// Heightmap terrain, the vertices are already in world space
+
+struct VertexInput {
+    @location(0) pos: vec3<f32>,
+    // 0 to 1 across the whole terrain
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) normal: vec3<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) world_position: vec3<f32>,
+};
+
+struct CameraUniform {
+    view_projection: mat4x4<f32>,
+}
+
+@group(1) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct Light {
+    position: vec3<f32>,
+    color: vec3<f32>,
+}
+
+@group(2) @binding(0)
+var<uniform> light: Light;
+
+@vertex
+fn vs_main(vertex_input: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.tex_coords = vertex_input.tex_coords;
+    out.world_normal = vertex_input.normal;
+    out.world_position = vertex_input.pos;
+    out.clip_position = camera.view_projection * vec4<f32>(vertex_input.pos, 1.0);
+    return out;
+}
+
+// layer weights in the channels
+@group(0) @binding(0)
+var t_splat: texture_2d<f32>;
+@group(0) @binding(1)
+var s_splat: sampler;
+
+@group(0) @binding(2)
+var t_layer_0: texture_2d<f32>;
+@group(0) @binding(3)
+var t_layer_1: texture_2d<f32>;
+@group(0) @binding(4)
+var t_layer_2: texture_2d<f32>;
+@group(0) @binding(5)
+var t_layer_3: texture_2d<f32>;
+@group(0) @binding(6)
+var s_layer: sampler;
+
+struct TerrainUniform {
+    tiling: vec4<f32>,
+    // 1 for the layers that have a texture
+    layer_mask: vec4<f32>,
+}
+
+@group(0) @binding(7)
+var<uniform> terrain: TerrainUniform;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    var weights = textureSample(t_splat, s_splat, in.tex_coords) * terrain.layer_mask;
+    let total = weights.x + weights.y + weights.z + weights.w;
+    // texels weighting no layer show the first one
+    if (total <= 0.0) {
+        weights = vec4<f32>(1.0, 0.0, 0.0, 0.0);
+    } else {
+        weights = weights / total;
+    }
+
+    let color = textureSample(t_layer_0, s_layer, in.tex_coords * terrain.tiling.x).rgb * weights.x
+        + textureSample(t_layer_1, s_layer, in.tex_coords * terrain.tiling.y).rgb * weights.y
+        + textureSample(t_layer_2, s_layer, in.tex_coords * terrain.tiling.z).rgb * weights.z
+        + textureSample(t_layer_3, s_layer, in.tex_coords * terrain.tiling.w).rgb * weights.w;
+
+    let ambient = 0.05 * light.color;
+    let light_dir = normalize(light.position - in.world_position);
+    let diffuse = max(dot(normalize(in.world_normal), light_dir), 0.0) * light.color;
+    return vec4<f32>((ambient + diffuse) * color, 1.0);
+}
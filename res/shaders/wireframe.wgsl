@@ -0,0 +1,85 @@
+// Wireframe overlay, drawn on top of the shaded scene
+
+struct ModelMatrix {
+    @location(3) model_matrix_0: vec4<f32>,
+    @location(4) model_matrix_1: vec4<f32>,
+    @location(5) model_matrix_2: vec4<f32>,
+    @location(6) model_matrix_3: vec4<f32>,
+}
+
+struct CameraUniform {
+    view_projection: mat4x4<f32>,
+    inv_view_projection: mat4x4<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+const WIRE_COLOR: vec4<f32> = vec4<f32>(0.05, 0.05, 0.05, 1.0);
+
+fn model_matrix(model: ModelMatrix) -> mat4x4<f32> {
+    return mat4x4<f32>(
+        model.model_matrix_0,
+        model.model_matrix_1,
+        model.model_matrix_2,
+        model.model_matrix_3,
+    );
+}
+
+// POLYGON_MODE_LINE path: regular indexed mesh drawn with line rasterization
+
+@vertex
+fn vs_line(
+    @location(0) pos: vec3<f32>,
+    model: ModelMatrix,
+) -> @builtin(position) vec4<f32> {
+    return camera.view_projection * model_matrix(model) * vec4<f32>(pos, 1.0);
+}
+
+@fragment
+fn fs_line() -> @location(0) vec4<f32> {
+    return WIRE_COLOR;
+}
+
+// Fallback path: the mesh is expanded to a non indexed triangle list by reading
+// the index and vertex buffers as storage, so every corner gets its own barycentric
+
+// must match the ModelVertex layout: position, tex_coords, normal, tangent
+const VERTEX_STRIDE: u32 = 12u;
+
+@group(1) @binding(0)
+var<storage, read> indices: array<u32>;
+@group(1) @binding(1)
+var<storage, read> vertices: array<f32>;
+
+struct BarycentricOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) barycentric: vec3<f32>,
+}
+
+@vertex
+fn vs_barycentric(
+    @builtin(vertex_index) vertex_index: u32,
+    model: ModelMatrix,
+) -> BarycentricOutput {
+    let base = indices[vertex_index] * VERTEX_STRIDE;
+    let pos = vec3<f32>(vertices[base], vertices[base + 1u], vertices[base + 2u]);
+
+    var out: BarycentricOutput;
+    out.clip_position = camera.view_projection * model_matrix(model) * vec4<f32>(pos, 1.0);
+    let corner = vertex_index % 3u;
+    out.barycentric = vec3<f32>(f32(corner == 0u), f32(corner == 1u), f32(corner == 2u));
+    return out;
+}
+
+@fragment
+fn fs_barycentric(in: BarycentricOutput) -> @location(0) vec4<f32> {
+    // distance to the closest edge in pixels keeps lines about one pixel wide
+    let width = fwidth(in.barycentric);
+    let edge = smoothstep(vec3<f32>(0.0), width * 1.5, in.barycentric);
+    let coverage = 1.0 - min(min(edge.x, edge.y), edge.z);
+    if (coverage <= 0.0) {
+        discard;
+    }
+    return vec4<f32>(WIRE_COLOR.rgb, coverage);
+}
@@ -0,0 +1,311 @@
+//! Single file bundle of the asset tree, written by the `pack_assets` tool and read
+//! through the `Vfs` like a directory.
+//!
+//! Layout, all numbers little endian:
+//! magic `WPAK`, u32 version, u32 entry count and per entry: u32 path length + UTF-8 path,
+//! u8 compression (0 stored, 1 deflate), u64 offset from the start of the archive,
+//! u64 stored size, u64 size, u32 CRC-32 of the uncompressed data. The entry data follows.
+//!
+//! Also compiled into the build script to embed the archive, so it only depends on other crates.
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use anyhow::{bail, Context};
+
+pub const ARCHIVE_VERSION: u32 = 1;
+pub const ARCHIVE_EXTENSION: &str = "pak";
+const MAGIC: &[u8; 4] = b"WPAK";
+const STORED: u8 = 0;
+const DEFLATE: u8 = 1;
+const DEFLATE_LEVEL: u8 = 6;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    // files that don't get smaller, like most images, are stored anyway
+    Deflate,
+}
+
+/// Builds an archive in memory.
+pub struct ArchiveWriter {
+    compression: Compression,
+    entries: Vec<PackedEntry>,
+}
+
+struct PackedEntry {
+    path: String,
+    method: u8,
+    stored: Vec<u8>,
+    size: u64,
+    checksum: u32,
+}
+
+impl ArchiveWriter {
+    pub fn new(compression: Compression) -> Self {
+        Self {
+            compression,
+            entries: Vec::new(),
+        }
+    }
+
+    /// `path` is relative to the asset root with `/` separators.
+    pub fn add(&mut self, path: &str, data: &[u8]) {
+        let checksum = crc32fast::hash(data);
+        let compressed = match self.compression {
+            Compression::Deflate => Some(miniz_oxide::deflate::compress_to_vec(data, DEFLATE_LEVEL))
+                .filter(|compressed| compressed.len() < data.len()),
+            Compression::None => None,
+        };
+        let (method, stored) = match compressed {
+            Some(compressed) => (DEFLATE, compressed),
+            None => (STORED, data.to_vec()),
+        };
+        self.entries.push(PackedEntry {
+            path: path.to_string(),
+            method,
+            stored,
+            size: data.len() as u64,
+            checksum,
+        });
+    }
+
+    /// Sizes of everything added so far, uncompressed and as stored.
+    pub fn sizes(&self) -> (u64, u64) {
+        self.entries.iter().fold((0, 0), |(size, stored), entry| (size + entry.size, stored + entry.stored.len() as u64))
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        // sorted so packing the same files twice gives the same bytes
+        self.entries.sort_by(|a, b| a.path.cmp(&b.path));
+        let index_size = 12 + self.entries.iter().map(|entry| 4 + entry.path.len() + 29).sum::<usize>();
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&ARCHIVE_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+        let mut offset = index_size as u64;
+        for entry in &self.entries {
+            bytes.extend_from_slice(&(entry.path.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(entry.path.as_bytes());
+            bytes.push(entry.method);
+            bytes.extend_from_slice(&offset.to_le_bytes());
+            bytes.extend_from_slice(&(entry.stored.len() as u64).to_le_bytes());
+            bytes.extend_from_slice(&entry.size.to_le_bytes());
+            bytes.extend_from_slice(&entry.checksum.to_le_bytes());
+            offset += entry.stored.len() as u64;
+        }
+        for entry in &self.entries {
+            bytes.extend_from_slice(&entry.stored);
+        }
+        bytes
+    }
+}
+
+struct Entry {
+    method: u8,
+    offset: usize,
+    stored_size: usize,
+    size: usize,
+    checksum: u32,
+}
+
+/// An archive read into memory or embedded in the binary.
+pub struct Archive {
+    name: String,
+    data: Cow<'static, [u8]>,
+    entries: HashMap<String, Entry>,
+}
+
+impl Archive {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let data = std::fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+        Self::from_bytes(&path.display().to_string(), Cow::Owned(data))
+    }
+
+    /// Reads the index, entries are only decompressed when they are read.
+    pub fn from_bytes(name: &str, data: Cow<'static, [u8]>) -> anyhow::Result<Self> {
+        let mut reader = Reader { bytes: &data };
+        if reader.take(4)? != MAGIC {
+            bail!("{} is not an asset archive", name);
+        }
+        let version = reader.u32()?;
+        if version != ARCHIVE_VERSION {
+            bail!("{} is archive version {} but {} is supported", name, version, ARCHIVE_VERSION);
+        }
+
+        let mut entries = HashMap::new();
+        for _ in 0..reader.u32()? {
+            let len = reader.u32()? as usize;
+            let path = std::str::from_utf8(reader.take(len)?).context("archive entry path")?.to_string();
+            let entry = Entry {
+                method: reader.take(1)?[0],
+                offset: reader.u64()? as usize,
+                stored_size: reader.u64()? as usize,
+                size: reader.u64()? as usize,
+                checksum: reader.u32()?,
+            };
+            if entry.offset.checked_add(entry.stored_size).is_none_or(|end| end > data.len()) {
+                bail!("{} in {} lies past the end of the archive", path, name);
+            }
+            entries.insert(path, entry);
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            data,
+            entries,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Fails with `io::ErrorKind::NotFound` for paths that aren't in the archive
+    /// and `InvalidData` for entries that are damaged.
+    pub fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        let Some(entry) = self.entries.get(path) else {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("{} is not in {}", path, self.name)));
+        };
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+
+        let stored = &self.data[entry.offset..entry.offset + entry.stored_size];
+        let data = match entry.method {
+            STORED => stored.to_vec(),
+            DEFLATE => miniz_oxide::inflate::decompress_to_vec_with_limit(stored, entry.size)
+                .map_err(|e| invalid(format!("decompressing {}: {:?}", path, e.status)))?,
+            method => return Err(invalid(format!("{} uses unknown compression {}", path, method))),
+        };
+        if data.len() != entry.size || crc32fast::hash(&data) != entry.checksum {
+            return Err(invalid(format!("{} in {} is damaged", path, self.name)));
+        }
+        Ok(data)
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> anyhow::Result<&'a [u8]> {
+        if len > self.bytes.len() {
+            bail!("asset archive index is truncated");
+        }
+        let (head, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> anyhow::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn u64(&mut self) -> anyhow::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+}
+
+/// Packs every file under `dir`, with paths relative to it.
+pub fn pack_dir(dir: &Path, compression: Compression) -> anyhow::Result<ArchiveWriter> {
+    let mut writer = ArchiveWriter::new(compression);
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(current) = dirs.pop() {
+        let entries = std::fs::read_dir(&current).with_context(|| format!("reading {}", current.display()))?;
+        for entry in entries {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+            let relative = path.strip_prefix(dir)?
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            let data = std::fs::read(&path).with_context(|| format!("reading {}", path.display()))?;
+            writer.add(&relative, &data);
+        }
+    }
+    Ok(writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pack(compression: Compression, files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = ArchiveWriter::new(compression);
+        for (path, data) in files {
+            writer.add(path, data);
+        }
+        writer.finish()
+    }
+
+    #[test]
+    fn round_trip_stored_and_deflated() {
+        let text = "the same line over and over\n".repeat(100);
+        let noise = (0..256u32).map(|i| (i.wrapping_mul(2654435761) >> 24) as u8).collect::<Vec<_>>();
+        for compression in [Compression::None, Compression::Deflate] {
+            let bytes = pack(compression, &[("shaders/a.wgsl", text.as_bytes()), ("b.bin", &noise), ("empty", &[])]);
+            let archive = Archive::from_bytes("test", Cow::Owned(bytes)).unwrap();
+            assert_eq!(archive.len(), 3);
+            assert_eq!(archive.read("shaders/a.wgsl").unwrap(), text.as_bytes());
+            assert_eq!(archive.read("b.bin").unwrap(), noise);
+            assert!(archive.read("empty").unwrap().is_empty());
+            assert_eq!(archive.read("missing").unwrap_err().kind(), io::ErrorKind::NotFound);
+
+            let method = archive.entries["shaders/a.wgsl"].method;
+            assert_eq!(method, if compression == Compression::Deflate { DEFLATE } else { STORED });
+            // doesn't get smaller, so it's stored either way
+            assert_eq!(archive.entries["b.bin"].method, STORED);
+        }
+    }
+
+    #[test]
+    fn bad_checksum_is_invalid_data() {
+        for compression in [Compression::None, Compression::Deflate] {
+            let mut bytes = pack(compression, &[("a.txt", "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa".as_bytes())]);
+            // the checksum is the last field of the only index entry
+            let checksum_offset = 12 + 4 + "a.txt".len() + 25;
+            bytes[checksum_offset] ^= 1;
+            let archive = Archive::from_bytes("test", Cow::Owned(bytes)).unwrap();
+            assert_eq!(archive.read("a.txt").unwrap_err().kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn truncated_index_fails_to_open() {
+        let bytes = pack(Compression::None, &[("a.txt", b"abc"), ("b.txt", b"def")]);
+        let index_size = 12 + 2 * (4 + 5 + 29);
+        for len in [0, 3, 8, 12, 20, index_size - 1] {
+            assert!(Archive::from_bytes("test", Cow::Owned(bytes[..len].to_vec())).is_err(), "{} bytes", len);
+        }
+        // the index is whole but the data it points at isn't
+        assert!(Archive::from_bytes("test", Cow::Owned(bytes[..bytes.len() - 1].to_vec())).is_err());
+    }
+
+    #[test]
+    fn wrong_magic_or_version_fails_to_open() {
+        let bytes = pack(Compression::None, &[("a.txt", b"abc")]);
+        let mut magic = bytes.clone();
+        magic[0] = b'X';
+        assert!(Archive::from_bytes("test", Cow::Owned(magic)).is_err());
+        let mut version = bytes;
+        version[4..8].copy_from_slice(&(ARCHIVE_VERSION + 1).to_le_bytes());
+        assert!(Archive::from_bytes("test", Cow::Owned(version)).is_err());
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::{HashMap, HashSet};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use crate::asset_manager::{asset_path, AssetManager, Handle, ModelAssets};
+use crate::constants::{ASSET_LOADER_THREADS, MODEL_UPLOADS_PER_FRAME};
+use crate::model::{read_model_source, upload_model_source, MeshData, Model, ModelSource, SourceMeshes};
+use crate::primitives::Primitive;
+
+// key the placeholder is registered under, not a file
+const PLACEHOLDER: &str = "<placeholder>";
+const PLACEHOLDER_COLOR: [u8; 4] = [128, 128, 128, 255];
+
+/// Ticket for a model requested from an `AssetLoader`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct LoadRequest(u64);
+
+/// A request that finished, successfully or not.
+pub struct LoadedModel {
+    pub request: LoadRequest,
+    pub result: anyhow::Result<Handle<ModelAssets>>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct LoadProgress {
+    pub requested: usize,
+    pub finished: usize,
+    pub failed: usize,
+}
+
+impl LoadProgress {
+    pub fn is_done(&self) -> bool {
+        self.finished == self.requested
+    }
+}
+
+struct Job {
+    folder: String,
+    file_name: String,
+    loaded_textures: HashSet<String>,
+}
+
+struct JobResult {
+    folder: String,
+    file_name: String,
+    source: anyhow::Result<ModelSource>,
+}
+
+/// Reads and decodes models on a pool of worker threads. The GPU side is created on the
+/// render thread by `update`, a few models per frame, so loading never blocks a frame for long.
+pub struct AssetLoader {
+    jobs: Option<mpsc::Sender<Job>>,
+    results: mpsc::Receiver<JobResult>,
+    workers: Vec<thread::JoinHandle<()>>,
+    // tells the workers to skip the queued jobs, see `drop`
+    cancelled: Arc<AtomicBool>,
+    next_request: u64,
+    // requests waiting for each file a worker is reading
+    in_flight: HashMap<String, Vec<LoadRequest>>,
+    // requests for files that were already loaded or couldn't be queued, answered by the next `update`
+    ready: Vec<LoadedModel>,
+    placeholder: Handle<ModelAssets>,
+    progress: LoadProgress,
+}
+
+impl AssetLoader {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        assets: &mut AssetManager,
+    ) -> anyhow::Result<Self> {
+        let placeholder = upload_model_source("", PLACEHOLDER, placeholder_source(), device, queue, layout, assets)?;
+
+        let thread_count = match ASSET_LOADER_THREADS {
+            0 => thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        };
+        let (job_sender, job_receiver) = mpsc::channel::<Job>();
+        let (result_sender, results) = mpsc::channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let workers = (0..thread_count)
+            .map(|i| {
+                let jobs = Arc::clone(&job_receiver);
+                let results = result_sender.clone();
+                let cancelled = Arc::clone(&cancelled);
+                thread::Builder::new()
+                    .name(format!("asset loader {}", i))
+                    .spawn(move || loop {
+                        // the lock is only held while waiting, not while loading
+                        let job = jobs.lock().unwrap().recv();
+                        let Ok(job) = job else {
+                            break;
+                        };
+                        if cancelled.load(Ordering::Relaxed) {
+                            break;
+                        }
+                        // a panic fails this model only, the worker goes on with the next one
+                        let source = panic::catch_unwind(AssertUnwindSafe(|| {
+                            pollster::block_on(read_model_source(&job.folder, &job.file_name, &job.loaded_textures))
+                        }))
+                        .unwrap_or_else(|panic| Err(anyhow::anyhow!("panicked while reading: {}", panic_message(&panic))));
+                        let result = JobResult {
+                            folder: job.folder,
+                            file_name: job.file_name,
+                            source,
+                        };
+                        if results.send(result).is_err() {
+                            break;
+                        }
+                    })
+            })
+            .collect::<std::io::Result<Vec<_>>>()?;
+
+        Ok(Self {
+            jobs: Some(job_sender),
+            results,
+            workers,
+            cancelled,
+            next_request: 0,
+            in_flight: HashMap::new(),
+            ready: Vec::new(),
+            placeholder,
+            progress: LoadProgress::default(),
+        })
+    }
+
+    /// Starts loading a model in the background. Files that are already loaded or
+    /// being loaded aren't read again.
+    pub fn request_model(
+        &mut self,
+        path_to_folder_in_res: &str,
+        file_name: &str,
+        assets: &AssetManager,
+    ) -> LoadRequest {
+        let path = asset_path(path_to_folder_in_res, file_name);
+        match assets.models.get(&path) {
+            Some(model_assets) => {
+                let request = self.next_request();
+                self.ready.push(LoadedModel {
+                    request,
+                    result: Ok(model_assets),
+                });
+                request
+            }
+            None => self.read(path_to_folder_in_res, file_name, assets),
+        }
+    }
+
+    /// Reads a model again even if it was loaded before, for when its files changed.
+    /// The new meshes and materials replace the old ones in `assets` once it finished.
+    pub fn request_reload(
+        &mut self,
+        path_to_folder_in_res: &str,
+        file_name: &str,
+        assets: &AssetManager,
+    ) -> LoadRequest {
+        self.read(path_to_folder_in_res, file_name, assets)
+    }
+
+    fn next_request(&mut self) -> LoadRequest {
+        let request = LoadRequest(self.next_request);
+        self.next_request += 1;
+        self.progress.requested += 1;
+        request
+    }
+
+    fn read(&mut self, path_to_folder_in_res: &str, file_name: &str, assets: &AssetManager) -> LoadRequest {
+        let request = self.next_request();
+        let path = asset_path(path_to_folder_in_res, file_name);
+        if let Some(waiting) = self.in_flight.get_mut(&path) {
+            waiting.push(request);
+        } else {
+            self.in_flight.insert(path.clone(), vec![request]);
+            let job = Job {
+                folder: path_to_folder_in_res.to_string(),
+                file_name: file_name.to_string(),
+                loaded_textures: assets.textures.paths().map(String::from).collect(),
+            };
+            // the workers only stop once the loader is dropped, unless every one of them died
+            let sent = self.jobs.as_ref().is_some_and(|jobs| jobs.send(job).is_ok());
+            if !sent {
+                log::error!("no asset loader thread left to read {}", path);
+                let waiting = self.in_flight.remove(&path).unwrap_or_default();
+                self.ready.extend(waiting.into_iter().map(|request| LoadedModel {
+                    request,
+                    result: Err(anyhow::anyhow!("no asset loader thread left to read {}", path)),
+                }));
+            }
+        }
+        request
+    }
+
+    /// A model drawn with a grey cube until the file arrives, see `Model::set_assets`.
+    pub fn placeholder(&self, device: &wgpu::Device, path_to_folder_in_res: &str, file_name: &str) -> Model {
+        let mut model = Model::new(device, path_to_folder_in_res, file_name, &self.placeholder);
+        model.loading = true;
+        model
+    }
+
+    /// Uploads models the workers finished and returns every request that completed since the last call.
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        assets: &mut AssetManager,
+    ) -> Vec<LoadedModel> {
+        let mut loaded = std::mem::take(&mut self.ready);
+
+        for result in self.results.try_iter().take(MODEL_UPLOADS_PER_FRAME) {
+            let path = asset_path(&result.folder, &result.file_name);
+            let uploaded = result.source.and_then(|source| {
+                upload_model_source(&result.folder, &result.file_name, source, device, queue, layout, assets)
+            });
+            for request in self.in_flight.remove(&path).unwrap_or_default() {
+                loaded.push(LoadedModel {
+                    request,
+                    result: match &uploaded {
+                        Ok(model_assets) => Ok(model_assets.clone()),
+                        Err(e) => Err(anyhow::anyhow!("{:#}", e)),
+                    },
+                });
+            }
+        }
+
+        self.progress.finished += loaded.len();
+        self.progress.failed += loaded.iter().filter(|model| model.result.is_err()).count();
+        loaded
+    }
+
+    pub fn progress(&self) -> LoadProgress {
+        self.progress
+    }
+}
+
+impl Drop for AssetLoader {
+    fn drop(&mut self) {
+        // the workers stop after their current job instead of reading the rest of the queue,
+        // closing the channel wakes the idle ones
+        self.cancelled.store(true, Ordering::Relaxed);
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+// the text a panic was started with, if it has one
+fn panic_message(panic: &(dyn std::any::Any + Send)) -> &str {
+    panic.downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("no message")
+}
+
+// unit cube with a single grey texel as its texture
+fn placeholder_source() -> ModelSource {
+    let (vertices, indices) = Primitive::Cube { size: 1.0, subdivisions: 1 }.geometry();
+    let texture = "placeholder".to_string();
+    let image = image::RgbaImage::from_pixel(1, 1, image::Rgba(PLACEHOLDER_COLOR));
+    ModelSource {
+        meshes: SourceMeshes::Parsed(vec![MeshData::new(0, vertices, indices)]),
+        materials: vec![tobj::Material {
+            name: "placeholder".to_string(),
+            diffuse_texture: texture.clone(),
+            ..Default::default()
+        }],
+        images: HashMap::from([(texture, image::DynamicImage::ImageRgba8(image))]),
+        warnings: Vec::new(),
+    }
+}
@@ -0,0 +1,289 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::Arc;
+use anyhow::Context;
+use crate::model::{load_string, Material, MeshGeometry};
+use crate::load_report::LoadReport;
+use crate::texture::{load_texture, Texture, WHITE_TEXEL};
+use crate::vfs::normalize_path;
+
+// keys of the textures made up instead of loaded
+const MISSING_TEXTURE: &str = "<missing>";
+const WHITE_TEXTURE: &str = "<white>";
+
+/// Shared, reference counted access to an asset. Cloning is cheap and the asset stays
+/// alive until the last handle is dropped, whether or not its store still lists it.
+pub struct Handle<T> {
+    id: u64,
+    asset: Arc<T>,
+}
+
+impl<T> Handle<T> {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Handles pointing at this asset, including the store's own.
+    pub fn ref_count(&self) -> usize {
+        Arc::strong_count(&self.asset)
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            id: self.id,
+            asset: Arc::clone(&self.asset),
+        }
+    }
+}
+
+impl<T> Deref for Handle<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.asset
+    }
+}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Handle<{}>({})", std::any::type_name::<T>(), self.id)
+    }
+}
+
+/// Assets of one type by the path they were loaded from. The store keeps one handle
+/// per path so loading it again shares the asset instead of creating it twice.
+pub struct AssetStore<T> {
+    by_path: HashMap<String, Handle<T>>,
+    next_id: u64,
+}
+
+impl<T> AssetStore<T> {
+    fn new() -> Self {
+        Self {
+            by_path: HashMap::new(),
+            next_id: 0,
+        }
+    }
+
+    pub fn get(&self, path: &str) -> Option<Handle<T>> {
+        self.by_path.get(path).cloned()
+    }
+
+    /// Registers `asset` under `path`, replacing whatever was there.
+    pub fn insert(&mut self, path: &str, asset: T) -> Handle<T> {
+        let handle = self.add(asset);
+        self.by_path.insert(path.to_string(), handle.clone());
+        handle
+    }
+
+    /// Wraps an asset that isn't loaded from anywhere, like a material with overrides,
+    /// without registering it.
+    pub fn add(&mut self, asset: T) -> Handle<T> {
+        self.next_id += 1;
+        Handle {
+            id: self.next_id,
+            asset: Arc::new(asset),
+        }
+    }
+
+    pub fn get_or_try_insert_with(
+        &mut self,
+        path: &str,
+        create: impl FnOnce() -> anyhow::Result<T>,
+    ) -> anyhow::Result<Handle<T>> {
+        if let Some(handle) = self.get(path) {
+            return Ok(handle);
+        }
+        let asset = create()?;
+        Ok(self.insert(path, asset))
+    }
+
+    /// Forgets the asset at `path`. It is freed once the handles still in use are dropped,
+    /// and loading the path again creates a new one.
+    pub fn unload(&mut self, path: &str) -> bool {
+        self.by_path.remove(path).is_some()
+    }
+
+    /// Unloads every asset nothing but the store refers to, returns how many.
+    pub fn unload_unused(&mut self) -> usize {
+        let before = self.by_path.len();
+        self.by_path.retain(|_, handle| handle.ref_count() > 1);
+        before - self.by_path.len()
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.by_path.keys().map(String::as_str)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Handle<T>)> {
+        self.by_path.iter().map(|(path, handle)| (path.as_str(), handle))
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_path.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_path.is_empty()
+    }
+}
+
+/// Meshes and materials of one OBJ file, in the order its meshes refer to them.
+#[derive(Clone)]
+pub struct ModelAssets {
+    pub meshes: Vec<Handle<MeshGeometry>>,
+    pub materials: Vec<Handle<Material>>,
+}
+
+/// Owns everything loaded from `res/`, deduplicated by path.
+///
+/// Paths are relative to `res/`. Meshes are keyed by their OBJ file and index in it,
+/// materials by their OBJ file and name, and textures additionally by whether they are
+/// normal maps since those are uploaded in a different format.
+pub struct AssetManager {
+    pub textures: AssetStore<Texture>,
+    pub meshes: AssetStore<MeshGeometry>,
+    pub materials: AssetStore<Material>,
+    pub shaders: AssetStore<wgpu::ShaderModule>,
+    // what `upload_model_source` registers for every OBJ file it loaded
+    pub models: AssetStore<ModelAssets>,
+    // what went wrong while loading the assets above
+    pub report: LoadReport,
+}
+
+impl AssetManager {
+    pub fn new() -> Self {
+        Self {
+            textures: AssetStore::new(),
+            meshes: AssetStore::new(),
+            materials: AssetStore::new(),
+            shaders: AssetStore::new(),
+            models: AssetStore::new(),
+            report: LoadReport::new(),
+        }
+    }
+
+    pub fn load_texture(
+        &mut self,
+        path_to_folder_in_res: &str,
+        file_name: &str,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        is_normal_map: bool,
+    ) -> anyhow::Result<Handle<Texture>> {
+        let path = texture_path(path_to_folder_in_res, file_name, is_normal_map);
+        self.textures.get_or_try_insert_with(&path, || {
+            load_texture(path_to_folder_in_res, file_name, device, queue, is_normal_map)
+        })
+    }
+
+    /// Drawn in place of textures that failed to load.
+    pub fn missing_texture(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> Handle<Texture> {
+        self.textures.get_or_try_insert_with(MISSING_TEXTURE, || Texture::checkerboard(device, queue, MISSING_TEXTURE))
+            .expect("creating the missing texture")
+    }
+
+    /// For color slots of a material that have no texture.
+    pub fn white_texture(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> Handle<Texture> {
+        self.textures.get_or_try_insert_with(WHITE_TEXTURE, || Texture::solid(device, queue, WHITE_TEXEL, WHITE_TEXTURE, false))
+            .expect("creating the white texture")
+    }
+
+    pub fn load_shader(
+        &mut self,
+        path_to_folder_in_res: &str,
+        file_name: &str,
+        device: &wgpu::Device,
+    ) -> anyhow::Result<Handle<wgpu::ShaderModule>> {
+        let path = asset_path(path_to_folder_in_res, file_name);
+        self.shaders.get_or_try_insert_with(&path, || {
+            let source = load_string(path_to_folder_in_res, file_name)
+                .with_context(|| format!("reading shader {}", path))?;
+            Ok(device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some(file_name),
+                source: wgpu::ShaderSource::Wgsl(source.into()),
+            }))
+        })
+    }
+
+    /// Unloads the meshes and materials of an OBJ file, models already loaded from it keep theirs.
+    pub fn unload_model(&mut self, path_to_folder_in_res: &str, file_name: &str) -> bool {
+        self.unload_model_at(&asset_path(path_to_folder_in_res, file_name))
+    }
+
+    fn unload_model_at(&mut self, path: &str) -> bool {
+        let Some(model_assets) = self.models.get(path) else {
+            return false;
+        };
+        self.models.unload(path);
+        for i in 0..model_assets.meshes.len() {
+            self.meshes.unload(&format!("{}#{}", path, i));
+        }
+        for material in &model_assets.materials {
+            self.materials.unload(&format!("{}#{}", path, material.name));
+        }
+        true
+    }
+
+    /// Unloads every asset no model, material or renderer uses anymore. OBJ files go first
+    /// since the mesh store holds on to their meshes too, then materials since they hold
+    /// on to their textures.
+    pub fn unload_unused(&mut self) -> usize {
+        // a mesh nobody uses is only held by the mesh store and the model's entry
+        let unused_models = self.models.by_path.iter()
+            .filter(|(_, model_assets)| model_assets.meshes.iter().all(|mesh| mesh.ref_count() <= 2))
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>();
+        for path in &unused_models {
+            self.unload_model_at(path);
+        }
+        unused_models.len()
+            + self.materials.unload_unused()
+            + self.meshes.unload_unused()
+            + self.textures.unload_unused()
+            + self.shaders.unload_unused()
+    }
+
+    pub fn log_stats(&self) {
+        log::info!(
+            "assets: {} models, {} textures, {} meshes, {} materials, {} shaders",
+            self.models.len(),
+            self.textures.len(),
+            self.meshes.len(),
+            self.materials.len(),
+            self.shaders.len(),
+        );
+    }
+}
+
+impl Default for AssetManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Key of a file under `res/`, the same however the folder was spelled.
+pub fn asset_path(path_to_folder_in_res: &str, file_name: &str) -> String {
+    normalize_path(&format!("{}/{}", path_to_folder_in_res, file_name))
+}
+
+/// Key of a texture, normal maps are uploaded in a different format than color textures.
+pub fn texture_path(path_to_folder_in_res: &str, file_name: &str, is_normal_map: bool) -> String {
+    let path = asset_path(path_to_folder_in_res, file_name);
+    if is_normal_map {
+        path + "#normal"
+    } else {
+        path
+    }
+}
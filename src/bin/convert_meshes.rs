@@ -0,0 +1,43 @@
+//! Writes a binary mesh cache next to every OBJ file under `res/`, which `read_model_source`
+//! then reads instead of parsing the OBJ. Run again after changing a model; stale
+//! caches are ignored at load time.
+use std::path::{Path, PathBuf};
+use lib::mesh_cache::cache_file_name;
+use lib::model::load_obj;
+use lib::vfs::{set_vfs, DirSource, Vfs};
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+    let res = Path::new(env!("CARGO_MANIFEST_DIR")).join("res");
+    // read the OBJ files from where the caches are written
+    set_vfs(Vfs::new().mount(DirSource::new(&res)))?;
+
+    for path in obj_files(&res)? {
+        let folder = path.parent()
+            .and_then(|folder| folder.strip_prefix(&res).ok())
+            .map(|folder| folder.to_string_lossy().replace('\\', "/"))
+            .unwrap_or_default();
+        let file_name = path.file_name().unwrap().to_string_lossy();
+
+        let obj = pollster::block_on(load_obj(&folder, &file_name))?;
+        let bytes = obj.cache().encode();
+        let cache_path = path.with_file_name(cache_file_name(&file_name));
+        std::fs::write(&cache_path, &bytes)?;
+        println!("{} -> {} ({} bytes)", path.display(), cache_path.display(), bytes.len());
+    }
+    Ok(())
+}
+
+fn obj_files(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files.extend(obj_files(&path)?);
+        } else if path.extension().is_some_and(|extension| extension == "obj") {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
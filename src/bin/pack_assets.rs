@@ -0,0 +1,47 @@
+//! Bundles `res/` into a single archive the program reads instead of the directory,
+//! see `--assets` and `vfs::asset_root`.
+//!
+//! `pack_assets [--no-compression] [--out <archive>] [dir]`, packing the source `res/`
+//! into `res.pak` by default.
+use std::path::{Path, PathBuf};
+use lib::archive::{pack_dir, Archive, Compression, ARCHIVE_EXTENSION};
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+
+    let compression = match args.iter().any(|arg| arg == "--no-compression") {
+        true => Compression::None,
+        false => Compression::Deflate,
+    };
+    let out = args.iter()
+        .position(|arg| arg == "--out")
+        .and_then(|i| args.get(i + 1))
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new("res").with_extension(ARCHIVE_EXTENSION));
+    let dir = args.iter()
+        .enumerate()
+        .filter(|(i, arg)| !arg.starts_with("--") && (*i == 0 || args[i - 1] != "--out"))
+        .map(|(_, arg)| PathBuf::from(arg))
+        .next()
+        .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("res"));
+
+    let writer = pack_dir(&dir, compression)?;
+    let (size, stored) = writer.sizes();
+    let bytes = writer.finish();
+    // read back so a broken archive is noticed here and not when shipping it
+    let archive = Archive::from_bytes(&out.display().to_string(), bytes.clone().into())?;
+    for path in archive.paths() {
+        archive.read(path)?;
+    }
+    std::fs::write(&out, &bytes)?;
+    println!(
+        "{} -> {}: {} files, {} bytes packed into {}",
+        dir.display(),
+        out.display(),
+        archive.len(),
+        size,
+        stored,
+    );
+    Ok(())
+}
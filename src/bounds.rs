@@ -0,0 +1,76 @@
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, MetricSpace, Point3, Transform, Vector3};
+
+/// Axis aligned bounding box.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    /// Degenerate box at the origin for an empty point set.
+    pub fn from_points(points: impl IntoIterator<Item = Point3<f32>>) -> Self {
+        let mut points = points.into_iter();
+        let Some(first) = points.next() else {
+            return Self { min: Point3::origin(), max: Point3::origin() };
+        };
+        points.fold(Self { min: first, max: first }, |aabb, p| Self {
+            min: Point3::new(aabb.min.x.min(p.x), aabb.min.y.min(p.y), aabb.min.z.min(p.z)),
+            max: Point3::new(aabb.max.x.max(p.x), aabb.max.y.max(p.y), aabb.max.z.max(p.z)),
+        })
+    }
+
+    pub fn center(&self) -> Point3<f32> {
+        self.min.midpoint(self.max)
+    }
+
+    pub fn half_extents(&self) -> Vector3<f32> {
+        (self.max - self.min) * 0.5
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb::from_points([self.min, self.max, other.min, other.max])
+    }
+
+    /// Smallest axis aligned box around this box after `matrix` is applied (Arvo's method).
+    pub fn transform(&self, matrix: &Matrix4<f32>) -> Aabb {
+        let center = matrix.transform_point(self.center());
+        let half = self.half_extents();
+        let abs = |v: Vector3<f32>| Vector3::new(v.x.abs(), v.y.abs(), v.z.abs());
+        let extents = abs(matrix.x.truncate()) * half.x
+            + abs(matrix.y.truncate()) * half.y
+            + abs(matrix.z.truncate()) * half.z;
+        Aabb {
+            min: center - extents,
+            max: center + extents,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Point3<f32>,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    /// Centered on the points' bounding box, which is tight enough for culling.
+    pub fn from_points(points: &[Point3<f32>]) -> Self {
+        let center = Aabb::from_points(points.iter().copied()).center();
+        let radius = points.iter()
+            .map(|p| p.distance(center))
+            .fold(0.0, f32::max);
+        Self { center, radius }
+    }
+
+    /// Non-uniform scale grows the sphere by the largest axis scale.
+    pub fn transform(&self, matrix: &Matrix4<f32>) -> BoundingSphere {
+        let scale = matrix.x.truncate().magnitude()
+            .max(matrix.y.truncate().magnitude())
+            .max(matrix.z.truncate().magnitude());
+        BoundingSphere {
+            center: matrix.transform_point(self.center),
+            radius: self.radius * scale,
+        }
+    }
+}
@@ -2,7 +2,6 @@ use cgmath::{InnerSpace, SquareMatrix, Vector3};
 use wgpu::Device;
 use wgpu::util::DeviceExt;
 use winit::event::{ElementState, KeyboardInput, VirtualKeyCode, WindowEvent};
-use winit::window::Window;
 use crate::constants::{CAM_ROT_SPEED, CAM_SPEED, FAR_CLIP, FOV, HEIGHT, NEAR_CLIP, OPENGL_TO_WGPU_MATRIX, WIDTH};
 
 pub struct Camera {
@@ -10,6 +9,10 @@ pub struct Camera {
     target: cgmath::Point3<f32>,
     up: cgmath::Vector3<f32>,
     pub aspect: f32,
+    // vertical field of view in degrees
+    pub fovy: f32,
+    pub znear: f32,
+    pub zfar: f32,
 
     pub uniform: CameraUniform,
     pub buffer: wgpu::Buffer,
@@ -36,12 +39,13 @@ impl Camera {
         let matrix = OPENGL_TO_WGPU_MATRIX * projection * view;
 
         let camera_uniform = CameraUniform::new(
-            matrix
+            matrix,
+            position,
         );
 
         let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Camera Uniform Buffer"),
-            contents: bytemuck::cast_slice(&[camera_uniform.view_proj]),
+            contents: bytemuck::cast_slice(&[camera_uniform]),
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
@@ -62,31 +66,63 @@ impl Camera {
             target,
             up,
             aspect: WIDTH as f32 / HEIGHT as f32,
+            fovy: FOV,
+            znear: NEAR_CLIP,
+            zfar: FAR_CLIP,
             uniform: camera_uniform,
             buffer,
             bind_group,
         }
     }
 
-    fn build_view_projection_matrix(&self) -> cgmath::Matrix4<f32> {
-        let view = cgmath::Matrix4::look_at_rh(
+    pub fn view_matrix(&self) -> cgmath::Matrix4<f32> {
+        cgmath::Matrix4::look_at_rh(
             self.position,
             self.target,
-            self.up);
+            self.up)
+    }
+
+    pub fn position(&self) -> cgmath::Point3<f32> {
+        self.position
+    }
+
+    pub fn target(&self) -> cgmath::Point3<f32> {
+        self.target
+    }
+
+    /// Places the camera at `position` looking at `target`.
+    /// The uniform is only refreshed by the next `update_view_proj`.
+    pub fn set_view(
+        &mut self,
+        position: cgmath::Point3<f32>,
+        target: cgmath::Point3<f32>,
+        up: cgmath::Vector3<f32>,
+    ) {
+        self.position = position;
+        self.target = target;
+        self.up = up;
+    }
+
+    // projection already converted to wgpu's 0..1 depth range
+    pub fn projection_matrix(&self) -> cgmath::Matrix4<f32> {
         let projection = cgmath::perspective(
-            cgmath::Deg(FOV),
+            cgmath::Deg(self.fovy),
             self.aspect,
-            NEAR_CLIP,
-            FAR_CLIP);
+            self.znear,
+            self.zfar);
 
-        OPENGL_TO_WGPU_MATRIX * projection * view
+        OPENGL_TO_WGPU_MATRIX * projection
+    }
+
+    pub fn build_view_projection_matrix(&self) -> cgmath::Matrix4<f32> {
+        self.projection_matrix() * self.view_matrix()
     }
 
     pub fn update_view_proj(&mut self, device: &Device) {
-        self.uniform.view_proj = self.build_view_projection_matrix().into();
+        self.uniform = CameraUniform::new(self.build_view_projection_matrix(), self.position);
         self.buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Camera Uniform Buffer"),
-            contents: bytemuck::cast_slice(&[self.uniform.view_proj]),
+            contents: bytemuck::cast_slice(&[self.uniform]),
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
         self.bind_group = device.create_bind_group(
@@ -109,7 +145,7 @@ pub fn create_camera_bind_group_layout(device: &Device) -> wgpu::BindGroupLayout
         entries: &[
             wgpu::BindGroupLayoutEntry {
                 binding: 0,
-                visibility: wgpu::ShaderStages::VERTEX,
+                visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
                 ty: wgpu::BindingType::Buffer {
                     ty: wgpu::BufferBindingType::Uniform,
                     has_dynamic_offset: false,
@@ -124,32 +160,47 @@ pub fn create_camera_bind_group_layout(device: &Device) -> wgpu::BindGroupLayout
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct CameraUniform {
-    pub view_proj: [[f32; 4]; 4]
+    pub view_proj: [[f32; 4]; 4],
+    // used by screen space passes to reconstruct world positions from depth
+    pub inv_view_proj: [[f32; 4]; 4],
+    // world space eye position, w is unused
+    pub view_position: [f32; 4],
 }
 
 impl CameraUniform {
-    pub fn new(view_proj_matrix: cgmath::Matrix4<f32>) -> Self {
+    pub fn new(view_proj_matrix: cgmath::Matrix4<f32>, position: cgmath::Point3<f32>) -> Self {
         Self {
             view_proj: view_proj_matrix.into(),
+            inv_view_proj: view_proj_matrix.invert().unwrap().into(),
+            view_position: [position.x, position.y, position.z, 1.0],
         }
     }
 
     pub fn update_view_proj(&mut self, camera: &Camera) {
-        self.view_proj = camera.build_view_projection_matrix().into();
+        *self = Self::new(camera.build_view_projection_matrix(), camera.position);
     }
 }
 
 pub struct CameraController {
     speed: f32,
+    #[allow(dead_code)]
     rot_speed: f32,
     left_press: bool,
     right_press: bool,
     up_press: bool,
     down_press: bool,
+    #[allow(dead_code)]
     rotate_left: bool,
+    #[allow(dead_code)]
     rotate_right: bool,
 }
 
+impl Default for CameraController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl CameraController {
     pub fn new() -> Self {
         Self {
@@ -0,0 +1,58 @@
+use std::path::PathBuf;
+use crate::gpu_culling::GpuCulling;
+use crate::render_path::RenderPath;
+use crate::transparency::TransparencyMode;
+
+/// Startup options read from the command line.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// `--render-path <forward|deferred|clustered>`
+    pub render_path: RenderPath,
+    /// `--transparency <sorted|oit>`
+    pub transparency: TransparencyMode,
+    /// `--scene <path to .ron>`, the built in default scene otherwise
+    pub scene: Option<PathBuf>,
+    /// `--gpu-culling <off|frustum|hiz>`, forward path only
+    pub gpu_culling: GpuCulling,
+    /// `--assets <dir or .pak archive>`, where `res/` is, see `vfs::asset_root` for the default
+    pub assets: Option<PathBuf>,
+    /// `--hot-reload`, reloads assets when they change, reading them from the source `res`
+    /// directory unless `--assets` is given
+    pub hot_reload: bool,
+    /// `--stress-lights <count>`, synthetic point lights the clustered path shades on top
+    /// of the scene's lights
+    pub stress_lights: usize,
+}
+
+impl Options {
+    pub fn from_args(args: impl Iterator<Item = String>) -> Self {
+        let args = args.collect::<Vec<_>>();
+        Self {
+            render_path: parse_flag(&args, "--render-path", RenderPath::from_name),
+            transparency: parse_flag(&args, "--transparency", TransparencyMode::from_name),
+            scene: flag_value(&args, "--scene").map(PathBuf::from),
+            gpu_culling: parse_flag(&args, "--gpu-culling", GpuCulling::from_name),
+            assets: flag_value(&args, "--assets").map(PathBuf::from),
+            hot_reload: args.iter().any(|arg| arg == "--hot-reload"),
+            stress_lights: parse_flag(&args, "--stress-lights", |value| value.parse().ok()),
+        }
+    }
+}
+
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|arg| arg == flag)
+        .and_then(|i| args.get(i + 1))
+        .map(String::as_str)
+}
+
+// unknown values are reported and replaced by the default instead of aborting startup
+fn parse_flag<T: Default>(args: &[String], flag: &str, parse: impl Fn(&str) -> Option<T>) -> T {
+    match flag_value(args, flag) {
+        Some(value) => parse(value).unwrap_or_else(|| {
+            log::warn!("unknown value {:?} for {}, using the default", value, flag);
+            T::default()
+        }),
+        None => T::default(),
+    }
+}
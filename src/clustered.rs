@@ -0,0 +1,385 @@
+use cgmath::SquareMatrix;
+use wgpu::util::DeviceExt;
+use wgpu::{FragmentState, VertexState};
+use crate::camera::{Camera, create_camera_bind_group_layout};
+use crate::constants::{CLUSTERED_LIGHT_INTENSITY, CLUSTERED_LIGHT_RADIUS};
+use crate::light::create_light_bind_group_layout;
+use crate::model::{create_material_bind_group_layout, ModelVertex, Vertex};
+use crate::model_matrix::RawModelMatrix;
+use crate::node::NodeId;
+use crate::scene::Scene;
+use crate::texture::Texture;
+use crate::shader::{create_shader_module, try_create};
+
+pub const CLUSTER_X: u32 = 16;
+pub const CLUSTER_Y: u32 = 9;
+pub const CLUSTER_Z: u32 = 24;
+pub const CLUSTER_COUNT: u32 = CLUSTER_X * CLUSTER_Y * CLUSTER_Z;
+// must match the constant of the same name in cluster_lights.wgsl and clustered.wgsl
+pub const MAX_LIGHTS_PER_CLUSTER: u32 = 128;
+pub const MAX_POINT_LIGHTS: usize = 1024;
+
+const WORKGROUP_SIZE: u32 = 4;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct PointLight {
+    pub position: [f32; 3],
+    // lights have no influence past this distance, used for cluster assignment
+    pub radius: f32,
+    pub color: [f32; 3],
+    pub intensity: f32,
+}
+
+impl PointLight {
+    pub fn new(position: cgmath::Vector3<f32>, color: cgmath::Vector3<f32>, radius: f32, intensity: f32) -> Self {
+        Self {
+            position: position.into(),
+            radius,
+            color: color.into(),
+            intensity,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ClusterUniform {
+    pub view: [[f32; 4]; 4],
+    pub inv_projection: [[f32; 4]; 4],
+    pub screen_size: [f32; 2],
+    pub near: f32,
+    pub far: f32,
+    // xyz: cluster grid dimensions, w: number of point lights
+    pub grid: [u32; 4],
+}
+
+impl ClusterUniform {
+    pub fn new(camera: &Camera, config: &wgpu::SurfaceConfiguration, light_count: u32) -> Self {
+        Self {
+            view: camera.view_matrix().into(),
+            inv_projection: camera.projection_matrix().invert().unwrap().into(),
+            screen_size: [config.width as f32, config.height as f32],
+            near: camera.znear,
+            far: camera.zfar,
+            grid: [CLUSTER_X, CLUSTER_Y, CLUSTER_Z, light_count],
+        }
+    }
+}
+
+/// Point lights shaded through the cluster grid, the scene's lights and optionally synthetic
+/// ones. The compute pass rebuilds the per cluster light lists every frame from the current camera.
+pub struct ClusteredLighting {
+    // the scene's lights followed by `stress_lights`
+    pub lights: Vec<PointLight>,
+    // added to stress the path, see `scatter_point_lights`
+    pub stress_lights: Vec<PointLight>,
+    pub uniform: ClusterUniform,
+    pub uniform_buffer: wgpu::Buffer,
+    pub light_buffer: wgpu::Buffer,
+    // only the bind groups use it
+    #[allow(dead_code)]
+    pub cluster_buffer: wgpu::Buffer,
+    pub compute_bind_group: wgpu::BindGroup,
+    pub render_bind_group: wgpu::BindGroup,
+    pub compute_pipeline: wgpu::ComputePipeline,
+    pub render_pipeline: wgpu::RenderPipeline,
+}
+
+impl ClusteredLighting {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        camera: &Camera,
+        stress_lights: Vec<PointLight>,
+    ) -> anyhow::Result<Self> {
+        let lights = capped_lights(stress_lights.clone());
+        let uniform = ClusterUniform::new(camera, config, lights.len() as u32);
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Cluster Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        // sized for MAX_POINT_LIGHTS, later writes only happen when the lights change
+        let mut initial_lights = lights.clone();
+        initial_lights.resize(MAX_POINT_LIGHTS, bytemuck::Zeroable::zeroed());
+        let light_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Point Light Buffer"),
+            contents: bytemuck::cast_slice(&initial_lights),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+
+        // every cluster stores its light count followed by up to MAX_LIGHTS_PER_CLUSTER indices
+        let cluster_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cluster Light Index Buffer"),
+            size: (CLUSTER_COUNT * (MAX_LIGHTS_PER_CLUSTER + 1) * 4) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let compute_layout = create_cluster_bind_group_layout(device, false);
+        let render_layout = create_cluster_bind_group_layout(device, true);
+
+        let entries = [
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: light_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: cluster_buffer.as_entire_binding(),
+            },
+        ];
+        let compute_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Cluster Compute Bind Group"),
+            layout: &compute_layout,
+            entries: &entries,
+        });
+        let render_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Cluster Render Bind Group"),
+            layout: &render_layout,
+            entries: &entries,
+        });
+
+        Ok(Self {
+            lights,
+            stress_lights,
+            uniform,
+            uniform_buffer,
+            light_buffer,
+            cluster_buffer,
+            compute_bind_group,
+            render_bind_group,
+            compute_pipeline: create_cluster_compute_pipeline(device, &compute_layout)?,
+            render_pipeline: create_clustered_render_pipeline(device, config, &render_layout)?,
+        })
+    }
+
+    /// Replaces the scene's lights, past `MAX_POINT_LIGHTS` lights are left out.
+    /// The light buffer is only written when the lights changed.
+    pub fn set_scene_lights(&mut self, queue: &wgpu::Queue, scene_lights: Vec<PointLight>) {
+        let mut lights = scene_lights;
+        lights.extend_from_slice(&self.stress_lights);
+        let lights = capped_lights(lights);
+        if lights != self.lights {
+            queue.write_buffer(&self.light_buffer, 0, bytemuck::cast_slice(&lights));
+            self.lights = lights;
+        }
+    }
+
+    pub fn update(
+        &mut self,
+        queue: &wgpu::Queue,
+        config: &wgpu::SurfaceConfiguration,
+        camera: &Camera,
+    ) {
+        self.uniform = ClusterUniform::new(camera, config, self.lights.len() as u32);
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+    }
+
+    pub fn assign_lights(&self, encoder: &mut wgpu::CommandEncoder) {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("cluster light assignment pass"),
+        });
+        compute_pass.set_pipeline(&self.compute_pipeline);
+        compute_pass.set_bind_group(0, &self.compute_bind_group, &[]);
+        compute_pass.dispatch_workgroups(
+            CLUSTER_X.div_ceil(WORKGROUP_SIZE),
+            CLUSTER_Y.div_ceil(WORKGROUP_SIZE),
+            CLUSTER_Z.div_ceil(WORKGROUP_SIZE),
+        );
+    }
+
+    // shader files the pipelines are built from, see `reload_pipelines`
+    pub const SHADERS: &'static [&'static str] = &["cluster_lights.wgsl", "clustered.wgsl"];
+
+    /// Rebuilds the pipelines from the current shader files, the old ones stay if that fails.
+    /// The bind groups stay valid since layouts with the same entries are compatible.
+    pub fn reload_pipelines(
+        &mut self,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+    ) {
+        let pipelines = try_create(device, "clustered pipelines", || {
+            Ok((
+                create_cluster_compute_pipeline(device, &create_cluster_bind_group_layout(device, false))?,
+                create_clustered_render_pipeline(device, config, &create_cluster_bind_group_layout(device, true))?,
+            ))
+        });
+        if let Some((compute_pipeline, render_pipeline)) = pipelines {
+            self.compute_pipeline = compute_pipeline;
+            self.render_pipeline = render_pipeline;
+        }
+    }
+}
+
+// past MAX_POINT_LIGHTS the buffer is full, the rest is left out
+fn capped_lights(mut lights: Vec<PointLight>) -> Vec<PointLight> {
+    if lights.len() > MAX_POINT_LIGHTS {
+        log::warn!("the clustered path shades {} of {} point lights", MAX_POINT_LIGHTS, lights.len());
+        lights.truncate(MAX_POINT_LIGHTS);
+    }
+    lights
+}
+
+/// The lights of `scene` except `main_light`, which the clustered shader lights like the
+/// forward one does.
+pub fn scene_point_lights(scene: &Scene, main_light: NodeId) -> Vec<PointLight> {
+    scene.lights()
+        .into_iter()
+        .filter(|(id, _)| *id != main_light)
+        .map(|(_, light)| PointLight::new(
+            light.uniform.position.into(),
+            light.uniform.color.into(),
+            CLUSTERED_LIGHT_RADIUS,
+            CLUSTERED_LIGHT_INTENSITY,
+        ))
+        .collect()
+}
+
+/// Scatters `count` lights on a grid around the origin, used to stress the clustered path.
+pub fn scatter_point_lights(count: usize) -> Vec<PointLight> {
+    let side = (count as f32).sqrt().ceil() as usize;
+    let spacing = 1.5;
+    let half = (side as f32 - 1.0) * spacing * 0.5;
+    (0..count)
+        .map(|i| {
+            let x = (i % side) as f32 * spacing - half;
+            let z = (i / side) as f32 * spacing - half;
+            // cheap hue spread so neighbouring lights are distinguishable
+            let hue = i as f32 * 0.618_034;
+            let color = cgmath::Vector3::new(
+                0.5 + 0.5 * (hue * std::f32::consts::TAU).cos(),
+                0.5 + 0.5 * ((hue + 1.0 / 3.0) * std::f32::consts::TAU).cos(),
+                0.5 + 0.5 * ((hue + 2.0 / 3.0) * std::f32::consts::TAU).cos(),
+            );
+            PointLight::new(cgmath::Vector3::new(x, -1.0, z), color, 2.5, 2.0)
+        })
+        .collect()
+}
+
+pub fn create_cluster_bind_group_layout(device: &wgpu::Device, read_only: bool) -> wgpu::BindGroupLayout {
+    let visibility = if read_only {
+        wgpu::ShaderStages::FRAGMENT
+    } else {
+        wgpu::ShaderStages::COMPUTE
+    };
+    let storage = |binding, read_only| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    };
+
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Cluster Bind Group Layout"),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            storage(1, true),
+            storage(2, read_only),
+        ],
+    })
+}
+
+fn create_cluster_compute_pipeline(
+    device: &wgpu::Device,
+    cluster_layout: &wgpu::BindGroupLayout,
+) -> anyhow::Result<wgpu::ComputePipeline> {
+    let layout = device.create_pipeline_layout(
+        &wgpu::PipelineLayoutDescriptor {
+            label: Some("Cluster Compute Pipeline Layout"),
+            bind_group_layouts: &[cluster_layout],
+            push_constant_ranges: &[],
+        }
+    );
+
+    let shader = create_shader_module(device, "cluster_lights.wgsl")?;
+
+    Ok(device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("Cluster Compute Pipeline"),
+        layout: Some(&layout),
+        module: &shader,
+        entry_point: "cs_main",
+    }))
+}
+
+fn create_clustered_render_pipeline(
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+    cluster_layout: &wgpu::BindGroupLayout,
+) -> anyhow::Result<wgpu::RenderPipeline> {
+    // SimplePipeline's layouts plus the cluster data in group 3
+    let layouts = &[
+        &create_material_bind_group_layout(device),
+        &create_camera_bind_group_layout(device),
+        &create_light_bind_group_layout(device),
+        cluster_layout,
+    ];
+
+    let layout = device.create_pipeline_layout(
+        &wgpu::PipelineLayoutDescriptor {
+            label: Some("Clustered Pipeline Layout"),
+            bind_group_layouts: layouts,
+            push_constant_ranges: &[],
+        }
+    );
+
+    let shader = create_shader_module(device, "clustered.wgsl")?;
+
+    Ok(device.create_render_pipeline(
+        &wgpu::RenderPipelineDescriptor {
+            label: Some("Clustered Pipeline"),
+            layout: Some(&layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[ModelVertex::desc(), RawModelMatrix::desc()],
+            },
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: Default::default(),
+            multiview: None,
+        }
+    ))
+}
@@ -1,3 +1,5 @@
+use crate::obj_import::UvProjection;
+
 #[rustfmt::skip]
 pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
     1.0, 0.0, 0.0, 0.0,
@@ -9,10 +11,52 @@ pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
 pub const WIDTH: u32 = 1280;
 pub const HEIGHT: u32 = 720;
 
+pub const CLEAR_COLOR: wgpu::Color = wgpu::Color {
+    r: 0.1,
+    g: 0.5,
+    b: 0.9,
+    a: 1.0,
+};
+
 pub const FOV: f32 = 90.0;
 
 pub const NEAR_CLIP: f32 = 0.1;
 pub const FAR_CLIP: f32 = 100.0;
 
+// reach and strength of the scene's lights in the clustered render path, lights have no range of their own
+pub const CLUSTERED_LIGHT_RADIUS: f32 = 10.0;
+pub const CLUSTERED_LIGHT_INTENSITY: f32 = 1.0;
+
+// reorder mesh triangles and vertices on load for the GPU caches, logging the gain.
+// recorded in the mesh caches, a cache written with the other setting is not used
+pub const OPTIMIZE_MESHES: bool = true;
+
+// OBJ files without normals get them smoothed across edges flatter than this, in degrees
+pub const NORMAL_SMOOTHING_ANGLE: f32 = 60.0;
+// and OBJ files without texture coordinates get them projected
+pub const UV_FALLBACK: UvProjection = UvProjection::Box;
+
+// models read and decoded in the background at once, 0 uses one thread per core but one
+pub const ASSET_LOADER_THREADS: usize = 0;
+// finished models uploaded to the GPU per frame, bounds the hitch when many finish together
+pub const MODEL_UPLOADS_PER_FRAME: usize = 2;
+
+// directory assets are read from when --assets isn't given, see vfs::asset_root
+pub const ASSET_ROOT_ENV: &str = "WGPU_TEST_ASSETS";
+
+// how often --hot-reload looks for changed files under res/
+pub const HOT_RELOAD_POLL_MS: u64 = 250;
+
+// terrains without a resolution or chunk size in their scene file, in quads along each side
+pub const TERRAIN_RESOLUTION: u32 = 256;
+pub const TERRAIN_CHUNK_SIZE: u32 = 32;
+// coarser levels per terrain chunk, each halves the samples along both sides
+pub const TERRAIN_LOD_LEVELS: usize = 3;
+// screen size below which a chunk switches to its first coarser level, each further level halves it
+pub const TERRAIN_LOD_SCREEN_SIZE: f32 = 0.5;
+
+// written by F5 into the working directory
+pub const SCENE_SAVE_FILE: &str = "saved_scene.ron";
+
 pub const CAM_SPEED: f32 = 0.05;
 pub const CAM_ROT_SPEED: f32 = 0.1;
\ No newline at end of file
@@ -0,0 +1,131 @@
+use cgmath::{InnerSpace, Matrix4, Point3, Vector3, Vector4};
+use crate::bounds::{Aabb, BoundingSphere};
+use crate::model::{DrawModel, Mesh, Model};
+
+/// The six planes of a view frustum, normals pointing inwards.
+#[derive(Debug, Copy, Clone)]
+pub struct Frustum {
+    // xyz: normal, w: distance, so a point is inside when dot(normal, p) + w >= 0
+    pub planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes from a wgpu view projection matrix (0..1 clip depth).
+    pub fn from_view_projection(m: Matrix4<f32>) -> Self {
+        let row = |i: usize| Vector4::new(m.x[i], m.y[i], m.z[i], m.w[i]);
+        let (r0, r1, r2, r3) = (row(0), row(1), row(2), row(3));
+        let normalize = |p: Vector4<f32>| p / p.truncate().magnitude();
+        Self {
+            planes: [
+                normalize(r3 + r0), // left
+                normalize(r3 - r0), // right
+                normalize(r3 + r1), // bottom
+                normalize(r3 - r1), // top
+                normalize(r2),      // near
+                normalize(r3 - r2), // far
+            ],
+        }
+    }
+
+    fn distance(plane: &Vector4<f32>, point: Point3<f32>) -> f32 {
+        plane.truncate().dot(Vector3::new(point.x, point.y, point.z)) + plane.w
+    }
+
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.planes.iter().all(|plane| Self::distance(plane, sphere.center) >= -sphere.radius)
+    }
+
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // corner furthest along the plane normal
+            let corner = Point3::new(
+                if plane.x >= 0.0 { aabb.max.x } else { aabb.min.x },
+                if plane.y >= 0.0 { aabb.max.y } else { aabb.min.y },
+                if plane.z >= 0.0 { aabb.max.z } else { aabb.min.z },
+            );
+            Self::distance(plane, corner) >= 0.0
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct CullStats {
+    pub tested: u32,
+    pub culled: u32,
+}
+
+/// A model and the meshes of it that survived culling.
+pub struct VisibleModel<'a> {
+    pub model: &'a Model,
+    pub meshes: Vec<&'a Mesh>,
+}
+
+/// Tests every mesh against `frustum` in world space, sphere first and box second.
+/// Instanced models are kept whole, their copies can be spread anywhere.
+pub fn cull_models<'a>(
+    models: impl IntoIterator<Item = &'a Model>,
+    frustum: &Frustum,
+    stats: &mut CullStats,
+) -> Vec<VisibleModel<'a>> {
+    *stats = CullStats::default();
+    models.into_iter()
+        .map(|model| {
+            if model.instances.is_some() {
+                return VisibleModel { model, meshes: model.meshes.iter().collect() };
+            }
+            let world = model.model_matrix.matrix();
+            let meshes = model.meshes.iter()
+                .filter(|mesh| {
+                    stats.tested += 1;
+                    let visible = frustum.intersects_sphere(&mesh.geometry.bounding_sphere.transform(&world))
+                        && frustum.intersects_aabb(&mesh.geometry.bounds.transform(&world));
+                    if !visible {
+                        stats.culled += 1;
+                    }
+                    visible
+                })
+                .collect();
+            VisibleModel { model, meshes }
+        })
+        .filter(|visible| !visible.meshes.is_empty())
+        .collect()
+}
+
+pub trait DrawVisible<'a> {
+    fn draw_visible(
+        &mut self,
+        visible: &[VisibleModel<'a>],
+        camera_bind_group: &'a wgpu::BindGroup,
+        light_bind_group: &'a wgpu::BindGroup,
+    );
+}
+
+impl<'a, 'b> DrawVisible<'b> for wgpu::RenderPass<'a>
+    where
+        'b: 'a,
+{
+    fn draw_visible(
+        &mut self,
+        visible: &[VisibleModel<'b>],
+        camera_bind_group: &'b wgpu::BindGroup,
+        light_bind_group: &'b wgpu::BindGroup,
+    ) {
+        for entry in visible {
+            self.set_vertex_buffer(1, entry.model.instance_buffer().slice(..));
+            for mesh in &entry.meshes {
+                let material = &entry.model.materials[mesh.geometry.material];
+                // blended meshes are drawn back to front by the transparent pass
+                if material.is_transparent() {
+                    continue;
+                }
+                self.draw_mesh_instanced(
+                    mesh,
+                    material,
+                    entry.model.instance_range(),
+                    camera_bind_group,
+                    light_bind_group,
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,315 @@
+use cgmath::{Matrix4, Point3, SquareMatrix, Transform, Vector3};
+use wgpu::{FragmentState, VertexState};
+use winit::event::{ElementState, KeyboardInput, VirtualKeyCode, WindowEvent};
+use crate::camera::create_camera_bind_group_layout;
+use crate::model::Vertex;
+use crate::texture::Texture;
+use crate::shader::{create_shader_module, try_create};
+
+pub const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+pub const GREEN: [f32; 4] = [0.0, 1.0, 0.0, 1.0];
+pub const BLUE: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+pub const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+pub const YELLOW: [f32; 4] = [1.0, 1.0, 0.0, 1.0];
+pub const GREY: [f32; 4] = [0.5, 0.5, 0.5, 1.0];
+
+const SPHERE_SEGMENTS: usize = 24;
+const INITIAL_CAPACITY: usize = 1024;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LineVertex {
+    pub position: [f32; 3],
+    pub color: [f32; 4],
+}
+
+impl Vertex for LineVertex {
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        const ATTRIBS: [wgpu::VertexAttribute; 2] =
+            wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x4];
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<LineVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &ATTRIBS,
+        }
+    }
+}
+
+/// Immediate mode line renderer. Shapes are queued every frame from anywhere that has
+/// access to it, uploaded once by `prepare` and thrown away after `draw`.
+///
+/// Lines queued while depth testing is off (see `set_depth_test`) are drawn on top of everything.
+pub struct DebugDraw {
+    pub enabled: bool,
+    depth_test: bool,
+    depth_tested: Vec<LineVertex>,
+    overlay: Vec<LineVertex>,
+
+    buffer: wgpu::Buffer,
+    capacity: usize,
+    // vertex counts uploaded by the last prepare
+    depth_tested_count: u32,
+    overlay_count: u32,
+
+    depth_tested_pipeline: wgpu::RenderPipeline,
+    overlay_pipeline: wgpu::RenderPipeline,
+}
+
+impl DebugDraw {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            enabled: false,
+            depth_test: true,
+            depth_tested: Vec::new(),
+            overlay: Vec::new(),
+            buffer: create_line_buffer(device, INITIAL_CAPACITY),
+            capacity: INITIAL_CAPACITY,
+            depth_tested_count: 0,
+            overlay_count: 0,
+            depth_tested_pipeline: create_line_pipeline(device, config, true)?,
+            overlay_pipeline: create_line_pipeline(device, config, false)?,
+        })
+    }
+
+    // shader files the pipelines are built from, see `reload_pipelines`
+    pub const SHADERS: &'static [&'static str] = &["debug_lines.wgsl"];
+
+    /// Rebuilds the pipelines from the current shader files, the old ones stay if that fails.
+    pub fn reload_pipelines(
+        &mut self,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+    ) {
+        let pipelines = try_create(device, "debug line pipelines", || {
+            Ok((create_line_pipeline(device, config, true)?, create_line_pipeline(device, config, false)?))
+        });
+        if let Some((depth_tested_pipeline, overlay_pipeline)) = pipelines {
+            self.depth_tested_pipeline = depth_tested_pipeline;
+            self.overlay_pipeline = overlay_pipeline;
+        }
+    }
+
+    // F3 toggles the built in gizmos drawn by State
+    pub fn process_events(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(VirtualKeyCode::F3),
+                    ..
+                },
+                ..
+            } => {
+                self.enabled = !self.enabled;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Affects every shape queued after this call.
+    pub fn set_depth_test(&mut self, enabled: bool) {
+        self.depth_test = enabled;
+    }
+
+    pub fn line(&mut self, from: Point3<f32>, to: Point3<f32>, color: [f32; 4]) {
+        let lines = if self.depth_test {
+            &mut self.depth_tested
+        } else {
+            &mut self.overlay
+        };
+        lines.push(LineVertex { position: from.into(), color });
+        lines.push(LineVertex { position: to.into(), color });
+    }
+
+    pub fn aabb(&mut self, min: Point3<f32>, max: Point3<f32>, color: [f32; 4]) {
+        let corners = [
+            Point3::new(min.x, min.y, min.z),
+            Point3::new(max.x, min.y, min.z),
+            Point3::new(max.x, max.y, min.z),
+            Point3::new(min.x, max.y, min.z),
+            Point3::new(min.x, min.y, max.z),
+            Point3::new(max.x, min.y, max.z),
+            Point3::new(max.x, max.y, max.z),
+            Point3::new(min.x, max.y, max.z),
+        ];
+        self.box_edges(&corners, color);
+    }
+
+    /// Three great circles around `center`.
+    pub fn sphere(&mut self, center: Point3<f32>, radius: f32, color: [f32; 4]) {
+        let axes = [
+            (Vector3::unit_x(), Vector3::unit_y()),
+            (Vector3::unit_y(), Vector3::unit_z()),
+            (Vector3::unit_z(), Vector3::unit_x()),
+        ];
+        for (u, v) in axes {
+            let point = |i: usize| {
+                let angle = i as f32 / SPHERE_SEGMENTS as f32 * std::f32::consts::TAU;
+                center + (u * angle.cos() + v * angle.sin()) * radius
+            };
+            for i in 0..SPHERE_SEGMENTS {
+                self.line(point(i), point(i + 1), color);
+            }
+        }
+    }
+
+    /// Outline of the volume seen through `view_proj` (wgpu clip space, 0..1 depth).
+    #[allow(dead_code)]
+    pub fn frustum(&mut self, view_proj: Matrix4<f32>, color: [f32; 4]) {
+        let Some(inverse) = view_proj.invert() else {
+            return;
+        };
+        let corner = |x: f32, y: f32, z: f32| inverse.transform_point(Point3::new(x, y, z));
+        let corners = [
+            corner(-1.0, -1.0, 0.0),
+            corner(1.0, -1.0, 0.0),
+            corner(1.0, 1.0, 0.0),
+            corner(-1.0, 1.0, 0.0),
+            corner(-1.0, -1.0, 1.0),
+            corner(1.0, -1.0, 1.0),
+            corner(1.0, 1.0, 1.0),
+            corner(-1.0, 1.0, 1.0),
+        ];
+        self.box_edges(&corners, color);
+    }
+
+    /// Local x, y and z axes of `transform` in red, green and blue.
+    pub fn axes(&mut self, transform: Matrix4<f32>, size: f32) {
+        let origin = transform.transform_point(Point3::new(0.0, 0.0, 0.0));
+        let axis = |v: Vector3<f32>| transform.transform_point(Point3::new(0.0, 0.0, 0.0) + v * size);
+        self.line(origin, axis(Vector3::unit_x()), RED);
+        self.line(origin, axis(Vector3::unit_y()), GREEN);
+        self.line(origin, axis(Vector3::unit_z()), BLUE);
+    }
+
+    /// Grid on the xz plane centered on `center` with `cells` cells per side.
+    pub fn grid(&mut self, center: Point3<f32>, cells: u32, spacing: f32, color: [f32; 4]) {
+        let half = cells as f32 * spacing * 0.5;
+        for i in 0..=cells {
+            let offset = i as f32 * spacing - half;
+            self.line(
+                center + Vector3::new(offset, 0.0, -half),
+                center + Vector3::new(offset, 0.0, half),
+                color,
+            );
+            self.line(
+                center + Vector3::new(-half, 0.0, offset),
+                center + Vector3::new(half, 0.0, offset),
+                color,
+            );
+        }
+    }
+
+    // corners 0..4 are one face and 4..8 the opposite one, in matching order
+    fn box_edges(&mut self, corners: &[Point3<f32>; 8], color: [f32; 4]) {
+        for i in 0..4 {
+            let next = (i + 1) % 4;
+            self.line(corners[i], corners[next], color);
+            self.line(corners[i + 4], corners[next + 4], color);
+            self.line(corners[i], corners[i + 4], color);
+        }
+    }
+
+    /// Uploads this frame's lines and clears the queues for the next frame.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let count = self.depth_tested.len() + self.overlay.len();
+        if count > self.capacity {
+            self.capacity = count.next_power_of_two();
+            self.buffer = create_line_buffer(device, self.capacity);
+        }
+
+        self.depth_tested_count = self.depth_tested.len() as u32;
+        self.overlay_count = self.overlay.len() as u32;
+        self.depth_tested.append(&mut self.overlay);
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&self.depth_tested));
+        self.depth_tested.clear();
+        self.depth_test = true;
+    }
+
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+    ) {
+        if self.depth_tested_count + self.overlay_count == 0 {
+            return;
+        }
+        render_pass.set_vertex_buffer(0, self.buffer.slice(..));
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+
+        render_pass.set_pipeline(&self.depth_tested_pipeline);
+        render_pass.draw(0..self.depth_tested_count, 0..1);
+
+        render_pass.set_pipeline(&self.overlay_pipeline);
+        let overlay_start = self.depth_tested_count;
+        render_pass.draw(overlay_start..overlay_start + self.overlay_count, 0..1);
+    }
+}
+
+fn create_line_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Debug Line Buffer"),
+        size: (capacity * std::mem::size_of::<LineVertex>()) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+fn create_line_pipeline(
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+    depth_test: bool,
+) -> anyhow::Result<wgpu::RenderPipeline> {
+    let layout = device.create_pipeline_layout(
+        &wgpu::PipelineLayoutDescriptor {
+            label: Some("Debug Line Pipeline Layout"),
+            bind_group_layouts: &[&create_camera_bind_group_layout(device)],
+            push_constant_ranges: &[],
+        }
+    );
+
+    let shader = create_shader_module(device, "debug_lines.wgsl")?;
+
+    Ok(device.create_render_pipeline(
+        &wgpu::RenderPipelineDescriptor {
+            label: Some("Debug Line Pipeline"),
+            layout: Some(&layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[LineVertex::desc()],
+            },
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                ..Default::default()
+            },
+            // the overlay still needs a depth state to be used in passes with a depth attachment
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: if depth_test {
+                    wgpu::CompareFunction::LessEqual
+                } else {
+                    wgpu::CompareFunction::Always
+                },
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: Default::default(),
+            multiview: None,
+        }
+    ))
+}
@@ -0,0 +1,494 @@
+use wgpu::util::DeviceExt;
+use wgpu::{FragmentState, VertexState};
+use winit::event::{ElementState, KeyboardInput, VirtualKeyCode, WindowEvent};
+use crate::camera::{Camera, create_camera_bind_group_layout};
+use crate::model::{Model, ModelVertex, Vertex};
+use crate::model_matrix::RawModelMatrix;
+use crate::texture::Texture;
+use crate::shader::{create_shader_module, try_create};
+
+// meshes past this count wrap around and reuse colors in the mesh color view
+pub const MAX_DEBUG_MESHES: u32 = 256;
+
+/// Replacement shading for inspecting models, cycled at runtime with F1.
+/// The values are the view ids used by debug.wgsl.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[repr(u32)]
+pub enum DebugView {
+    #[default]
+    Lit = 0,
+    Normals = 1,
+    UvChecker = 2,
+    Depth = 3,
+    MeshColors = 4,
+}
+
+impl DebugView {
+    pub fn next(self) -> Self {
+        match self {
+            DebugView::Lit => DebugView::Normals,
+            DebugView::Normals => DebugView::UvChecker,
+            DebugView::UvChecker => DebugView::Depth,
+            DebugView::Depth => DebugView::MeshColors,
+            DebugView::MeshColors => DebugView::Lit,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct DebugUniform {
+    pub view: u32,
+    pub near: f32,
+    pub far: f32,
+    _padding: u32,
+}
+
+impl DebugUniform {
+    pub fn new(view: DebugView, camera: &Camera) -> Self {
+        Self {
+            view: view as u32,
+            near: camera.znear,
+            far: camera.zfar,
+            _padding: 0,
+        }
+    }
+}
+
+pub struct DebugRenderer {
+    pub view: DebugView,
+    // F2 toggles an edge overlay on top of whatever view is active
+    pub wireframe: bool,
+
+    uniform_buffer: wgpu::Buffer,
+    // one mesh index per dynamic offset slot
+    mesh_stride: u32,
+    bind_group: wgpu::BindGroup,
+    view_pipeline: wgpu::RenderPipeline,
+
+    // used when the device supports POLYGON_MODE_LINE
+    line_pipeline: Option<wgpu::RenderPipeline>,
+    barycentric_pipeline: wgpu::RenderPipeline,
+    mesh_storage_layout: wgpu::BindGroupLayout,
+}
+
+impl DebugRenderer {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        camera: &Camera,
+    ) -> anyhow::Result<Self> {
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Debug Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[DebugUniform::new(DebugView::Lit, camera)]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let mesh_stride = device.limits().min_uniform_buffer_offset_alignment;
+        let mut mesh_data = vec![0u8; (mesh_stride * MAX_DEBUG_MESHES) as usize];
+        for index in 0..MAX_DEBUG_MESHES {
+            let offset = (index * mesh_stride) as usize;
+            mesh_data[offset..offset + 4].copy_from_slice(&index.to_ne_bytes());
+        }
+        let mesh_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Debug Mesh Index Buffer"),
+            contents: &mesh_data,
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let layout = create_debug_bind_group_layout(device);
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Debug Bind Group"),
+            layout: &layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: &mesh_buffer,
+                        offset: 0,
+                        size: wgpu::BufferSize::new(4),
+                    }),
+                },
+            ],
+        });
+
+        let mesh_storage_layout = create_mesh_storage_bind_group_layout(device);
+
+        let line_pipeline = device.features()
+            .contains(wgpu::Features::POLYGON_MODE_LINE)
+            .then(|| create_line_pipeline(device, config))
+            .transpose()?;
+
+        Ok(Self {
+            view: DebugView::Lit,
+            wireframe: false,
+            uniform_buffer,
+            mesh_stride,
+            bind_group,
+            view_pipeline: create_view_pipeline(device, config, &layout)?,
+            line_pipeline,
+            barycentric_pipeline: create_barycentric_pipeline(device, config, &mesh_storage_layout)?,
+            mesh_storage_layout,
+        })
+    }
+
+    pub fn process_events(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(keycode),
+                    ..
+                },
+                ..
+            } => match keycode {
+                VirtualKeyCode::F1 => {
+                    self.view = self.view.next();
+                    log::info!("debug view: {:?}", self.view);
+                    true
+                }
+                VirtualKeyCode::F2 => {
+                    self.wireframe = !self.wireframe;
+                    true
+                }
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    // shader files the pipelines are built from, see `reload_pipelines`
+    pub const SHADERS: &'static [&'static str] = &["debug.wgsl", "wireframe.wgsl"];
+
+    /// Rebuilds the pipelines from the current shader files, the old ones stay if that fails.
+    pub fn reload_pipelines(
+        &mut self,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+    ) {
+        let pipelines = try_create(device, "debug view pipelines", || {
+            Ok((
+                create_view_pipeline(device, config, &create_debug_bind_group_layout(device))?,
+                self.line_pipeline.is_some().then(|| create_line_pipeline(device, config)).transpose()?,
+                create_barycentric_pipeline(device, config, &self.mesh_storage_layout)?,
+            ))
+        });
+        if let Some((view_pipeline, line_pipeline, barycentric_pipeline)) = pipelines {
+            self.view_pipeline = view_pipeline;
+            self.line_pipeline = line_pipeline;
+            self.barycentric_pipeline = barycentric_pipeline;
+        }
+    }
+
+    pub fn update(&self, queue: &wgpu::Queue, camera: &Camera) {
+        queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[DebugUniform::new(self.view, camera)]),
+        );
+    }
+
+    /// Draws every mesh of `models` with the active debug view.
+    pub fn draw_models<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        models: &[&'a Model],
+        camera_bind_group: &'a wgpu::BindGroup,
+    ) {
+        render_pass.set_pipeline(&self.view_pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+
+        let mut mesh_index = 0;
+        for model in models {
+            render_pass.set_vertex_buffer(1, model.instance_buffer().slice(..));
+            for mesh in &model.meshes {
+                let offset = (mesh_index % MAX_DEBUG_MESHES) * self.mesh_stride;
+                render_pass.set_bind_group(1, &self.bind_group, &[offset]);
+                let (index_buffer, num_elements) = mesh.lod_indices();
+                render_pass.set_vertex_buffer(0, mesh.geometry.vertex_buffer.slice(..));
+                render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                render_pass.draw_indexed(0..num_elements, 0, model.instance_range());
+                mesh_index += 1;
+            }
+        }
+    }
+
+    /// Bind groups exposing mesh buffers to the barycentric fallback, one per mesh of `models`.
+    /// Empty when line rasterization is available.
+    pub fn wireframe_bind_groups(&self, device: &wgpu::Device, models: &[&Model]) -> Vec<wgpu::BindGroup> {
+        if self.line_pipeline.is_some() {
+            return Vec::new();
+        }
+        models.iter()
+            .flat_map(|model| &model.meshes)
+            .map(|mesh| device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Wireframe Mesh Bind Group"),
+                layout: &self.mesh_storage_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: mesh.lod_indices().0.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: mesh.geometry.vertex_buffer.as_entire_binding(),
+                    },
+                ],
+            }))
+            .collect()
+    }
+
+    pub fn draw_wireframe<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        models: &[&'a Model],
+        camera_bind_group: &'a wgpu::BindGroup,
+        mesh_bind_groups: &'a [wgpu::BindGroup],
+    ) {
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+
+        if let Some(line_pipeline) = &self.line_pipeline {
+            render_pass.set_pipeline(line_pipeline);
+            for model in models {
+                render_pass.set_vertex_buffer(1, model.instance_buffer().slice(..));
+                for mesh in &model.meshes {
+                    let (index_buffer, num_elements) = mesh.lod_indices();
+                    render_pass.set_vertex_buffer(0, mesh.geometry.vertex_buffer.slice(..));
+                    render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                    render_pass.draw_indexed(0..num_elements, 0, model.instance_range());
+                }
+            }
+            return;
+        }
+
+        render_pass.set_pipeline(&self.barycentric_pipeline);
+        let mut bind_groups = mesh_bind_groups.iter();
+        for model in models {
+            render_pass.set_vertex_buffer(0, model.instance_buffer().slice(..));
+            for mesh in &model.meshes {
+                let Some(bind_group) = bind_groups.next() else {
+                    return;
+                };
+                render_pass.set_bind_group(1, bind_group, &[]);
+                render_pass.draw(0..mesh.lod_indices().1, model.instance_range());
+            }
+        }
+    }
+}
+
+pub fn create_debug_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Debug Bind Group Layout"),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: wgpu::BufferSize::new(4),
+                },
+                count: None,
+            },
+        ],
+    })
+}
+
+pub fn create_mesh_storage_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    let storage = |binding| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::VERTEX,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only: true },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    };
+
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Mesh Storage Bind Group Layout"),
+        entries: &[storage(0), storage(1)],
+    })
+}
+
+fn create_view_pipeline(
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+    debug_layout: &wgpu::BindGroupLayout,
+) -> anyhow::Result<wgpu::RenderPipeline> {
+    let layout = device.create_pipeline_layout(
+        &wgpu::PipelineLayoutDescriptor {
+            label: Some("Debug View Pipeline Layout"),
+            bind_group_layouts: &[&create_camera_bind_group_layout(device), debug_layout],
+            push_constant_ranges: &[],
+        }
+    );
+
+    let shader = create_shader_module(device, "debug.wgsl")?;
+
+    Ok(device.create_render_pipeline(
+        &wgpu::RenderPipelineDescriptor {
+            label: Some("Debug View Pipeline"),
+            layout: Some(&layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[ModelVertex::desc(), RawModelMatrix::desc()],
+            },
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: Default::default(),
+            multiview: None,
+        }
+    ))
+}
+
+// pulls the overlay slightly towards the camera so it wins the depth test against the shaded surface
+fn wireframe_depth_stencil() -> wgpu::DepthStencilState {
+    wgpu::DepthStencilState {
+        format: Texture::DEPTH_FORMAT,
+        depth_write_enabled: false,
+        depth_compare: wgpu::CompareFunction::LessEqual,
+        stencil: wgpu::StencilState::default(),
+        bias: wgpu::DepthBiasState {
+            constant: -2,
+            slope_scale: -1.0,
+            clamp: 0.0,
+        },
+    }
+}
+
+fn create_line_pipeline(
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+) -> anyhow::Result<wgpu::RenderPipeline> {
+    let layout = device.create_pipeline_layout(
+        &wgpu::PipelineLayoutDescriptor {
+            label: Some("Wireframe Line Pipeline Layout"),
+            bind_group_layouts: &[&create_camera_bind_group_layout(device)],
+            push_constant_ranges: &[],
+        }
+    );
+
+    let shader = create_shader_module(device, "wireframe.wgsl")?;
+
+    Ok(device.create_render_pipeline(
+        &wgpu::RenderPipelineDescriptor {
+            label: Some("Wireframe Line Pipeline"),
+            layout: Some(&layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: "vs_line",
+                buffers: &[ModelVertex::desc(), RawModelMatrix::desc()],
+            },
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: "fs_line",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Line,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: Some(wireframe_depth_stencil()),
+            multisample: Default::default(),
+            multiview: None,
+        }
+    ))
+}
+
+fn create_barycentric_pipeline(
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+    mesh_storage_layout: &wgpu::BindGroupLayout,
+) -> anyhow::Result<wgpu::RenderPipeline> {
+    let layout = device.create_pipeline_layout(
+        &wgpu::PipelineLayoutDescriptor {
+            label: Some("Wireframe Barycentric Pipeline Layout"),
+            bind_group_layouts: &[&create_camera_bind_group_layout(device), mesh_storage_layout],
+            push_constant_ranges: &[],
+        }
+    );
+
+    let shader = create_shader_module(device, "wireframe.wgsl")?;
+
+    Ok(device.create_render_pipeline(
+        &wgpu::RenderPipelineDescriptor {
+            label: Some("Wireframe Barycentric Pipeline"),
+            layout: Some(&layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: "vs_barycentric",
+                buffers: &[RawModelMatrix::desc()],
+            },
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: "fs_barycentric",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: Some(wireframe_depth_stencil()),
+            multisample: Default::default(),
+            multiview: None,
+        }
+    ))
+}
@@ -0,0 +1,276 @@
+use wgpu::{FragmentState, VertexState};
+use crate::camera::create_camera_bind_group_layout;
+use crate::light::create_light_bind_group_layout;
+use crate::model::{create_material_bind_group_layout, ModelVertex, Vertex};
+use crate::model_matrix::RawModelMatrix;
+use crate::texture::Texture;
+use crate::shader::{create_shader_module, try_create};
+
+pub const ALBEDO_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+pub const NORMAL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+pub const MATERIAL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+/// Render targets written by the geometry pass and read by the lighting pass.
+/// Depth is shared with the forward path through `GraphicsContext::depth_texture`.
+pub struct GBuffer {
+    pub albedo: Texture,
+    pub normal: Texture,
+    pub material: Texture,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl GBuffer {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        depth_texture: &Texture,
+    ) -> Self {
+        let albedo = Texture::create_render_target(device, config, ALBEDO_FORMAT, "gbuffer albedo");
+        let normal = Texture::create_render_target(device, config, NORMAL_FORMAT, "gbuffer normal");
+        let material = Texture::create_render_target(device, config, MATERIAL_FORMAT, "gbuffer material");
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("GBuffer Bind Group"),
+            layout: &create_gbuffer_bind_group_layout(device),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&albedo.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&normal.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&material.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&depth_texture.view),
+                },
+            ],
+        });
+
+        Self {
+            albedo,
+            normal,
+            material,
+            bind_group,
+        }
+    }
+
+    pub fn color_attachments(&self) -> [Option<wgpu::RenderPassColorAttachment<'_>>; 3] {
+        [&self.albedo, &self.normal, &self.material].map(|target| {
+            Some(wgpu::RenderPassColorAttachment {
+                view: &target.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: true,
+                },
+            })
+        })
+    }
+}
+
+pub fn create_gbuffer_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    let color_entry = |binding| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            multisampled: false,
+            view_dimension: wgpu::TextureViewDimension::D2,
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+        },
+        count: None,
+    };
+
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("GBuffer Bind Group Layout"),
+        entries: &[
+            color_entry(0),
+            color_entry(1),
+            color_entry(2),
+            wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Depth,
+                },
+                count: None,
+            },
+        ],
+    })
+}
+
+pub struct DeferredPipeline {
+    pub geometry_pipeline: wgpu::RenderPipeline,
+    pub lighting_pipeline: wgpu::RenderPipeline,
+}
+
+impl DeferredPipeline {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            geometry_pipeline: create_geometry_pipeline(device)?,
+            lighting_pipeline: create_lighting_pipeline(device, config)?,
+        })
+    }
+}
+
+fn create_geometry_pipeline(device: &wgpu::Device) -> anyhow::Result<wgpu::RenderPipeline> {
+    // same layouts as SimplePipeline so models can be drawn with DrawModel
+    let layouts = &[
+        &create_material_bind_group_layout(device),
+        &create_camera_bind_group_layout(device),
+        &create_light_bind_group_layout(device),
+    ];
+
+    let layout = device.create_pipeline_layout(
+        &wgpu::PipelineLayoutDescriptor {
+            label: Some("GBuffer Pipeline Layout"),
+            bind_group_layouts: layouts,
+            push_constant_ranges: &[],
+        }
+    );
+
+    let shader = create_shader_module(device, "gbuffer.wgsl")?;
+
+    let target = |format| Some(wgpu::ColorTargetState {
+        format,
+        blend: Some(wgpu::BlendState::REPLACE),
+        write_mask: wgpu::ColorWrites::ALL,
+    });
+
+    Ok(device.create_render_pipeline(
+        &wgpu::RenderPipelineDescriptor {
+            label: Some("GBuffer Pipeline"),
+            layout: Some(&layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[ModelVertex::desc(), RawModelMatrix::desc()],
+            },
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[
+                    target(ALBEDO_FORMAT),
+                    target(NORMAL_FORMAT),
+                    target(MATERIAL_FORMAT),
+                ],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: Default::default(),
+            multiview: None,
+        }
+    ))
+}
+
+fn create_lighting_pipeline(
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+) -> anyhow::Result<wgpu::RenderPipeline> {
+    let layouts = &[
+        &create_gbuffer_bind_group_layout(device),
+        &create_camera_bind_group_layout(device),
+        &create_light_bind_group_layout(device),
+    ];
+
+    let layout = device.create_pipeline_layout(
+        &wgpu::PipelineLayoutDescriptor {
+            label: Some("Deferred Lighting Pipeline Layout"),
+            bind_group_layouts: layouts,
+            push_constant_ranges: &[],
+        }
+    );
+
+    let shader = create_shader_module(device, "deferred_lighting.wgsl")?;
+
+    Ok(device.create_render_pipeline(
+        &wgpu::RenderPipelineDescriptor {
+            label: Some("Deferred Lighting Pipeline"),
+            layout: Some(&layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: Default::default(),
+            multiview: None,
+        }
+    ))
+}
+
+pub struct DeferredRenderer {
+    pub pipeline: DeferredPipeline,
+    pub gbuffer: GBuffer,
+}
+
+impl DeferredRenderer {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        depth_texture: &Texture,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            pipeline: DeferredPipeline::new(device, config)?,
+            gbuffer: GBuffer::new(device, config, depth_texture),
+        })
+    }
+
+    // the G-buffer has to match the surface size and reference the current depth texture
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        depth_texture: &Texture,
+    ) {
+        self.gbuffer = GBuffer::new(device, config, depth_texture);
+    }
+
+    // shader files the pipelines are built from, see `reload_pipelines`
+    pub const SHADERS: &'static [&'static str] = &["gbuffer.wgsl", "deferred_lighting.wgsl"];
+
+    /// Rebuilds the pipelines from the current shader files, the old ones stay if that fails.
+    pub fn reload_pipelines(
+        &mut self,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+    ) {
+        if let Some(pipeline) = try_create(device, "deferred pipelines", || DeferredPipeline::new(device, config)) {
+            self.pipeline = pipeline;
+        }
+    }
+}
@@ -0,0 +1,754 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use wgpu::util::DeviceExt;
+use crate::camera::Camera;
+use crate::culling::{CullStats, Frustum};
+use crate::model::{Model, ModelVertex};
+use crate::lod::MAX_LODS;
+use crate::model_matrix::RawModelMatrix;
+use crate::texture::Texture;
+use crate::shader::{create_shader_module, try_create};
+
+const WORKGROUP_SIZE: u32 = 64;
+const HIZ_WORKGROUP_SIZE: u32 = 8;
+const HIZ_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Float;
+const MATRIX_SIZE: u64 = std::mem::size_of::<RawModelMatrix>() as u64;
+const ARGS_SIZE: u64 = std::mem::size_of::<DrawIndexedArgs>() as u64;
+
+/// Whether opaque meshes are culled and drawn on the GPU. Picked once at startup.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum GpuCulling {
+    // CPU frustum culling and one draw call per visible mesh
+    #[default]
+    Off,
+    Frustum,
+    // frustum plus occlusion against the previous frame's depth
+    HiZ,
+}
+
+impl GpuCulling {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "off" => Some(GpuCulling::Off),
+            "frustum" => Some(GpuCulling::Frustum),
+            "hiz" => Some(GpuCulling::HiZ),
+            _ => None,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct CullUniform {
+    pub view_proj: [[f32; 4]; 4],
+    pub planes: [[f32; 4]; 6],
+    pub screen_size: [f32; 2],
+    pub object_count: u32,
+    pub hiz_levels: u32,
+    pub camera_position: [f32; 3],
+    // 1 / tan(fovy / 2), turns radius / distance into the screen height fraction
+    pub lod_scale: f32,
+}
+
+/// Layout of one `draw_indexed_indirect` call.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct DrawIndexedArgs {
+    pub index_count: u32,
+    pub instance_count: u32,
+    pub first_index: u32,
+    pub base_vertex: i32,
+    pub first_instance: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct GpuObject {
+    center: [f32; 3],
+    radius: f32,
+    // batch of the full detail mesh, its coarser levels follow
+    batch: u32,
+    instance: u32,
+    // first slot of that batch in the visible instance buffer
+    base: u32,
+    // batches of the mesh, 1 without coarser levels
+    lod_count: u32,
+    // `MeshLod::screen_size` of the coarser levels
+    lod_screen_sizes: [f32; MAX_LODS],
+    // slots per batch, the next level's batch starts this many slots later
+    level_slots: u32,
+}
+
+// one indirect draw: a level of detail of a mesh of a model with all of its visible
+// instances that picked that level
+struct Batch {
+    model: usize,
+    material: usize,
+    // first slot in the visible instance buffer
+    base: u32,
+}
+
+/// Every opaque mesh of the registered models merged into shared buffers,
+/// rebuilt whenever the set of models, their instance counts or meshes change.
+struct GpuGeometry {
+    // identity, instance count and meshes of every model, compared each frame
+    signature: Vec<(usize, u32, Vec<u64>)>,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    instance_buffer: wgpu::Buffer,
+    draw_buffer: wgpu::Buffer,
+    visible_buffer: wgpu::Buffer,
+    // copied over the draw args before every cull to reset the instance counts
+    draw_template: wgpu::Buffer,
+    batches: Vec<Batch>,
+    object_count: u32,
+    bind_group: wgpu::BindGroup,
+    stats_readback: StatsReadback,
+}
+
+// the draw arguments copied back after a cull to count the instances that survived it,
+// a frame or more late
+struct StatsReadback {
+    buffer: wgpu::Buffer,
+    // the cull's arguments were copied in this frame's commands
+    copied: bool,
+    // map requested, `mapped` is set once the data can be read
+    in_flight: bool,
+    mapped: Arc<AtomicBool>,
+}
+
+/// Max depth mip chain built from the depth buffer after the opaque pass.
+struct HiZPyramid {
+    view: wgpu::TextureView,
+    levels: u32,
+    sizes: Vec<(u32, u32)>,
+    copy_bind_group: wgpu::BindGroup,
+    downsample_bind_groups: Vec<wgpu::BindGroup>,
+}
+
+/// GPU driven opaque rendering. A compute pass culls every mesh instance and fills
+/// indirect draw arguments, so the CPU records one draw per mesh regardless of instance count.
+pub struct GpuDrivenRenderer {
+    pub mode: GpuCulling,
+    cull_layout: wgpu::BindGroupLayout,
+    copy_layout: wgpu::BindGroupLayout,
+    downsample_layout: wgpu::BindGroupLayout,
+    cull_pipeline: wgpu::ComputePipeline,
+    copy_pipeline: wgpu::ComputePipeline,
+    downsample_pipeline: wgpu::ComputePipeline,
+    uniform_buffer: wgpu::Buffer,
+    geometry: Option<GpuGeometry>,
+    hiz: HiZPyramid,
+    // the pyramid holds a depth buffer, until then every object would look occluded
+    hiz_valid: bool,
+    stats: Option<CullStats>,
+}
+
+impl GpuDrivenRenderer {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        depth: &Texture,
+        mode: GpuCulling,
+    ) -> anyhow::Result<Self> {
+        let cull_layout = create_cull_bind_group_layout(device);
+        let copy_layout = create_hiz_bind_group_layout(device, true);
+        let downsample_layout = create_hiz_bind_group_layout(device, false);
+
+        let cull_shader = create_shader_module(device, "gpu_cull.wgsl")?;
+        let hiz_shader = create_shader_module(device, "hiz.wgsl")?;
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cull Uniform Buffer"),
+            size: std::mem::size_of::<CullUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let hiz = HiZPyramid::new(device, config, depth, &copy_layout, &downsample_layout);
+
+        Ok(Self {
+            mode,
+            cull_pipeline: create_compute_pipeline(device, &cull_layout, &cull_shader, "cs_main", "Cull Pipeline"),
+            copy_pipeline: create_compute_pipeline(device, &copy_layout, &hiz_shader, "cs_copy", "Hi-Z Copy Pipeline"),
+            downsample_pipeline: create_compute_pipeline(device, &downsample_layout, &hiz_shader, "cs_downsample", "Hi-Z Downsample Pipeline"),
+            cull_layout,
+            copy_layout,
+            downsample_layout,
+            uniform_buffer,
+            geometry: None,
+            hiz,
+            hiz_valid: false,
+            stats: None,
+        })
+    }
+
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        depth: &Texture,
+    ) {
+        self.hiz = HiZPyramid::new(device, config, depth, &self.copy_layout, &self.downsample_layout);
+        self.hiz_valid = false;
+        // the cull bind group references the old pyramid
+        self.geometry = None;
+    }
+
+    // shader files the pipelines are built from, see `reload_pipelines`
+    pub const SHADERS: &'static [&'static str] = &["gpu_cull.wgsl", "hiz.wgsl"];
+
+    /// Rebuilds the pipelines from the current shader files, the old ones stay if that fails.
+    pub fn reload_pipelines(&mut self, device: &wgpu::Device) {
+        let pipelines = try_create(device, "GPU culling pipelines", || {
+            let cull_shader = create_shader_module(device, "gpu_cull.wgsl")?;
+            let hiz_shader = create_shader_module(device, "hiz.wgsl")?;
+            Ok((
+                create_compute_pipeline(device, &self.cull_layout, &cull_shader, "cs_main", "Cull Pipeline"),
+                create_compute_pipeline(device, &self.copy_layout, &hiz_shader, "cs_copy", "Hi-Z Copy Pipeline"),
+                create_compute_pipeline(device, &self.downsample_layout, &hiz_shader, "cs_downsample", "Hi-Z Downsample Pipeline"),
+            ))
+        });
+        if let Some((cull_pipeline, copy_pipeline, downsample_pipeline)) = pipelines {
+            self.cull_pipeline = cull_pipeline;
+            self.copy_pipeline = copy_pipeline;
+            self.downsample_pipeline = downsample_pipeline;
+        }
+    }
+
+    /// Uploads this frame's instances and records the cull pass.
+    /// `models` has to be passed in the same order to `draw`.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        models: &[&Model],
+        camera: &Camera,
+        config: &wgpu::SurfaceConfiguration,
+    ) {
+        let signature = model_signature(models);
+        if self.geometry.as_ref().is_none_or(|geometry| geometry.signature != signature) {
+            self.geometry = GpuGeometry::new(device, encoder, models, signature, &self.cull_layout, &self.uniform_buffer, &self.hiz.view);
+        }
+        let Some(geometry) = &mut self.geometry else {
+            self.stats = None;
+            return;
+        };
+
+        // instance matrices are copied on the GPU from each model's own buffer
+        let mut offset = 0;
+        for model in models {
+            let size = model.instance_range().end as u64 * MATRIX_SIZE;
+            if size > 0 {
+                encoder.copy_buffer_to_buffer(model.instance_buffer(), 0, &geometry.instance_buffer, offset, size);
+            }
+            offset += size;
+        }
+        encoder.copy_buffer_to_buffer(&geometry.draw_template, 0, &geometry.draw_buffer, 0, geometry.draw_template.size());
+
+        let view_proj = camera.build_view_projection_matrix();
+        let frustum = Frustum::from_view_projection(view_proj);
+        let uniform = CullUniform {
+            view_proj: view_proj.into(),
+            planes: frustum.planes.map(Into::into),
+            screen_size: [config.width as f32, config.height as f32],
+            object_count: geometry.object_count,
+            hiz_levels: if self.mode == GpuCulling::HiZ && self.hiz_valid { self.hiz.levels } else { 0 },
+            camera_position: camera.position().into(),
+            lod_scale: 1.0 / (camera.fovy * 0.5).to_radians().tan(),
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("gpu cull pass"),
+        });
+        compute_pass.set_pipeline(&self.cull_pipeline);
+        compute_pass.set_bind_group(0, &geometry.bind_group, &[]);
+        compute_pass.dispatch_workgroups(geometry.object_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+        drop(compute_pass);
+
+        let readback = &mut geometry.stats_readback;
+        if !readback.in_flight {
+            encoder.copy_buffer_to_buffer(&geometry.draw_buffer, 0, &readback.buffer, 0, readback.buffer.size());
+            readback.copied = true;
+        }
+    }
+
+    /// Starts reading back the instance counts of the cull recorded by `prepare`,
+    /// call after submitting its commands.
+    pub fn request_stats(&mut self) {
+        let Some(geometry) = &mut self.geometry else {
+            return;
+        };
+        let readback = &mut geometry.stats_readback;
+        if !readback.copied {
+            return;
+        }
+        let mapped = readback.mapped.clone();
+        readback.buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+            mapped.store(result.is_ok(), Ordering::Release);
+        });
+        readback.copied = false;
+        readback.in_flight = true;
+    }
+
+    /// Mesh instances tested and culled by the latest cull that was read back,
+    /// none before the first one arrived.
+    pub fn stats(&mut self, device: &wgpu::Device) -> Option<CullStats> {
+        device.poll(wgpu::Maintain::Poll);
+        if let Some(geometry) = &mut self.geometry {
+            let readback = &mut geometry.stats_readback;
+            if readback.in_flight && readback.mapped.swap(false, Ordering::Acquire) {
+                let visible = {
+                    let data = readback.buffer.slice(..).get_mapped_range();
+                    bytemuck::cast_slice::<u8, DrawIndexedArgs>(&data).iter()
+                        .map(|args| args.instance_count)
+                        .sum::<u32>()
+                };
+                readback.buffer.unmap();
+                readback.in_flight = false;
+                self.stats = Some(CullStats {
+                    tested: geometry.object_count,
+                    culled: geometry.object_count - visible,
+                });
+            }
+        }
+        self.stats
+    }
+
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        models: &[&'a Model],
+        camera_bind_group: &'a wgpu::BindGroup,
+        light_bind_group: &'a wgpu::BindGroup,
+    ) {
+        let Some(geometry) = &self.geometry else {
+            return;
+        };
+        render_pass.set_vertex_buffer(0, geometry.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(geometry.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        render_pass.set_bind_group(1, camera_bind_group, &[]);
+        render_pass.set_bind_group(2, light_bind_group, &[]);
+        for (i, batch) in geometry.batches.iter().enumerate() {
+            let material = &models[batch.model].materials[batch.material];
+            render_pass.set_bind_group(0, &material.bind_group, &[]);
+            // first_instance in indirect draws needs an optional feature, offset the buffer instead
+            render_pass.set_vertex_buffer(1, geometry.visible_buffer.slice(batch.base as u64 * MATRIX_SIZE..));
+            render_pass.draw_indexed_indirect(&geometry.draw_buffer, i as u64 * ARGS_SIZE);
+        }
+    }
+
+    /// Builds the Hi-Z pyramid from the finished depth buffer for the next frame's cull.
+    pub fn build_hiz(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if self.mode != GpuCulling::HiZ {
+            return;
+        }
+        self.hiz_valid = true;
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("hi-z pass"),
+        });
+        let groups = |(width, height): (u32, u32)| (width.div_ceil(HIZ_WORKGROUP_SIZE), height.div_ceil(HIZ_WORKGROUP_SIZE));
+
+        compute_pass.set_pipeline(&self.copy_pipeline);
+        compute_pass.set_bind_group(0, &self.hiz.copy_bind_group, &[]);
+        let (x, y) = groups(self.hiz.sizes[0]);
+        compute_pass.dispatch_workgroups(x, y, 1);
+
+        compute_pass.set_pipeline(&self.downsample_pipeline);
+        for (bind_group, &size) in self.hiz.downsample_bind_groups.iter().zip(&self.hiz.sizes[1..]) {
+            compute_pass.set_bind_group(0, bind_group, &[]);
+            let (x, y) = groups(size);
+            compute_pass.dispatch_workgroups(x, y, 1);
+        }
+    }
+}
+
+// meshes are part of it since a model's meshes are swapped once it finishes loading
+fn model_signature(models: &[&Model]) -> Vec<(usize, u32, Vec<u64>)> {
+    models.iter()
+        .map(|&model| (
+            model as *const Model as usize,
+            model.instance_range().end,
+            model.meshes.iter().map(|mesh| mesh.geometry.id()).collect(),
+        ))
+        .collect()
+}
+
+impl GpuGeometry {
+    fn new(
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        models: &[&Model],
+        signature: Vec<(usize, u32, Vec<u64>)>,
+        layout: &wgpu::BindGroupLayout,
+        uniform_buffer: &wgpu::Buffer,
+        hiz_view: &wgpu::TextureView,
+    ) -> Option<Self> {
+        let vertex_stride = std::mem::size_of::<ModelVertex>() as u64;
+
+        // place every opaque mesh in the merged buffers
+        let mut vertex_count = 0u64;
+        let mut index_count = 0u64;
+        let mut copies = Vec::new();
+        let mut vertex_copies = Vec::new();
+        let mut batches = Vec::new();
+        let mut draw_args = Vec::new();
+        let mut objects = Vec::new();
+        let mut first_instance = 0u32;
+        let mut visible_slots = 0u32;
+
+        for (model_index, model) in models.iter().enumerate() {
+            let instance_count = model.instance_range().end;
+            for mesh in &model.meshes {
+                if model.materials[mesh.geometry.material].is_transparent() {
+                    continue;
+                }
+                let geometry = &mesh.geometry;
+                let mesh_vertices = geometry.vertex_buffer.size() / vertex_stride;
+                let first_batch = batches.len() as u32;
+                let first_slot = visible_slots;
+                let lods = &geometry.lods[..geometry.lods.len().min(MAX_LODS)];
+                let mut lod_screen_sizes = [0.0; MAX_LODS];
+                for (size, lod) in lod_screen_sizes.iter_mut().zip(lods) {
+                    *size = lod.screen_size;
+                }
+
+                // the full index buffer and then every coarser level, each instance picks one
+                let levels = std::iter::once((&geometry.index_buffer, geometry.num_elements))
+                    .chain(lods.iter().map(|lod| (&lod.index_buffer, lod.num_elements)));
+                for (index_buffer, num_elements) in levels {
+                    copies.push((index_buffer, num_elements, index_count));
+                    draw_args.push(DrawIndexedArgs {
+                        index_count: num_elements,
+                        instance_count: 0,
+                        first_index: index_count as u32,
+                        base_vertex: vertex_count as i32,
+                        first_instance: 0,
+                    });
+                    batches.push(Batch {
+                        model: model_index,
+                        material: geometry.material,
+                        base: visible_slots,
+                    });
+                    visible_slots += instance_count;
+                    index_count += num_elements as u64;
+                }
+                vertex_copies.push((geometry, vertex_count));
+
+                for instance in 0..instance_count {
+                    objects.push(GpuObject {
+                        center: geometry.bounding_sphere.center.into(),
+                        radius: geometry.bounding_sphere.radius,
+                        batch: first_batch,
+                        instance: first_instance + instance,
+                        base: first_slot,
+                        lod_count: 1 + lods.len() as u32,
+                        lod_screen_sizes,
+                        level_slots: instance_count,
+                    });
+                }
+                vertex_count += mesh_vertices;
+            }
+            first_instance += instance_count;
+        }
+
+        if objects.is_empty() {
+            return None;
+        }
+
+        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Merged Vertex Buffer"),
+            size: vertex_count * vertex_stride,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let index_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Merged Index Buffer"),
+            size: index_count * 4,
+            usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        for (geometry, first_vertex) in vertex_copies {
+            encoder.copy_buffer_to_buffer(&geometry.vertex_buffer, 0, &vertex_buffer, first_vertex * vertex_stride, geometry.vertex_buffer.size());
+        }
+        for (source, num_elements, first_index) in copies {
+            encoder.copy_buffer_to_buffer(source, 0, &index_buffer, first_index * 4, num_elements as u64 * 4);
+        }
+
+        let instance_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cull Instance Buffer"),
+            size: first_instance as u64 * MATRIX_SIZE,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let object_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Cull Object Buffer"),
+            contents: bytemuck::cast_slice(&objects),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let draw_template = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Indirect Draw Template Buffer"),
+            contents: bytemuck::cast_slice(&draw_args),
+            usage: wgpu::BufferUsages::COPY_SRC,
+        });
+        let draw_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Indirect Draw Buffer"),
+            size: draw_template.size(),
+            usage: wgpu::BufferUsages::INDIRECT | wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let stats_readback = StatsReadback {
+            buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Indirect Draw Readback Buffer"),
+                size: draw_template.size(),
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            copied: false,
+            in_flight: false,
+            mapped: Arc::new(AtomicBool::new(false)),
+        };
+        let visible_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Visible Instance Buffer"),
+            size: visible_slots as u64 * MATRIX_SIZE,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Cull Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: instance_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: object_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: draw_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: visible_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::TextureView(hiz_view),
+                },
+            ],
+        });
+
+        Some(Self {
+            signature,
+            vertex_buffer,
+            index_buffer,
+            instance_buffer,
+            draw_buffer,
+            visible_buffer,
+            draw_template,
+            batches,
+            object_count: objects.len() as u32,
+            bind_group,
+            stats_readback,
+        })
+    }
+}
+
+impl HiZPyramid {
+    fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        depth: &Texture,
+        copy_layout: &wgpu::BindGroupLayout,
+        downsample_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let mut sizes = vec![(config.width.max(1), config.height.max(1))];
+        while let Some(&(width, height)) = sizes.last() {
+            if width == 1 && height == 1 {
+                break;
+            }
+            sizes.push(((width / 2).max(1), (height / 2).max(1)));
+        }
+        let levels = sizes.len() as u32;
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Hi-Z Pyramid"),
+            size: wgpu::Extent3d {
+                width: sizes[0].0,
+                height: sizes[0].1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: levels,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: HIZ_FORMAT,
+            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let level_view = |level: u32| texture.create_view(&wgpu::TextureViewDescriptor {
+            base_mip_level: level,
+            mip_level_count: std::num::NonZeroU32::new(1),
+            ..Default::default()
+        });
+
+        let copy_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Hi-Z Copy Bind Group"),
+            layout: copy_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&depth.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&level_view(0)),
+                },
+            ],
+        });
+        let downsample_bind_groups = (1..levels)
+            .map(|level| device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Hi-Z Downsample Bind Group"),
+                layout: downsample_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(&level_view(level)),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(&level_view(level - 1)),
+                    },
+                ],
+            }))
+            .collect();
+
+        Self {
+            view,
+            levels,
+            sizes,
+            copy_bind_group,
+            downsample_bind_groups,
+        }
+    }
+}
+
+pub fn create_cull_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    let storage = |binding, read_only| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    };
+
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Cull Bind Group Layout"),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            storage(1, true),
+            storage(2, true),
+            storage(3, false),
+            storage(4, false),
+            wgpu::BindGroupLayoutEntry {
+                binding: 5,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                },
+                count: None,
+            },
+        ],
+    })
+}
+
+// `copy` reads the depth buffer into level 0, otherwise one level is reduced into the next
+fn create_hiz_bind_group_layout(device: &wgpu::Device, copy: bool) -> wgpu::BindGroupLayout {
+    let source = if copy {
+        wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Depth,
+            },
+            count: None,
+        }
+    } else {
+        wgpu::BindGroupLayoutEntry {
+            binding: 2,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            },
+            count: None,
+        }
+    };
+
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Hi-Z Bind Group Layout"),
+        entries: &[
+            source,
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::StorageTexture {
+                    access: wgpu::StorageTextureAccess::WriteOnly,
+                    format: HIZ_FORMAT,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                },
+                count: None,
+            },
+        ],
+    })
+}
+
+fn create_compute_pipeline(
+    device: &wgpu::Device,
+    bind_group_layout: &wgpu::BindGroupLayout,
+    shader: &wgpu::ShaderModule,
+    entry_point: &str,
+    label: &str,
+) -> wgpu::ComputePipeline {
+    let layout = device.create_pipeline_layout(
+        &wgpu::PipelineLayoutDescriptor {
+            label: Some(label),
+            bind_group_layouts: &[bind_group_layout],
+            push_constant_ranges: &[],
+        }
+    );
+
+    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some(label),
+        layout: Some(&layout),
+        module: shader,
+        entry_point,
+    })
+}
@@ -30,7 +30,8 @@ impl GraphicsContext {
 
         let (device, queue) = adapter.request_device(
             &wgpu::DeviceDescriptor {
-                features: wgpu::Features::empty(),
+                // line rasterization is optional, the wireframe overlay falls back to barycentrics
+                features: adapter.features() & wgpu::Features::POLYGON_MODE_LINE,
                 limits: wgpu::Limits::default(),
                 label: None,
             },
@@ -65,6 +66,7 @@ impl GraphicsContext {
         }
     }
 
+    #[allow(dead_code)]
     pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
         self.size = new_size;
         self.config.width = new_size.width;
@@ -0,0 +1,347 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, SystemTime};
+use crate::asset_loader::{AssetLoader, LoadRequest, LoadedModel};
+use crate::asset_manager::{asset_path, texture_path, AssetManager, Handle, ModelAssets};
+use crate::model::{load_string, Material, Model};
+use crate::shader::try_create;
+use crate::texture::{load_texture, Texture};
+
+/// The `res` directory of the source tree, edits there show up without rebuilding.
+pub fn source_res_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("res")
+}
+
+/// Watches every file under a directory by polling modification times on a background thread.
+pub struct FileWatcher {
+    changes: mpsc::Receiver<String>,
+}
+
+impl FileWatcher {
+    pub fn new(root: PathBuf, interval: Duration) -> std::io::Result<Self> {
+        let (sender, changes) = mpsc::channel();
+        let mut known = scan(&root);
+        thread::Builder::new()
+            .name("file watcher".to_string())
+            .spawn(move || loop {
+                thread::sleep(interval);
+                let current = scan(&root);
+                for (path, modified) in &current {
+                    if known.get(path) == Some(modified) {
+                        continue;
+                    }
+                    let Ok(relative) = path.strip_prefix(&root) else {
+                        continue;
+                    };
+                    let relative = relative.components()
+                        .map(|c| c.as_os_str().to_string_lossy())
+                        .collect::<Vec<_>>()
+                        .join("/");
+                    // the watcher was dropped
+                    if sender.send(relative).is_err() {
+                        return;
+                    }
+                }
+                known = current;
+            })?;
+        Ok(Self { changes })
+    }
+
+    /// Files created or modified since the last call, relative to the watched directory
+    /// with `/` separators.
+    pub fn changes(&self) -> Vec<String> {
+        let mut changes = Vec::<String>::new();
+        for path in self.changes.try_iter() {
+            if !changes.contains(&path) {
+                changes.push(path);
+            }
+        }
+        changes
+    }
+}
+
+// modification time of every file under `dir`, files that can't be read are skipped
+fn scan(dir: &Path) -> HashMap<PathBuf, SystemTime> {
+    let mut files = HashMap::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if metadata.is_dir() {
+                dirs.push(entry.path());
+            } else if let Ok(modified) = metadata.modified() {
+                files.insert(entry.path(), modified);
+            }
+        }
+    }
+    files
+}
+
+/// What a changed file under `res/` means for the running program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AssetChange {
+    Shader { folder: String, file_name: String },
+    Texture { folder: String, file_name: String },
+    Model { folder: String, file_name: String },
+    // every model in the folder may use it
+    MaterialLibrary { folder: String },
+}
+
+impl AssetChange {
+    /// `None` for files nothing is reloaded for, like scenes or mesh caches.
+    pub fn from_path(path: &str) -> Option<Self> {
+        let (folder, file_name) = path.rsplit_once('/').unwrap_or(("", path));
+        let (folder, file_name) = (folder.to_string(), file_name.to_string());
+        let extension = Path::new(&file_name).extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "wgsl" => Some(Self::Shader { folder, file_name }),
+            "png" | "jpg" | "jpeg" | "tga" | "bmp" => Some(Self::Texture { folder, file_name }),
+            "obj" => Some(Self::Model { folder, file_name }),
+            "mtl" => Some(Self::MaterialLibrary { folder }),
+            _ => None,
+        }
+    }
+}
+
+struct ModelReload {
+    request: LoadRequest,
+    path: String,
+    // what the file had before, to tell the scene's material overrides apart
+    old: Option<Handle<ModelAssets>>,
+}
+
+/// Reloads assets edited while the program runs, see `--hot-reload`.
+///
+/// Whatever fails to load or compile is logged and the old version stays in use.
+pub struct HotReload {
+    watcher: FileWatcher,
+    reloads: Vec<ModelReload>,
+}
+
+impl HotReload {
+    pub fn new(root: PathBuf, interval: Duration) -> std::io::Result<Self> {
+        log::info!("watching {} for changes", root.display());
+        Ok(Self {
+            watcher: FileWatcher::new(root, interval)?,
+            reloads: Vec::new(),
+        })
+    }
+
+    pub fn changes(&self) -> Vec<AssetChange> {
+        self.watcher.changes()
+            .iter()
+            .filter_map(|path| AssetChange::from_path(path))
+            .collect()
+    }
+
+    /// Reads an OBJ file again, models using it are updated by `update_models` once it finished.
+    pub fn reload_model(
+        &mut self,
+        path_to_folder_in_res: &str,
+        file_name: &str,
+        loader: &mut AssetLoader,
+        assets: &AssetManager,
+    ) {
+        let path = asset_path(path_to_folder_in_res, file_name);
+        if self.reloads.iter().any(|reload| reload.path == path) {
+            return;
+        }
+        self.reloads.push(ModelReload {
+            request: loader.request_reload(path_to_folder_in_res, file_name, assets),
+            old: assets.models.get(&path),
+            path,
+        });
+    }
+
+    /// Reloads every loaded OBJ file in a folder, since any of them may use a changed MTL file.
+    pub fn reload_folder(&mut self, path_to_folder_in_res: &str, loader: &mut AssetLoader, assets: &AssetManager) {
+        let folder = path_to_folder_in_res.trim_matches('/');
+        let files = assets.models.paths()
+            .filter_map(|path| path.rsplit_once('/'))
+            .filter(|(model_folder, _)| *model_folder == folder)
+            .map(|(_, file_name)| file_name.to_string())
+            .collect::<Vec<_>>();
+        for file_name in files {
+            self.reload_model(folder, &file_name, loader, assets);
+        }
+    }
+
+    /// Swaps reloaded files into the models using them. Materials the scene overrode
+    /// get the same overrides applied to their new version.
+    pub fn update_models(
+        &mut self,
+        loaded: &[LoadedModel],
+        models: &mut [&mut Model],
+        device: &wgpu::Device,
+        material_layout: &wgpu::BindGroupLayout,
+        assets: &mut AssetManager,
+    ) {
+        for loaded_model in loaded {
+            let Some(index) = self.reloads.iter().position(|reload| reload.request == loaded_model.request) else {
+                continue;
+            };
+            let reload = self.reloads.swap_remove(index);
+            let new_assets = match &loaded_model.result {
+                Ok(new_assets) => new_assets,
+                Err(e) => {
+                    log::error!("keeping the old {}: {:#}", reload.path, e);
+                    continue;
+                }
+            };
+
+            let users = models.iter_mut()
+                .filter(|model| !model.loading && asset_path(&model.folder, &model.file_name) == reload.path);
+            for model in users {
+                // anything the model doesn't share with the file is an override
+                let overrides = model.materials.iter()
+                    .filter(|material| reload.old.as_ref().is_some_and(|old| !old.materials.contains(material)))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                model.set_assets(new_assets);
+                for old in overrides {
+                    if let Some(material) = model.materials.iter_mut().find(|m| m.name == old.name) {
+                        *material = assets.materials.add(Material::new(
+                            device,
+                            material_layout,
+                            old.name.clone(),
+                            material.diffuse_texture.clone(),
+                            old.opacity,
+                            old.alpha_mode,
+                            old.height_map.clone(),
+                        ));
+                    }
+                }
+            }
+            log::info!("reloaded {}", reload.path);
+        }
+    }
+}
+
+/// Compiles a shader from `assets.shaders` again.
+pub fn reload_shader(path_to_folder_in_res: &str, file_name: &str, device: &wgpu::Device, assets: &mut AssetManager) {
+    let path = asset_path(path_to_folder_in_res, file_name);
+    if assets.shaders.get(&path).is_none() {
+        return;
+    }
+    let source = match load_string(path_to_folder_in_res, file_name) {
+        Ok(source) => source,
+        Err(e) => {
+            log::error!("keeping the old {}: {}", path, e);
+            return;
+        }
+    };
+    let module = try_create(device, &path, || {
+        Ok(device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(file_name),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        }))
+    });
+    if let Some(module) = module {
+        assets.shaders.insert(&path, module);
+        log::info!("reloaded {}", path);
+    }
+}
+
+/// Loads a texture from `assets.textures` again and rebuilds the materials using it,
+/// in the asset store as well as in `models`.
+pub fn reload_texture(
+    path_to_folder_in_res: &str,
+    file_name: &str,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    material_layout: &wgpu::BindGroupLayout,
+    assets: &mut AssetManager,
+    models: &mut [&mut Model],
+) {
+    // the same file may be loaded both as a color texture and as a normal map
+    for is_normal_map in [false, true] {
+        let path = texture_path(path_to_folder_in_res, file_name, is_normal_map);
+        let Some(old) = assets.textures.get(&path) else {
+            continue;
+        };
+        let texture = match load_texture(path_to_folder_in_res, file_name, device, queue, is_normal_map) {
+            Ok(texture) => texture,
+            Err(e) => {
+                log::error!("keeping the old {}: {:#}", path, e);
+                continue;
+            }
+        };
+        let new = assets.textures.insert(&path, texture);
+
+        let mut swap = TextureSwap {
+            old,
+            new,
+            device,
+            material_layout,
+            materials: HashMap::new(),
+        };
+        swap.apply(assets, models);
+        log::info!("reloaded {}, {} materials use it", path, swap.materials.len());
+    }
+}
+
+// replaces the materials using one texture, each material only once so
+// models that shared the old one share the new one
+struct TextureSwap<'a> {
+    old: Handle<Texture>,
+    new: Handle<Texture>,
+    device: &'a wgpu::Device,
+    material_layout: &'a wgpu::BindGroupLayout,
+    // new material by the id of the one it replaces
+    materials: HashMap<u64, Handle<Material>>,
+}
+
+impl TextureSwap<'_> {
+    fn apply(&mut self, assets: &mut AssetManager, models: &mut [&mut Model]) {
+        // registered materials keep their path so models loaded later get the new one
+        let registered = assets.materials.iter()
+            .filter(|(_, material)| material.uses_texture(&self.old))
+            .map(|(path, material)| (path.to_string(), material.clone()))
+            .collect::<Vec<_>>();
+        for (path, material) in registered {
+            let new_material = assets.materials.insert(&path, self.retexture(&material));
+            self.materials.insert(material.id(), new_material);
+        }
+
+        let files = assets.models.iter()
+            .filter(|(_, model_assets)| model_assets.materials.iter().any(|m| m.uses_texture(&self.old)))
+            .map(|(path, model_assets)| (path.to_string(), (**model_assets).clone()))
+            .collect::<Vec<_>>();
+        for (path, mut model_assets) in files {
+            self.swap(&mut model_assets.materials, assets);
+            assets.models.insert(&path, model_assets);
+        }
+
+        for model in models {
+            self.swap(&mut model.materials, assets);
+        }
+    }
+
+    fn swap(&mut self, materials: &mut [Handle<Material>], assets: &mut AssetManager) {
+        for material in materials {
+            if !material.uses_texture(&self.old) {
+                continue;
+            }
+            let new_material = match self.materials.get(&material.id()) {
+                Some(new_material) => new_material.clone(),
+                None => {
+                    let new_material = assets.materials.add(self.retexture(material));
+                    self.materials.insert(material.id(), new_material.clone());
+                    new_material
+                }
+            };
+            *material = new_material;
+        }
+    }
+
+    fn retexture(&self, material: &Material) -> Material {
+        material.with_texture(self.device, self.material_layout, &self.old, self.new.clone())
+    }
+}
@@ -0,0 +1,144 @@
+use std::ops::Range;
+use cgmath::{Matrix4, SquareMatrix};
+use crate::model_matrix::RawModelMatrix;
+use crate::transform::Transform;
+
+const INITIAL_CAPACITY: usize = 64;
+
+/// Handle to an instance, stays valid until the instance is removed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct InstanceId(u32);
+
+/// Many copies of one model, each with its own transform, drawn with a single call per mesh.
+/// Instance transforms are relative to the model's own model matrix (see `set_parent`).
+pub struct InstanceSet {
+    transforms: Vec<Transform>,
+    // index into `transforms` for every id handed out, None once removed
+    slots: Vec<Option<usize>>,
+    // id of each entry in `transforms`, kept in step for swap removal
+    owners: Vec<InstanceId>,
+    parent: Matrix4<f32>,
+
+    buffer: wgpu::Buffer,
+    capacity: usize,
+    // count uploaded by the last `upload`
+    uploaded: u32,
+    dirty: bool,
+}
+
+impl InstanceSet {
+    pub fn new(device: &wgpu::Device) -> Self {
+        Self {
+            transforms: Vec::new(),
+            slots: Vec::new(),
+            owners: Vec::new(),
+            parent: Matrix4::identity(),
+            buffer: create_instance_buffer(device, INITIAL_CAPACITY),
+            capacity: INITIAL_CAPACITY,
+            uploaded: 0,
+            dirty: true,
+        }
+    }
+
+    pub fn with_transforms(device: &wgpu::Device, transforms: impl IntoIterator<Item = Transform>) -> Self {
+        let mut set = Self::new(device);
+        for transform in transforms {
+            set.add(transform);
+        }
+        set
+    }
+
+    pub fn add(&mut self, transform: Transform) -> InstanceId {
+        let id = InstanceId(self.slots.len() as u32);
+        self.slots.push(Some(self.transforms.len()));
+        self.owners.push(id);
+        self.transforms.push(transform);
+        self.dirty = true;
+        id
+    }
+
+    /// Moves the last instance into the freed slot, so instance order is not stable.
+    pub fn remove(&mut self, id: InstanceId) -> Option<Transform> {
+        let index = self.slots.get_mut(id.0 as usize)?.take()?;
+        let removed = self.transforms.swap_remove(index);
+        self.owners.swap_remove(index);
+        if let Some(&moved) = self.owners.get(index) {
+            self.slots[moved.0 as usize] = Some(index);
+        }
+        self.dirty = true;
+        Some(removed)
+    }
+
+    /// Returns false if `id` was removed.
+    pub fn update(&mut self, id: InstanceId, transform: Transform) -> bool {
+        match self.index(id) {
+            Some(index) => {
+                self.transforms[index] = transform;
+                self.dirty = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn get(&self, id: InstanceId) -> Option<&Transform> {
+        self.index(id).map(|index| &self.transforms[index])
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (InstanceId, &Transform)> {
+        self.owners.iter().copied().zip(&self.transforms)
+    }
+
+    pub fn len(&self) -> usize {
+        self.transforms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transforms.is_empty()
+    }
+
+    fn index(&self, id: InstanceId) -> Option<usize> {
+        self.slots.get(id.0 as usize).copied().flatten()
+    }
+
+    /// Matrix every instance transform is multiplied with, normally the owning model's.
+    pub fn set_parent(&mut self, parent: Matrix4<f32>) {
+        self.parent = parent;
+        self.dirty = true;
+    }
+
+    /// Writes the instance matrices if anything changed, growing the buffer when needed.
+    pub fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        if !self.dirty {
+            return;
+        }
+        if self.transforms.len() > self.capacity {
+            self.capacity = self.transforms.len().next_power_of_two();
+            self.buffer = create_instance_buffer(device, self.capacity);
+        }
+
+        let raw = self.transforms.iter()
+            .map(|transform| RawModelMatrix::new(self.parent * transform.to_matrix()))
+            .collect::<Vec<_>>();
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&raw));
+        self.uploaded = raw.len() as u32;
+        self.dirty = false;
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    pub fn range(&self) -> Range<u32> {
+        0..self.uploaded
+    }
+}
+
+fn create_instance_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Instance Buffer"),
+        size: (capacity * std::mem::size_of::<RawModelMatrix>()) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    })
+}
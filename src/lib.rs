@@ -1,16 +1,45 @@
 mod graphics_context;
 mod texture;
 mod simple_pipeline;
-mod vertex;
 mod camera;
 mod constants;
-mod model;
+pub mod model;
 mod light;
 mod model_matrix;
-mod Node;
+mod node;
+mod transform;
+mod instance;
+mod bounds;
+mod simplify;
+mod lod;
+mod mesh_optimizer;
+pub mod mesh_cache;
+mod obj_import;
+mod tangents;
+mod primitives;
+mod load_report;
+mod asset_manager;
+mod asset_loader;
+pub mod vfs;
+pub mod archive;
+mod shader;
+mod hot_reload;
+mod culling;
+mod scene;
+mod scene_file;
+mod terrain;
+mod deferred;
+mod render_path;
+mod clustered;
+mod transparency;
+mod oit;
+mod cli;
+mod debug_view;
+mod debug_draw;
+mod gpu_culling;
 
-use cgmath::{Quaternion, Rotation3, Vector3};
-use wgpu::{FragmentState, include_wgsl, VertexState};
+use anyhow::Context;
+use cgmath::{SquareMatrix, Vector3};
 use winit::{
     event::*,
     event_loop::{ControlFlow, EventLoop},
@@ -18,90 +47,231 @@ use winit::{
 };
 use winit::window::Window;
 use crate::camera::{Camera, CameraController};
-use crate::constants::{HEIGHT, WIDTH};
+use crate::cli::Options;
+use crate::clustered::{ClusteredLighting, scatter_point_lights, scene_point_lights};
+use crate::constants::{CLEAR_COLOR, HEIGHT, HOT_RELOAD_POLL_MS, SCENE_SAVE_FILE, WIDTH};
+use crate::culling::{cull_models, CullStats, DrawVisible, Frustum, VisibleModel};
+use crate::debug_draw::DebugDraw;
+use crate::debug_view::{DebugRenderer, DebugView};
+use crate::deferred::DeferredRenderer;
+use crate::gpu_culling::{GpuCulling, GpuDrivenRenderer};
 use crate::graphics_context::GraphicsContext;
 use crate::light::{create_light_pipeline, DrawLight, Light};
-use crate::model::{DrawModel, load_model, Model};
-use crate::model_matrix::ModelMatrix;
+use crate::hot_reload::{source_res_dir, AssetChange, HotReload};
+use crate::model::{create_material_bind_group_layout, load_string, Model};
+use crate::node::{Node, NodeId};
+use crate::render_path::RenderPath;
+use crate::scene::Scene;
+use crate::scene_file::{PendingModels, SceneDesc};
+use crate::terrain::Terrain;
+use crate::asset_loader::{AssetLoader, LoadProgress, LoadRequest};
+use crate::asset_manager::AssetManager;
+use crate::load_report::LoadReport;
 use crate::simple_pipeline::SimplePipeline;
-use crate::vertex::Vertex;
-use crate::texture::{load_texture, Texture};
+use crate::oit::OitRenderer;
+use crate::shader::try_create;
+use crate::transparency::{DrawTransparent, sort_transparent, TransparencyMode};
+use crate::vfs::{asset_root, set_vfs, vfs, Vfs};
 
 struct State {
     ctx: GraphicsContext,
+    assets: AssetManager,
+    loader: AssetLoader,
+    // scene models still being loaded
+    pending_models: PendingModels,
+    material_layout: wgpu::BindGroupLayout,
+    // only created with --hot-reload
+    hot_reload: Option<HotReload>,
     pipeline: SimplePipeline,
-
-    vertex_buffer: wgpu::Buffer,
-    index_buffer: wgpu::Buffer,
-
-    texture: Texture,
+    transparent_pipeline: SimplePipeline,
 
     camera: Camera,
     camera_controller: CameraController,
 
-    obj_model: Model,
+    scene: Scene,
+    // only created for scenes that have one
+    terrain: Option<Terrain>,
+    // node whose light shades the scene
+    light_node: NodeId,
 
     light_model: Model,
-
-    light: Light,
+    light_model_request: Option<LoadRequest>,
     light_pipeline: wgpu::RenderPipeline,
+
+    // only created when the deferred render path was selected at startup
+    deferred: Option<DeferredRenderer>,
+    // only created when the clustered render path was selected at startup
+    clustered: Option<ClusteredLighting>,
+    // only created when weighted blended OIT was selected at startup
+    oit: Option<OitRenderer>,
+    // only created when GPU culling was selected for the forward path
+    gpu_driven: Option<GpuDrivenRenderer>,
+
+    debug: DebugRenderer,
+    debug_draw: DebugDraw,
+    // frustum culling results of the last frame, shown in the window title
+    cull_stats: CullStats,
+    // loading progress currently shown in the window title
+    load_progress: LoadProgress,
 }
 
 impl State {
     // Creating some of the wgpu types requires async code
-    async fn new(window: Window) -> Self {
+    async fn new(window: Window, options: &Options) -> anyhow::Result<Self> {
         let context = GraphicsContext::new(window).await;
 
-        let pipeline = SimplePipeline::new(
+        // the report collects problems from here on, missing shaders included
+        let mut assets = AssetManager::new();
+
+        let pipeline = required(&mut assets.report, "forward pipeline", SimplePipeline::new(
             &context.device,
             &context.config,
-        );
-
-        let vertex_buffer = Vertex::create_vertex_buffer(&context.device);
-        let index_buffer = Vertex::create_index_buffer(&context.device);
+        ))?;
 
-        let texture = load_texture(
-            "textures",
-            "default.jpg",
+        let transparent_pipeline = required(&mut assets.report, "transparent pipeline", SimplePipeline::transparent(
             &context.device,
-            &context.queue,
-            false
-        ).unwrap();
+            &context.config,
+        ))?;
+
+        let material_layout = create_material_bind_group_layout(&context.device);
+
+        // an unreadable scene leaves the scene empty
+        let scene_desc = match &options.scene {
+            Some(path) => SceneDesc::load(path),
+            None => load_string("scenes", "default.ron").and_then(|text| SceneDesc::parse(&text)),
+        }.unwrap_or_else(|e| {
+            assets.report.error(format!("failed to load the scene, starting with an empty one: {:#}", e));
+            SceneDesc::default()
+        });
+
+        let terrain = scene_desc.terrain.as_ref().and_then(|desc| {
+            match Terrain::new(&context.device, &context.queue, &context.config, desc, &mut assets) {
+                Ok(terrain) => Some(terrain),
+                Err(e) => {
+                    assets.report.error(format!("failed to load the terrain: {:#}", e));
+                    None
+                }
+            }
+        });
+        if terrain.is_some() && options.render_path == RenderPath::Deferred {
+            log::warn!("the terrain is only drawn by the forward and clustered render paths");
+        }
 
-        let camera = Camera::new(&context.device);
+        let mut camera = Camera::new(&context.device);
+        if let Some(camera_desc) = &scene_desc.camera {
+            camera_desc.apply(&mut camera);
+        }
         let camera_controller = CameraController::new();
 
-        let obj_model = load_model("models\\blob\\", "blob.obj", &context.device, &context.queue, texture.get_layout())
-                .await
-                .unwrap();
+        // models are drawn as placeholders until the loader's workers have read them
+        let mut loader = AssetLoader::new(&context.device, &context.queue, &material_layout, &mut assets)
+            .context("starting the asset loader")?;
+        let (mut scene, pending_models) = scene_desc.build_in_background(&context.device, &context.queue, &material_layout, &mut loader, &mut assets);
 
-        let light_model = load_model("models\\d20\\", "d20.obj", &context.device, &context.queue, texture.get_layout())
-                .await
-                .unwrap();
+        let light_model_request = Some(loader.request_model("models/d20/", "d20.obj", &assets));
+        let light_model = loader.placeholder(&context.device, "models/d20/", "d20.obj");
 
-        let light = Light::new(
-            &context.device,
-            Vector3::new(5.0, 0.0, 0.0),
-            Vector3::new(0.4, 0.8, 0.6));
+        // the shaders always need a light, scenes without one get a default
+        let light_node = scene.first_light().unwrap_or_else(|| {
+            let light = Light::new(
+                &context.device,
+                Vector3::new(0.0, 0.0, 0.0),
+                Vector3::new(1.0, 1.0, 1.0));
+            let mut node = Node::new("default light".to_string()).with_light(light);
+            node.set_translation(Vector3::new(5.0, 0.0, 0.0));
+            scene.add(node, None)
+        });
 
-        let light_pipeline = create_light_pipeline(
+        let light_pipeline = required(&mut assets.report, "light pipeline", create_light_pipeline(
             &context.device,
             &context.config,
-        );
+        ))?;
+
+        let deferred = optional(&mut assets.report, "deferred render path", match options.render_path {
+            RenderPath::Deferred => Some(DeferredRenderer::new(
+                &context.device,
+                &context.config,
+                &context.depth_texture,
+            )),
+            _ => None,
+        });
+
+        let clustered = optional(&mut assets.report, "clustered render path", match options.render_path {
+            RenderPath::Clustered => Some(ClusteredLighting::new(
+                &context.device,
+                &context.config,
+                &camera,
+                scatter_point_lights(options.stress_lights),
+            )),
+            _ => None,
+        });
+        if options.stress_lights > 0 && clustered.is_none() {
+            log::warn!("stress lights are only shaded by the clustered render path");
+        }
+
+        let oit = optional(&mut assets.report, "weighted blended transparency", match options.transparency {
+            TransparencyMode::WeightedBlended => Some(OitRenderer::new(&context.device, &context.config)),
+            TransparencyMode::Sorted => None,
+        });
+
+        let gpu_driven = optional(&mut assets.report, "GPU culling", match (options.gpu_culling, options.render_path) {
+            (GpuCulling::Off, _) => None,
+            (mode, RenderPath::Forward) => Some(GpuDrivenRenderer::new(
+                &context.device,
+                &context.config,
+                &context.depth_texture,
+                mode,
+            )),
+            (_, render_path) => {
+                log::warn!("GPU culling is not supported by the {:?} render path", render_path);
+                None
+            }
+        });
 
-        Self {
+        let hot_reload = match (options.hot_reload, vfs().dir()) {
+            (true, Some(dir)) => match HotReload::new(dir.to_path_buf(), std::time::Duration::from_millis(HOT_RELOAD_POLL_MS)) {
+                Ok(hot_reload) => Some(hot_reload),
+                Err(e) => {
+                    assets.report.error(format!("hot reload is off, can't watch {}: {}", dir.display(), e));
+                    None
+                }
+            },
+            (true, None) => {
+                log::warn!("hot reload needs the assets in a directory");
+                None
+            }
+            (false, _) => None,
+        };
+
+        let debug = required(&mut assets.report, "debug views", DebugRenderer::new(&context.device, &context.config, &camera))?;
+        let debug_draw = required(&mut assets.report, "debug lines", DebugDraw::new(&context.device, &context.config))?;
+
+        Ok(Self {
             ctx: context,
+            assets,
+            loader,
+            pending_models,
+            material_layout,
+            hot_reload,
             pipeline,
-            vertex_buffer,
-            index_buffer,
-            texture,
+            transparent_pipeline,
             camera,
             camera_controller,
-            obj_model,
+            scene,
+            terrain,
+            light_node,
             light_model,
-            light,
+            light_model_request,
             light_pipeline,
-        }
+            deferred,
+            clustered,
+            oit,
+            gpu_driven,
+            debug,
+            debug_draw,
+            cull_stats: CullStats::default(),
+            load_progress: LoadProgress::default(),
+        })
     }
 
     pub fn window(&self) -> &Window {
@@ -118,16 +288,178 @@ impl State {
             let new_depth = texture::Texture::create_depth_texture(&self.ctx.device, &self.ctx.config, "depth texture");
             self.ctx.depth_texture = new_depth;
 
+            if let Some(deferred) = &mut self.deferred {
+                deferred.resize(&self.ctx.device, &self.ctx.config, &self.ctx.depth_texture);
+            }
+            if let Some(oit) = &mut self.oit {
+                oit.resize(&self.ctx.device, &self.ctx.config);
+            }
+            if let Some(gpu_driven) = &mut self.gpu_driven {
+                gpu_driven.resize(&self.ctx.device, &self.ctx.config, &self.ctx.depth_texture);
+            }
+
             self.camera.aspect = new_size.width as f32 / new_size.height as f32;
             self.camera.update_view_proj(&self.ctx.device);
         }
     }
 
     fn input(&mut self, event: &WindowEvent) -> bool {
-        self.camera_controller.process_events(event)
+        if let WindowEvent::KeyboardInput {
+            input: KeyboardInput {
+                state: ElementState::Pressed,
+                virtual_keycode: Some(key @ (VirtualKeyCode::F5 | VirtualKeyCode::F6)),
+                ..
+            },
+            ..
+        } = event {
+            match key {
+                VirtualKeyCode::F5 => self.save_scene(),
+                _ => {
+                    log::info!("unloaded {} unused assets", self.assets.unload_unused());
+                    self.assets.log_stats();
+                }
+            }
+            return true;
+        }
+
+        self.debug.process_events(event)
+            || self.debug_draw.process_events(event)
+            || self.camera_controller.process_events(event)
+    }
+
+    fn update_loading(&mut self) {
+        let loaded = self.loader.update(&self.ctx.device, &self.ctx.queue, &self.material_layout, &mut self.assets);
+        if loaded.is_empty() {
+            return;
+        }
+        for loaded_model in &loaded {
+            if Some(loaded_model.request) != self.light_model_request {
+                continue;
+            }
+            match &loaded_model.result {
+                Ok(model_assets) => self.light_model.set_assets(model_assets),
+                Err(e) => self.assets.report.error(format!("failed to load the light model: {:#}", e)),
+            }
+            self.light_model_request = None;
+        }
+        self.pending_models.update(
+            &loaded,
+            &mut self.scene,
+            &self.ctx.device,
+            &self.ctx.queue,
+            &self.material_layout,
+            &mut self.assets,
+        );
+        if let Some(hot_reload) = &mut self.hot_reload {
+            let mut models = self.scene.models_mut();
+            models.push(&mut self.light_model);
+            hot_reload.update_models(&loaded, &mut models, &self.ctx.device, &self.material_layout, &mut self.assets);
+        }
+
+        let progress = self.loader.progress();
+        log::info!("loaded {} of {} models", progress.finished, progress.requested);
+        if progress.is_done() {
+            self.assets.log_stats();
+            self.assets.report.log_summary();
+        }
+    }
+
+    fn update_hot_reload(&mut self) {
+        let Some(changes) = self.hot_reload.as_ref().map(HotReload::changes) else {
+            return;
+        };
+        for change in changes {
+            match change {
+                AssetChange::Shader { folder, file_name } => {
+                    if folder == "shaders" {
+                        self.reload_pipelines(&file_name);
+                    }
+                    hot_reload::reload_shader(&folder, &file_name, &self.ctx.device, &mut self.assets);
+                }
+                AssetChange::Texture { folder, file_name } => {
+                    let mut models = self.scene.models_mut();
+                    models.push(&mut self.light_model);
+                    hot_reload::reload_texture(
+                        &folder,
+                        &file_name,
+                        &self.ctx.device,
+                        &self.ctx.queue,
+                        &self.material_layout,
+                        &mut self.assets,
+                        &mut models,
+                    );
+                }
+                AssetChange::Model { folder, file_name } => {
+                    if let Some(hot_reload) = &mut self.hot_reload {
+                        hot_reload.reload_model(&folder, &file_name, &mut self.loader, &self.assets);
+                    }
+                }
+                AssetChange::MaterialLibrary { folder } => {
+                    if let Some(hot_reload) = &mut self.hot_reload {
+                        hot_reload.reload_folder(&folder, &mut self.loader, &self.assets);
+                    }
+                }
+            }
+        }
+    }
+
+    // rebuilds every pipeline created from `res/shaders/<file_name>`
+    fn reload_pipelines(&mut self, file_name: &str) {
+        let device = &self.ctx.device;
+        let config = &self.ctx.config;
+        let uses = |shaders: &[&str]| shaders.contains(&file_name);
+        log::info!("reloading pipelines using {}", file_name);
+
+        if uses(SimplePipeline::SHADERS) {
+            let pipelines = try_create(device, "forward pipelines", || {
+                Ok((SimplePipeline::new(device, config)?, SimplePipeline::transparent(device, config)?))
+            });
+            if let Some((pipeline, transparent_pipeline)) = pipelines {
+                self.pipeline = pipeline;
+                self.transparent_pipeline = transparent_pipeline;
+            }
+        }
+        if file_name == "light.wgsl" {
+            if let Some(light_pipeline) = try_create(device, "light pipeline", || create_light_pipeline(device, config)) {
+                self.light_pipeline = light_pipeline;
+            }
+        }
+        if let Some(deferred) = self.deferred.as_mut().filter(|_| uses(DeferredRenderer::SHADERS)) {
+            deferred.reload_pipelines(device, config);
+        }
+        if let Some(clustered) = self.clustered.as_mut().filter(|_| uses(ClusteredLighting::SHADERS)) {
+            clustered.reload_pipelines(device, config);
+        }
+        if let Some(oit) = self.oit.as_mut().filter(|_| uses(OitRenderer::SHADERS)) {
+            oit.reload_pipelines(device, config);
+        }
+        if let Some(gpu_driven) = self.gpu_driven.as_mut().filter(|_| uses(GpuDrivenRenderer::SHADERS)) {
+            gpu_driven.reload_pipelines(device);
+        }
+        if uses(DebugRenderer::SHADERS) {
+            self.debug.reload_pipelines(device, config);
+        }
+        if uses(DebugDraw::SHADERS) {
+            self.debug_draw.reload_pipelines(device, config);
+        }
+        if let Some(terrain) = self.terrain.as_mut().filter(|_| uses(Terrain::SHADERS)) {
+            terrain.reload_pipelines(device, config);
+        }
+    }
+
+    fn save_scene(&self) {
+        let path = std::path::Path::new(SCENE_SAVE_FILE);
+        let mut scene_desc = SceneDesc::from_scene(&self.scene, &self.camera);
+        scene_desc.terrain = self.terrain.as_ref().map(|terrain| terrain.desc.clone());
+        match scene_desc.save(path) {
+            Ok(()) => log::info!("saved scene to {}", path.display()),
+            Err(e) => log::error!("{:?}", e),
+        }
     }
 
     fn update(&mut self) {
+        self.update_hot_reload();
+        self.update_loading();
         self.camera_controller.update_camera(&mut self.camera);
         self.camera.update_view_proj(&self.ctx.device);
         self.ctx.queue.write_buffer(
@@ -135,25 +467,54 @@ impl State {
             0,
             bytemuck::cast_slice(&[self.camera.uniform]),
         );
-        let pos: Vector3<_> = self.light.uniform.position.into();
-        self.light.uniform.position = (cgmath::Quaternion::from_axis_angle(
-            cgmath::Vector3::unit_y(),
-            cgmath::Deg(1.0)
-        ) * pos).into();
+        self.scene.animate();
+        self.scene.update(&self.ctx.device, &self.ctx.queue);
+        self.scene.update_lods(&self.camera);
+        if let Some(terrain) = &mut self.terrain {
+            terrain.update_lods(&self.camera);
+        }
 
-        self.ctx.queue.write_buffer(
-            &self.light.buffer,
-            0,
-            bytemuck::cast_slice(&[self.light.uniform]),
-        );
+        if let Some(clustered) = &mut self.clustered {
+            clustered.set_scene_lights(&self.ctx.queue, scene_point_lights(&self.scene, self.light_node));
+            clustered.update(&self.ctx.queue, &self.ctx.config, &self.camera);
+        }
 
-        let rotation = Quaternion::from_axis_angle(Vector3::unit_y(), cgmath::Deg(0.2));
-        self.obj_model.rotate_world(rotation);
-        self.ctx.queue.write_buffer(
-            &self.obj_model.model_matrix.buffer,
-            0,
-            bytemuck::cast_slice(&[self.obj_model.model_matrix.to_raw()]),
-        );
+        self.debug.update(&self.ctx.queue, &self.camera);
+
+        if self.debug_draw.enabled {
+            self.debug_draw.grid(cgmath::Point3::new(0.0, -1.0, 0.0), 20, 1.0, debug_draw::GREY);
+            self.debug_draw.axes(cgmath::Matrix4::identity(), 1.0);
+            let debug_draw = &mut self.debug_draw;
+            self.scene.traverse(|_, node| {
+                let Some(model) = &node.model else {
+                    return;
+                };
+                debug_draw.axes(node.world_matrix(), 0.5);
+                // culling bounds, instanced models are never culled on the CPU
+                if model.instances.is_none() {
+                    let world = model.model_matrix.matrix();
+                    for mesh in &model.meshes {
+                        let bounds = mesh.geometry.bounds.transform(&world);
+                        // colored by level of detail, white is full detail
+                        let color = [debug_draw::WHITE, debug_draw::GREEN, debug_draw::YELLOW, debug_draw::RED];
+                        debug_draw.aabb(bounds.min, bounds.max, color[mesh.lod.min(color.len() - 1)]);
+                    }
+                }
+            });
+            if let Some(terrain) = &self.terrain {
+                for chunk in terrain.chunks() {
+                    let color = [debug_draw::WHITE, debug_draw::GREEN, debug_draw::YELLOW, debug_draw::RED];
+                    self.debug_draw.aabb(chunk.bounds.min, chunk.bounds.max, color[chunk.lod.min(color.len() - 1)]);
+                }
+            }
+            self.debug_draw.set_depth_test(false);
+            self.debug_draw.sphere(self.light().uniform.position.into(), 0.3, debug_draw::YELLOW);
+        }
+        self.debug_draw.prepare(&self.ctx.device, &self.ctx.queue);
+    }
+
+    fn light(&self) -> &Light {
+        self.scene.node(self.light_node).light.as_ref().expect("light node lost its light")
     }
 
     fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
@@ -166,27 +527,351 @@ impl State {
             }
         );
 
-        {
+        let frustum = Frustum::from_view_projection(self.camera.build_view_projection_matrix());
+        let mut cull_stats = CullStats::default();
+        let visible = cull_models(self.scene.models(), &frustum, &mut cull_stats);
+
+        if let Some(gpu_driven) = &mut self.gpu_driven {
+            gpu_driven.prepare(
+                &self.ctx.device,
+                &self.ctx.queue,
+                &mut encoder,
+                &self.scene.models(),
+                &self.camera,
+                &self.ctx.config,
+            );
+        }
+
+        if self.debug.view != DebugView::Lit {
+            self.render_debug_view(&mut encoder, &view);
+        } else {
+            if let Some(deferred) = &self.deferred {
+                self.render_deferred(&mut encoder, &view, deferred, &visible);
+            } else if let Some(clustered) = &self.clustered {
+                clustered.assign_lights(&mut encoder);
+                self.render_forward(&mut encoder, &view, &frustum, &visible, Some(clustered));
+            } else {
+                self.render_forward(&mut encoder, &view, &frustum, &visible, None);
+                if let Some(gpu_driven) = &mut self.gpu_driven {
+                    // depth for the occlusion test of the next frame
+                    gpu_driven.build_hiz(&mut encoder);
+                }
+            }
+            self.render_transparent(&mut encoder, &view, &visible);
+        }
+
+        if self.debug.wireframe {
+            self.render_wireframe(&mut encoder, &view);
+        }
+        self.render_debug_lines(&mut encoder, &view);
+
+        self.ctx.queue.submit(std::iter::once(encoder.finish()));
+        out.present();
+
+        // CPU culling only decides what the transparent pass draws then
+        if let Some(gpu_driven) = &mut self.gpu_driven {
+            gpu_driven.request_stats();
+            cull_stats = gpu_driven.stats(&self.ctx.device).unwrap_or_default();
+        }
+        let load_progress = self.loader.progress();
+        if cull_stats != self.cull_stats || load_progress != self.load_progress {
+            self.cull_stats = cull_stats;
+            self.load_progress = load_progress;
+            let mut title = format!("culled {} of {} meshes", cull_stats.culled, cull_stats.tested);
+            if !load_progress.is_done() {
+                title = format!("loading {} of {} models, {}", load_progress.finished, load_progress.requested, title);
+            }
+            if !self.assets.report.is_empty() {
+                title = format!("{}, {} asset problems (see log)", title, self.assets.report.len());
+            }
+            self.window().set_title(&title);
+        }
+        Ok(())
+    }
+
+    fn render_forward(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        frustum: &Frustum,
+        visible: &[VisibleModel],
+        clustered: Option<&ClusteredLighting>,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(
+            &wgpu::RenderPassDescriptor {
+                label: Some("render pass"),
+                color_attachments: &[Some(
+                    wgpu::RenderPassColorAttachment {
+                        view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(CLEAR_COLOR),
+                            store: true,
+                        },
+                    }
+                )],
+                depth_stencil_attachment: Some(
+                    wgpu::RenderPassDepthStencilAttachment {
+                        view: &self.ctx.depth_texture.view,
+                        depth_ops: Some(
+                            wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(1.0),
+                                store: true,
+                            }
+                        ),
+                        stencil_ops: None,
+                    }
+                )
+            }
+        );
+
+        // all rendering things come here:
+
+
+        render_pass.set_pipeline(&self.light_pipeline);
+        render_pass.draw_light_model(
+            &self.light_model,
+            &self.camera.bind_group,
+            &self.light().bind_group,
+        );
+
+        match clustered {
+            Some(clustered) => {
+                render_pass.set_pipeline(&clustered.render_pipeline);
+                render_pass.set_bind_group(3, &clustered.render_bind_group, &[]);
+            }
+            None => render_pass.set_pipeline(&self.pipeline.render_pipeline),
+        }
+        match &self.gpu_driven {
+            Some(gpu_driven) => gpu_driven.draw(
+                &mut render_pass,
+                &self.scene.models(),
+                &self.camera.bind_group,
+                &self.light().bind_group),
+            None => render_pass.draw_visible(
+                visible,
+                &self.camera.bind_group,
+                &self.light().bind_group),
+        }
+
+        if let Some(terrain) = &self.terrain {
+            terrain.draw(&mut render_pass, frustum, &self.camera.bind_group, &self.light().bind_group);
+        }
+    }
+
+    fn render_debug_view(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        let mut render_pass = encoder.begin_render_pass(
+            &wgpu::RenderPassDescriptor {
+                label: Some("debug view pass"),
+                color_attachments: &[Some(
+                    wgpu::RenderPassColorAttachment {
+                        view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                            store: true,
+                        },
+                    }
+                )],
+                depth_stencil_attachment: Some(
+                    wgpu::RenderPassDepthStencilAttachment {
+                        view: &self.ctx.depth_texture.view,
+                        depth_ops: Some(
+                            wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(1.0),
+                                store: true,
+                            }
+                        ),
+                        stencil_ops: None,
+                    }
+                )
+            }
+        );
+
+        self.debug.draw_models(&mut render_pass, &self.scene.models(), &self.camera.bind_group);
+    }
+
+    fn render_debug_lines(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        let mut render_pass = encoder.begin_render_pass(
+            &wgpu::RenderPassDescriptor {
+                label: Some("debug line pass"),
+                color_attachments: &[Some(
+                    wgpu::RenderPassColorAttachment {
+                        view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Load,
+                            store: true,
+                        },
+                    }
+                )],
+                depth_stencil_attachment: Some(
+                    wgpu::RenderPassDepthStencilAttachment {
+                        view: &self.ctx.depth_texture.view,
+                        depth_ops: Some(
+                            wgpu::Operations {
+                                load: wgpu::LoadOp::Load,
+                                store: true,
+                            }
+                        ),
+                        stencil_ops: None,
+                    }
+                )
+            }
+        );
+
+        self.debug_draw.draw(&mut render_pass, &self.camera.bind_group);
+    }
+
+    fn render_wireframe(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        let models = self.scene.models();
+        let mesh_bind_groups = self.debug.wireframe_bind_groups(&self.ctx.device, &models);
+
+        let mut render_pass = encoder.begin_render_pass(
+            &wgpu::RenderPassDescriptor {
+                label: Some("wireframe pass"),
+                color_attachments: &[Some(
+                    wgpu::RenderPassColorAttachment {
+                        view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Load,
+                            store: true,
+                        },
+                    }
+                )],
+                depth_stencil_attachment: Some(
+                    wgpu::RenderPassDepthStencilAttachment {
+                        view: &self.ctx.depth_texture.view,
+                        depth_ops: Some(
+                            wgpu::Operations {
+                                load: wgpu::LoadOp::Load,
+                                store: true,
+                            }
+                        ),
+                        stencil_ops: None,
+                    }
+                )
+            }
+        );
+
+        self.debug.draw_wireframe(&mut render_pass, &models, &self.camera.bind_group, &mesh_bind_groups);
+    }
+
+    // blended meshes go last, sorted back to front over the finished opaque image
+    fn render_transparent(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        visible: &[VisibleModel],
+    ) {
+        let draws = sort_transparent(visible, self.camera.view_matrix());
+        if draws.is_empty() {
+            return;
+        }
+
+        if let Some(oit) = &self.oit {
+            // order independent: accumulate every fragment then resolve once
+            {
+                let mut render_pass = encoder.begin_render_pass(
+                    &wgpu::RenderPassDescriptor {
+                        label: Some("oit accumulate pass"),
+                        color_attachments: &oit.targets.color_attachments(),
+                        depth_stencil_attachment: Some(
+                            wgpu::RenderPassDepthStencilAttachment {
+                                view: &self.ctx.depth_texture.view,
+                                depth_ops: Some(
+                                    wgpu::Operations {
+                                        load: wgpu::LoadOp::Load,
+                                        store: true,
+                                    }
+                                ),
+                                stencil_ops: None,
+                            }
+                        )
+                    }
+                );
+
+                render_pass.set_pipeline(&oit.accumulate_pipeline);
+                render_pass.draw_transparent(
+                    &draws,
+                    &self.camera.bind_group,
+                    &self.light().bind_group,
+                );
+            }
+
             let mut render_pass = encoder.begin_render_pass(
                 &wgpu::RenderPassDescriptor {
-                    label: Some("render pass"),
+                    label: Some("oit composite pass"),
                     color_attachments: &[Some(
                         wgpu::RenderPassColorAttachment {
-                            view: &view,
+                            view,
                             resolve_target: None,
                             ops: wgpu::Operations {
-                                load: wgpu::LoadOp::Clear(
-                                    wgpu::Color {
-                                        r: 0.1,
-                                        g: 0.5,
-                                        b: 0.9,
-                                        a: 1.0,
-                                    }
-                                ),
+                                load: wgpu::LoadOp::Load,
                                 store: true,
                             },
                         }
                     )],
+                    depth_stencil_attachment: None,
+                }
+            );
+
+            render_pass.set_pipeline(&oit.composite_pipeline);
+            render_pass.set_bind_group(0, &oit.targets.bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+            return;
+        }
+
+        let mut render_pass = encoder.begin_render_pass(
+            &wgpu::RenderPassDescriptor {
+                label: Some("transparent pass"),
+                color_attachments: &[Some(
+                    wgpu::RenderPassColorAttachment {
+                        view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Load,
+                            store: true,
+                        },
+                    }
+                )],
+                depth_stencil_attachment: Some(
+                    wgpu::RenderPassDepthStencilAttachment {
+                        view: &self.ctx.depth_texture.view,
+                        depth_ops: Some(
+                            wgpu::Operations {
+                                load: wgpu::LoadOp::Load,
+                                store: true,
+                            }
+                        ),
+                        stencil_ops: None,
+                    }
+                )
+            }
+        );
+
+        render_pass.set_pipeline(&self.transparent_pipeline.render_pipeline);
+        render_pass.draw_transparent(
+            &draws,
+            &self.camera.bind_group,
+            &self.light().bind_group,
+        );
+    }
+
+    fn render_deferred(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        deferred: &DeferredRenderer,
+        visible: &[VisibleModel],
+    ) {
+        // geometry pass: fill the G-buffer and depth
+        {
+            let mut render_pass = encoder.begin_render_pass(
+                &wgpu::RenderPassDescriptor {
+                    label: Some("gbuffer pass"),
+                    color_attachments: &deferred.gbuffer.color_attachments(),
                     depth_stencil_attachment: Some(
                         wgpu::RenderPassDepthStencilAttachment {
                             view: &self.ctx.depth_texture.view,
@@ -202,37 +887,121 @@ impl State {
                 }
             );
 
-            // all rendering things come here:
+            render_pass.set_pipeline(&deferred.pipeline.geometry_pipeline);
+            render_pass.draw_visible(
+                visible,
+                &self.camera.bind_group,
+                &self.light().bind_group);
+        }
+
+        // lighting pass: one fullscreen triangle shading every G-buffer pixel
+        {
+            let mut render_pass = encoder.begin_render_pass(
+                &wgpu::RenderPassDescriptor {
+                    label: Some("deferred lighting pass"),
+                    color_attachments: &[Some(
+                        wgpu::RenderPassColorAttachment {
+                            view,
+                            resolve_target: None,
+                            ops: wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(CLEAR_COLOR),
+                                store: true,
+                            },
+                        }
+                    )],
+                    depth_stencil_attachment: None,
+                }
+            );
+
+            render_pass.set_pipeline(&deferred.pipeline.lighting_pipeline);
+            render_pass.set_bind_group(0, &deferred.gbuffer.bind_group, &[]);
+            render_pass.set_bind_group(1, &self.camera.bind_group, &[]);
+            render_pass.set_bind_group(2, &self.light().bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
 
+        // unlit light markers are drawn forward on top, tested against the G-buffer depth
+        {
+            let mut render_pass = encoder.begin_render_pass(
+                &wgpu::RenderPassDescriptor {
+                    label: Some("light marker pass"),
+                    color_attachments: &[Some(
+                        wgpu::RenderPassColorAttachment {
+                            view,
+                            resolve_target: None,
+                            ops: wgpu::Operations {
+                                load: wgpu::LoadOp::Load,
+                                store: true,
+                            },
+                        }
+                    )],
+                    depth_stencil_attachment: Some(
+                        wgpu::RenderPassDepthStencilAttachment {
+                            view: &self.ctx.depth_texture.view,
+                            depth_ops: Some(
+                                wgpu::Operations {
+                                    load: wgpu::LoadOp::Load,
+                                    store: true,
+                                }
+                            ),
+                            stencil_ops: None,
+                        }
+                    )
+                }
+            );
 
             render_pass.set_pipeline(&self.light_pipeline);
             render_pass.draw_light_model(
                 &self.light_model,
                 &self.camera.bind_group,
-                &self.light.bind_group,
+                &self.light().bind_group,
             );
+        }
+    }
+}
 
-            render_pass.set_pipeline(&self.pipeline.render_pipeline);
-            render_pass.set_vertex_buffer(1, self.obj_model.model_matrix.buffer.slice(..));
-            render_pass.draw_model(
-                &self.obj_model,
-                &self.camera.bind_group,
-            &self.light.bind_group);
+// for the pipelines nothing can be drawn without, ends the start with the report so far
+fn required<T>(report: &mut LoadReport, what: &str, result: anyhow::Result<T>) -> anyhow::Result<T> {
+    result.map_err(|e| {
+        report.error(format!("failed to create the {}: {:#}", what, e));
+        report.log_summary();
+        anyhow::anyhow!("can't start without the {}", what)
+    })
+}
 
+// for the optional renderers, the forward path, sorted transparency and CPU culling stand in
+fn optional<T>(report: &mut LoadReport, what: &str, result: Option<anyhow::Result<T>>) -> Option<T> {
+    match result? {
+        Ok(value) => Some(value),
+        Err(e) => {
+            report.error(format!("the {} is off: {:#}", what, e));
+            None
         }
-        self.ctx.queue.submit(std::iter::once(encoder.finish()));
-        out.present();
-        Ok(())
     }
 }
 
 pub async fn run() {
     env_logger::init();
+    let options = Options::from_args(std::env::args());
+    let root = match (&options.assets, options.hot_reload) {
+        // edits to the source tree show up without a rebuild copying them
+        (None, true) => source_res_dir(),
+        (dir, _) => asset_root(dir.as_deref()),
+    };
+    if let Err(e) = set_vfs(Vfs::open(&root)) {
+        log::error!("{:#}", e);
+    }
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new().build(&event_loop).unwrap();
     window.set_inner_size(winit::dpi::LogicalSize::new(WIDTH, HEIGHT));
 
-    let mut state = State::new(window).await;
+    let mut state = match State::new(window, &options).await {
+        Ok(state) => state,
+        Err(e) => {
+            log::error!("{:#}", e);
+            return;
+        }
+    };
 
     event_loop.run(move |event, _, control_flow| {
         match event {
@@ -255,7 +1024,7 @@ pub async fn run() {
             Event::WindowEvent {
                 ref event,
                 window_id,
-            } if window_id == state.window().id() => if !state.input(event) {
+            } if window_id == state.window().id() && !state.input(event) => {
                 match event {
                     WindowEvent::CloseRequested | WindowEvent::KeyboardInput {
                         input:
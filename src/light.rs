@@ -3,6 +3,7 @@ use wgpu::util::DeviceExt;
 use crate::camera::create_camera_bind_group_layout;
 use crate::model;
 use crate::model::{Mesh, Model, Vertex};
+use crate::shader::create_shader_module;
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
@@ -90,10 +91,8 @@ pub fn create_light_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupL
 pub fn create_light_pipeline(
     device: &wgpu::Device,
     config: &wgpu::SurfaceConfiguration,
-        ) -> wgpu::RenderPipeline {
-    let shader = device.create_shader_module(
-        wgpu::include_wgsl!("../res/shaders/light.wgsl")
-    );
+        ) -> anyhow::Result<wgpu::RenderPipeline> {
+    let shader = create_shader_module(device, "light.wgsl")?;
 
     let layouts = &[
         &create_camera_bind_group_layout(device),
@@ -110,7 +109,7 @@ pub fn create_light_pipeline(
 
     let vertex_layouts = &[model::ModelVertex::desc()];
 
-    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+    Ok(device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
         label: Some("light Render Pipeline"),
         layout: Some(&layout),
         vertex: wgpu::VertexState {
@@ -152,10 +151,11 @@ pub fn create_light_pipeline(
             alpha_to_coverage_enabled: false,
         },
         multiview: None,
-    })
+    }))
 }
 
 pub trait DrawLight<'a> {
+    #[allow(dead_code)]
     fn draw_light_mesh(
         &mut self,
         mesh: &'a Mesh,
@@ -205,11 +205,11 @@ impl<'a, 'b> DrawLight<'b> for wgpu::RenderPass<'a>
         camera_bind_group: &'b wgpu::BindGroup,
         light_bind_group: &'b wgpu::BindGroup,
     ) {
-        self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
-        self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        self.set_vertex_buffer(0, mesh.geometry.vertex_buffer.slice(..));
+        self.set_index_buffer(mesh.geometry.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
         self.set_bind_group(0, camera_bind_group, &[]);
         self.set_bind_group(1, light_bind_group, &[]);
-        self.draw_indexed(0..mesh.num_elements, 0, instances);
+        self.draw_indexed(0..mesh.geometry.num_elements, 0, instances);
     }
 
     fn draw_light_model(
@@ -0,0 +1,55 @@
+/// Problems met while loading assets. Warnings were worked around, like a texture
+/// replaced by the missing texture, errors left something out, like a model that
+/// kept its placeholder.
+#[derive(Debug, Default)]
+pub struct LoadReport {
+    warnings: Vec<String>,
+    errors: Vec<String>,
+}
+
+impl LoadReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn warn(&mut self, message: String) {
+        log::warn!("{}", message);
+        self.warnings.push(message);
+    }
+
+    pub fn error(&mut self, message: String) {
+        log::error!("{}", message);
+        self.errors.push(message);
+    }
+
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    pub fn errors(&self) -> &[String] {
+        &self.errors
+    }
+
+    pub fn len(&self) -> usize {
+        self.warnings.len() + self.errors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Repeats every problem so far in one place, they are easy to miss between the other logs.
+    pub fn log_summary(&self) {
+        if self.is_empty() {
+            log::info!("assets loaded without problems");
+            return;
+        }
+        log::warn!("assets loaded with {} errors and {} warnings", self.errors.len(), self.warnings.len());
+        for error in &self.errors {
+            log::warn!("  error: {}", error);
+        }
+        for warning in &self.warnings {
+            log::warn!("  warning: {}", warning);
+        }
+    }
+}
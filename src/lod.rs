@@ -0,0 +1,100 @@
+use cgmath::InnerSpace;
+use crate::bounds::BoundingSphere;
+use crate::camera::Camera;
+use crate::mesh_optimizer::optimize_vertex_cache;
+use crate::simplify::simplify;
+
+// triangle count of every generated level relative to the full mesh,
+// and the screen size below which it replaces the finer one
+const LOD_LEVELS: [(f32, f32); 3] = [(0.5, 0.4), (0.25, 0.2), (0.125, 0.1)];
+// most coarser levels a mesh gets
+pub const MAX_LODS: usize = LOD_LEVELS.len();
+// meshes with fewer triangles are cheap enough as they are
+const MIN_LOD_TRIANGLES: usize = 256;
+// a level that doesn't get at least this much smaller than the previous one is dropped
+const MIN_LOD_REDUCTION: f32 = 0.8;
+// switching needs the screen size to cross a threshold by this fraction of it
+const HYSTERESIS: f32 = 0.15;
+
+/// A coarser index buffer over the vertices of a `Mesh`.
+pub struct MeshLod {
+    pub index_buffer: wgpu::Buffer,
+    pub num_elements: u32,
+    // used once the mesh covers less than this fraction of the screen height
+    pub screen_size: f32,
+    // largest model space distance the surface moved while simplifying
+    pub error: f32,
+}
+
+/// CPU side of a `MeshLod`.
+pub struct LodData {
+    pub indices: Vec<u32>,
+    pub screen_size: f32,
+    pub error: f32,
+}
+
+/// Simplifies the mesh once per level in `LOD_LEVELS`, each from the previous one.
+/// `optimize` reorders every level's triangles for the vertex cache.
+pub fn generate_lods(
+    name: &str,
+    positions: &[[f32; 3]],
+    indices: &[u32],
+    optimize: bool,
+) -> Vec<LodData> {
+    let mut lods = Vec::new();
+    if indices.len() / 3 < MIN_LOD_TRIANGLES {
+        return lods;
+    }
+
+    let mut previous = indices.to_vec();
+    for (ratio, screen_size) in LOD_LEVELS {
+        let target = (indices.len() as f32 * ratio) as usize / 3 * 3;
+        let simplified = simplify(positions, &previous, target);
+        if simplified.indices.len() as f32 > previous.len() as f32 * MIN_LOD_REDUCTION {
+            break;
+        }
+
+        let level_indices = if optimize {
+            optimize_vertex_cache(&simplified.indices, positions.len())
+        } else {
+            simplified.indices.clone()
+        };
+        let error = simplified.error + lods.last().map_or(0.0, |lod: &LodData| lod.error);
+        lods.push(LodData {
+            indices: level_indices,
+            screen_size,
+            error,
+        });
+        previous = simplified.indices;
+    }
+    log::debug!(
+        "{}: {} triangles, levels {:?}",
+        name,
+        indices.len() / 3,
+        lods.iter().map(|lod| lod.indices.len() / 3).collect::<Vec<_>>(),
+    );
+    lods
+}
+
+/// Height of the world space `sphere` on screen as a fraction of the viewport height.
+pub fn screen_size(sphere: &BoundingSphere, camera: &Camera) -> f32 {
+    let distance = (sphere.center - camera.position()).magnitude();
+    if distance <= sphere.radius {
+        return f32::INFINITY;
+    }
+    let half_height = distance * (camera.fovy * 0.5).to_radians().tan();
+    sphere.radius / half_height
+}
+
+/// Level to draw at `screen_size`, moving from `current` only once a threshold is
+/// crossed by more than the hysteresis band so meshes near it don't pop back and forth.
+pub fn select_lod(lods: &[MeshLod], current: usize, screen_size: f32) -> usize {
+    let mut lod = current.min(lods.len());
+    while lod < lods.len() && screen_size < lods[lod].screen_size * (1.0 - HYSTERESIS) {
+        lod += 1;
+    }
+    while lod > 0 && screen_size > lods[lod - 1].screen_size * (1.0 + HYSTERESIS) {
+        lod -= 1;
+    }
+    lod
+}
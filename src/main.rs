@@ -1,10 +1,3 @@
-use wgpu::CompositeAlphaMode;
-use winit::{
-    event::*,
-    event_loop::{ControlFlow, EventLoop},
-    window::WindowBuilder,
-};
-
 fn main() {
     pollster::block_on(lib::run());
 }
@@ -0,0 +1,310 @@
+use anyhow::{bail, Context};
+use cgmath::Point3;
+use crate::bounds::{Aabb, BoundingSphere};
+use crate::model::ModelVertex;
+
+// bump whenever the layout below, `ModelVertex` or the OBJ processing in `load_obj` changes
+pub const MESH_CACHE_VERSION: u32 = 4;
+// appended to the OBJ file name, `blob.obj` is cached as `blob.obj.mesh`
+pub const MESH_CACHE_EXTENSION: &str = "mesh";
+const MAGIC: &[u8; 4] = b"WMSH";
+
+/// One coarser level of detail inside a `MeshBlob`.
+pub struct LodBlob<'a> {
+    // little endian u32 indices
+    pub indices: &'a [u8],
+    pub screen_size: f32,
+    pub error: f32,
+}
+
+/// A mesh as stored in the cache. Vertex and index data stay in the file's bytes
+/// and go to the GPU buffers without being parsed or copied.
+pub struct MeshBlob<'a> {
+    pub material: u32,
+    pub center: [f32; 3],
+    pub bounds: Aabb,
+    pub bounding_sphere: BoundingSphere,
+    // `ModelVertex` array
+    pub vertices: &'a [u8],
+    // little endian u32 indices
+    pub indices: &'a [u8],
+    pub lods: Vec<LodBlob<'a>>,
+}
+
+/// Binary replacement for an OBJ file and its processing.
+///
+/// Layout, all numbers little endian:
+/// magic `WMSH`, u32 version, u64 source hash, u32 1 if the meshes were optimized else 0,
+/// u32 material library count and that many strings (u32 length + UTF-8),
+/// u32 mesh count and per mesh: u32 material, 13 f32 (center, bounds min and max,
+/// sphere center and radius), u32 vertex count, u32 index count, u32 lod count,
+/// per lod u32 index count, f32 screen size, f32 error, then the vertex bytes,
+/// the index bytes and the index bytes of every lod.
+pub struct MeshCache<'a> {
+    // `SourceHasher` result over the OBJ and material library texts
+    pub source_hash: u64,
+    // whether `optimize_mesh` ran, see `OPTIMIZE_MESHES`
+    pub optimized: bool,
+    // MTL files the OBJ referenced, in order
+    pub material_libraries: Vec<&'a str>,
+    pub meshes: Vec<MeshBlob<'a>>,
+}
+
+impl<'a> MeshCache<'a> {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let put_u32 = |bytes: &mut Vec<u8>, value: u32| bytes.extend_from_slice(&value.to_le_bytes());
+        let put_f32 = |bytes: &mut Vec<u8>, value: f32| bytes.extend_from_slice(&value.to_le_bytes());
+
+        bytes.extend_from_slice(MAGIC);
+        put_u32(&mut bytes, MESH_CACHE_VERSION);
+        bytes.extend_from_slice(&self.source_hash.to_le_bytes());
+        put_u32(&mut bytes, self.optimized as u32);
+
+        put_u32(&mut bytes, self.material_libraries.len() as u32);
+        for library in &self.material_libraries {
+            put_u32(&mut bytes, library.len() as u32);
+            bytes.extend_from_slice(library.as_bytes());
+        }
+
+        put_u32(&mut bytes, self.meshes.len() as u32);
+        for mesh in &self.meshes {
+            put_u32(&mut bytes, mesh.material);
+            let array = |p: Point3<f32>| [p.x, p.y, p.z];
+            let floats = mesh.center.into_iter()
+                .chain(array(mesh.bounds.min))
+                .chain(array(mesh.bounds.max))
+                .chain(array(mesh.bounding_sphere.center))
+                .chain([mesh.bounding_sphere.radius]);
+            for value in floats {
+                put_f32(&mut bytes, value);
+            }
+            put_u32(&mut bytes, (mesh.vertices.len() / std::mem::size_of::<ModelVertex>()) as u32);
+            put_u32(&mut bytes, (mesh.indices.len() / 4) as u32);
+            put_u32(&mut bytes, mesh.lods.len() as u32);
+            for lod in &mesh.lods {
+                put_u32(&mut bytes, (lod.indices.len() / 4) as u32);
+                put_f32(&mut bytes, lod.screen_size);
+                put_f32(&mut bytes, lod.error);
+            }
+            bytes.extend_from_slice(mesh.vertices);
+            bytes.extend_from_slice(mesh.indices);
+            for lod in &mesh.lods {
+                bytes.extend_from_slice(lod.indices);
+            }
+        }
+        bytes
+    }
+
+    /// Reads a cache written by `encode`, borrowing the vertex and index data from `bytes`.
+    /// Checks the format and version, comparing `source_hash` is up to the caller.
+    pub fn decode(bytes: &'a [u8]) -> anyhow::Result<Self> {
+        let mut reader = Reader { bytes };
+        if reader.take(4)? != MAGIC {
+            bail!("not a mesh cache");
+        }
+        let version = reader.u32()?;
+        if version != MESH_CACHE_VERSION {
+            bail!("mesh cache version {} but {} is supported", version, MESH_CACHE_VERSION);
+        }
+        let source_hash = reader.u64()?;
+        let optimized = match reader.u32()? {
+            0 => false,
+            1 => true,
+            flag => bail!("mesh cache optimization flag {}", flag),
+        };
+
+        let material_libraries = (0..reader.u32()?)
+            .map(|_| {
+                let len = reader.u32()? as usize;
+                std::str::from_utf8(reader.take(len)?).context("material library name")
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let mesh_count = reader.u32()?;
+        let mut meshes = Vec::new();
+        for _ in 0..mesh_count {
+            let material = reader.u32()?;
+            let mut floats = [0.0; 13];
+            for value in &mut floats {
+                *value = reader.f32()?;
+            }
+            let point = |i: usize| Point3::new(floats[i], floats[i + 1], floats[i + 2]);
+            let vertex_count = reader.u32()? as usize;
+            let index_count = reader.u32()? as usize;
+            let lod_headers = (0..reader.u32()?)
+                .map(|_| Ok((reader.u32()? as usize, reader.f32()?, reader.f32()?)))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+
+            let vertices = reader.take(vertex_count * std::mem::size_of::<ModelVertex>())?;
+            let indices = reader.take(index_count * 4)?;
+            let lods = lod_headers.into_iter()
+                .map(|(index_count, screen_size, error)| Ok(LodBlob {
+                    indices: reader.take(index_count * 4)?,
+                    screen_size,
+                    error,
+                }))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+
+            meshes.push(MeshBlob {
+                material,
+                center: [floats[0], floats[1], floats[2]],
+                bounds: Aabb { min: point(3), max: point(6) },
+                bounding_sphere: BoundingSphere { center: point(9), radius: floats[12] },
+                vertices,
+                indices,
+                lods,
+            });
+        }
+        if !reader.bytes.is_empty() {
+            bail!("{} trailing bytes after the last mesh", reader.bytes.len());
+        }
+
+        Ok(Self {
+            source_hash,
+            optimized,
+            material_libraries,
+            meshes,
+        })
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> anyhow::Result<&'a [u8]> {
+        if len > self.bytes.len() {
+            bail!("mesh cache is truncated");
+        }
+        let (head, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> anyhow::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn u64(&mut self) -> anyhow::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+
+    fn f32(&mut self) -> anyhow::Result<f32> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+}
+
+/// 64 bit FNV-1a over the source files a cache was built from.
+pub struct SourceHasher(u64);
+
+impl SourceHasher {
+    pub fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+impl Default for SourceHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn cache_file_name(obj_file_name: &str) -> String {
+    format!("{}.{}", obj_file_name, MESH_CACHE_EXTENSION)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encoded() -> Vec<u8> {
+        let vertices = [ModelVertex {
+            position: [1.0, 2.0, 3.0],
+            tex_coords: [0.5, 0.25],
+            normal: [0.0, 1.0, 0.0],
+            tangent: [1.0, 0.0, 0.0, -1.0],
+        }; 3];
+        let indices = [0u32, 1, 2, 2, 1, 0];
+        let lod_indices = [0u32, 1, 2];
+        MeshCache {
+            source_hash: 0x0123_4567_89ab_cdef,
+            optimized: true,
+            material_libraries: vec!["blob.mtl"],
+            meshes: vec![MeshBlob {
+                material: 7,
+                center: [1.0, 2.0, 3.0],
+                bounds: Aabb { min: Point3::new(-1.0, -2.0, -3.0), max: Point3::new(1.0, 2.0, 3.0) },
+                bounding_sphere: BoundingSphere { center: Point3::new(0.0, 0.5, 0.0), radius: 4.0 },
+                vertices: bytemuck::cast_slice(&vertices),
+                indices: bytemuck::cast_slice(&indices),
+                lods: vec![LodBlob { indices: bytemuck::cast_slice(&lod_indices), screen_size: 0.25, error: 0.01 }],
+            }],
+        }.encode()
+    }
+
+    #[test]
+    fn round_trip() {
+        let bytes = encoded();
+        let cache = MeshCache::decode(&bytes).unwrap();
+        assert_eq!(cache.source_hash, 0x0123_4567_89ab_cdef);
+        assert!(cache.optimized);
+        assert_eq!(cache.material_libraries, ["blob.mtl"]);
+        let mesh = &cache.meshes[0];
+        assert_eq!(mesh.material, 7);
+        assert_eq!(mesh.center, [1.0, 2.0, 3.0]);
+        assert_eq!(mesh.bounds.max, Point3::new(1.0, 2.0, 3.0));
+        assert_eq!(mesh.bounding_sphere.radius, 4.0);
+        assert_eq!(mesh.vertices.len(), 3 * std::mem::size_of::<ModelVertex>());
+        assert_eq!(bytemuck::pod_read_unaligned::<ModelVertex>(&mesh.vertices[..std::mem::size_of::<ModelVertex>()]).tangent, [1.0, 0.0, 0.0, -1.0]);
+        assert_eq!(mesh.indices.len(), 6 * 4);
+        assert_eq!(mesh.lods.len(), 1);
+        assert_eq!((mesh.lods[0].indices.len(), mesh.lods[0].screen_size, mesh.lods[0].error), (3 * 4, 0.25, 0.01));
+    }
+
+    #[test]
+    fn wrong_magic_fails() {
+        let mut bytes = encoded();
+        bytes[..4].copy_from_slice(b"WPAK");
+        assert!(MeshCache::decode(&bytes).is_err());
+    }
+
+    #[test]
+    fn wrong_version_fails() {
+        let mut bytes = encoded();
+        for version in [MESH_CACHE_VERSION - 1, MESH_CACHE_VERSION + 1] {
+            bytes[4..8].copy_from_slice(&version.to_le_bytes());
+            assert!(MeshCache::decode(&bytes).is_err(), "version {}", version);
+        }
+    }
+
+    #[test]
+    fn unknown_optimization_flag_fails() {
+        let mut bytes = encoded();
+        bytes[16..20].copy_from_slice(&2u32.to_le_bytes());
+        assert!(MeshCache::decode(&bytes).is_err());
+    }
+
+    #[test]
+    fn truncated_or_trailing_bytes_fail() {
+        let bytes = encoded();
+        for len in [0, 3, 10, bytes.len() / 2, bytes.len() - 1] {
+            assert!(MeshCache::decode(&bytes[..len]).is_err(), "{} bytes", len);
+        }
+        let mut longer = bytes;
+        longer.push(0);
+        assert!(MeshCache::decode(&longer).is_err());
+    }
+}
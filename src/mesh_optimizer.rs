@@ -0,0 +1,251 @@
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3};
+
+// vertex cache modelled by the reordering, larger than most hardware so the order degrades gracefully
+const OPTIMIZE_CACHE_SIZE: usize = 32;
+// FIFO cache used to measure the result, close to what real hardware keeps
+const MEASURE_CACHE_SIZE: usize = 16;
+// overdraw sorting may make the cache hit rate this much worse
+const OVERDRAW_THRESHOLD: f32 = 1.05;
+
+// scoring constants from Tom Forsyth's "Linear-Speed Vertex Cache Optimisation"
+const CACHE_DECAY_POWER: f32 = 1.5;
+const LAST_TRIANGLE_SCORE: f32 = 0.75;
+const VALENCE_BOOST_SCALE: f32 = 2.0;
+const VALENCE_BOOST_POWER: f32 = 0.5;
+
+/// Cache statistics of an index buffer before and after `optimize_mesh`.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct OptimizeStats {
+    // average vertex shader invocations per triangle, 0.5 is ideal and 3 is the worst case
+    pub acmr_before: f32,
+    pub acmr_after: f32,
+    // invocations per vertex, 1 is ideal
+    pub atvr_before: f32,
+    pub atvr_after: f32,
+}
+
+/// Reorders triangles for the post transform vertex cache, then for less overdraw where that
+/// costs little cache efficiency, then reorders `vertices` in the order they are first used.
+pub fn optimize_mesh<V: Copy>(
+    vertices: &mut Vec<V>,
+    indices: &mut Vec<u32>,
+    position: impl Fn(&V) -> [f32; 3],
+) -> OptimizeStats {
+    let acmr_before = acmr(indices);
+    let atvr_before = atvr(indices, vertices.len());
+
+    let cached = optimize_vertex_cache(indices, vertices.len());
+    let positions = vertices.iter().map(&position).collect::<Vec<_>>();
+    *indices = optimize_overdraw(&cached, &positions, OVERDRAW_THRESHOLD);
+    *vertices = optimize_vertex_fetch(vertices, indices);
+
+    OptimizeStats {
+        acmr_before,
+        acmr_after: acmr(indices),
+        atvr_before,
+        atvr_after: atvr(indices, vertices.len()),
+    }
+}
+
+/// Average cache misses per triangle with a FIFO cache.
+pub fn acmr(indices: &[u32]) -> f32 {
+    cache_misses(indices) as f32 / (indices.len() / 3).max(1) as f32
+}
+
+/// Average cache misses per referenced vertex with a FIFO cache.
+pub fn atvr(indices: &[u32], vertex_count: usize) -> f32 {
+    cache_misses(indices) as f32 / vertex_count.max(1) as f32
+}
+
+fn cache_misses(indices: &[u32]) -> usize {
+    let mut cache = std::collections::VecDeque::with_capacity(MEASURE_CACHE_SIZE);
+    let mut misses = 0;
+    for &v in indices {
+        if !cache.contains(&v) {
+            misses += 1;
+            if cache.len() == MEASURE_CACHE_SIZE {
+                cache.pop_front();
+            }
+            cache.push_back(v);
+        }
+    }
+    misses
+}
+
+/// Greedy triangle reordering (Forsyth): always emits the highest scoring triangle, where
+/// vertices score higher the more recently they were used and the fewer triangles they have left.
+pub fn optimize_vertex_cache(indices: &[u32], vertex_count: usize) -> Vec<u32> {
+    let triangle_count = indices.len() / 3;
+
+    // triangles of every vertex
+    let mut offsets = vec![0; vertex_count + 1];
+    for &v in indices {
+        offsets[v as usize + 1] += 1;
+    }
+    for i in 0..vertex_count {
+        offsets[i + 1] += offsets[i];
+    }
+    let mut fill = offsets.clone();
+    let mut vertex_triangles = vec![0; indices.len()];
+    for (i, &v) in indices.iter().enumerate() {
+        vertex_triangles[fill[v as usize]] = i / 3;
+        fill[v as usize] += 1;
+    }
+
+    let mut remaining = (0..vertex_count)
+        .map(|v| offsets[v + 1] - offsets[v])
+        .collect::<Vec<_>>();
+    let mut cache_position = vec![None; vertex_count];
+    let mut vertex_score = (0..vertex_count)
+        .map(|v| vertex_cache_score(None, remaining[v]))
+        .collect::<Vec<_>>();
+    let triangle_score = |scores: &[f32], triangle: usize| {
+        indices[triangle * 3..triangle * 3 + 3].iter().map(|&v| scores[v as usize]).sum::<f32>()
+    };
+    let mut emitted = vec![false; triangle_count];
+    let mut result = Vec::with_capacity(indices.len());
+    let mut cache: Vec<u32> = Vec::with_capacity(OPTIMIZE_CACHE_SIZE + 3);
+
+    let mut best = (0..triangle_count).max_by(|&a, &b| {
+        triangle_score(&vertex_score, a).total_cmp(&triangle_score(&vertex_score, b))
+    });
+    // first triangle that might not be emitted yet, used when the cache has nothing to offer
+    let mut scan = 0;
+
+    while result.len() < triangle_count * 3 {
+        let triangle = match best {
+            Some(triangle) => triangle,
+            None => {
+                while emitted[scan] {
+                    scan += 1;
+                }
+                scan
+            }
+        };
+        emitted[triangle] = true;
+        let corners = &indices[triangle * 3..triangle * 3 + 3];
+        result.extend_from_slice(corners);
+
+        // the emitted vertices move to the front, everything else shifts back
+        let mut next_cache = corners.to_vec();
+        next_cache.extend(cache.iter().filter(|v| !corners.contains(v)));
+        for &v in corners {
+            remaining[v as usize] -= 1;
+        }
+        for (i, &v) in next_cache.iter().enumerate() {
+            cache_position[v as usize] = (i < OPTIMIZE_CACHE_SIZE).then_some(i);
+            vertex_score[v as usize] = vertex_cache_score(cache_position[v as usize], remaining[v as usize]);
+        }
+        next_cache.truncate(OPTIMIZE_CACHE_SIZE);
+        cache = next_cache;
+
+        // only triangles around cached vertices changed their score
+        best = None;
+        let mut best_score = f32::MIN;
+        for &v in &cache {
+            let v = v as usize;
+            for &candidate in &vertex_triangles[offsets[v]..offsets[v + 1]] {
+                if emitted[candidate] {
+                    continue;
+                }
+                let score = triangle_score(&vertex_score, candidate);
+                if score > best_score {
+                    best_score = score;
+                    best = Some(candidate);
+                }
+            }
+        }
+    }
+    result
+}
+
+fn vertex_cache_score(cache_position: Option<usize>, remaining: usize) -> f32 {
+    if remaining == 0 {
+        return -1.0;
+    }
+    let cache_score = match cache_position {
+        None => 0.0,
+        // the last triangle's vertices get a fixed score so it isn't simply repeated
+        Some(position) if position < 3 => LAST_TRIANGLE_SCORE,
+        Some(position) => {
+            let scale = 1.0 / (OPTIMIZE_CACHE_SIZE - 3) as f32;
+            (1.0 - (position - 3) as f32 * scale).powf(CACHE_DECAY_POWER)
+        }
+    };
+    cache_score + VALENCE_BOOST_SCALE * (remaining as f32).powf(-VALENCE_BOOST_POWER)
+}
+
+/// Splits the cache ordered triangles into clusters wherever the cache starts over and draws
+/// the clusters facing away from the mesh center first, since those tend to occlude the rest.
+/// Keeps the input order if that would raise the ACMR by more than `threshold`.
+pub fn optimize_overdraw(indices: &[u32], positions: &[[f32; 3]], threshold: f32) -> Vec<u32> {
+    let triangle_count = indices.len() / 3;
+    if triangle_count == 0 {
+        return indices.to_vec();
+    }
+
+    // a triangle missing all three vertices starts a new cluster
+    let mut starts = vec![0];
+    let mut cache = std::collections::VecDeque::with_capacity(MEASURE_CACHE_SIZE);
+    for (triangle, corners) in indices.chunks_exact(3).enumerate() {
+        let mut misses = 0;
+        for &v in corners {
+            if !cache.contains(&v) {
+                misses += 1;
+                if cache.len() == MEASURE_CACHE_SIZE {
+                    cache.pop_front();
+                }
+                cache.push_back(v);
+            }
+        }
+        if misses == 3 && triangle > 0 {
+            starts.push(triangle);
+        }
+    }
+    starts.push(triangle_count);
+
+    let point = |v: u32| Point3::from(positions[v as usize]);
+    let mesh_center = Point3::centroid(&indices.iter().map(|&v| point(v)).collect::<Vec<_>>());
+
+    let mut clusters = starts.windows(2)
+        .map(|range| {
+            let (mut center, mut normal, mut area) = (Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 0.0), 0.0);
+            for corners in indices[range[0] * 3..range[1] * 3].chunks_exact(3) {
+                let [a, b, c] = [point(corners[0]), point(corners[1]), point(corners[2])];
+                let cross = (b - a).cross(c - a);
+                let triangle_area = cross.magnitude();
+                center += (a.to_vec() + b.to_vec() + c.to_vec()) / 3.0 * triangle_area;
+                normal += cross;
+                area += triangle_area;
+            }
+            let center = if area > 0.0 { center / area } else { mesh_center.to_vec() };
+            let normal = if normal.magnitude2() > 0.0 { normal.normalize() } else { normal };
+            let sort_key = (Point3::from_vec(center) - mesh_center).dot(normal);
+            (sort_key, range[0], range[1])
+        })
+        .collect::<Vec<_>>();
+    clusters.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    let sorted = clusters.iter()
+        .flat_map(|&(_, start, end)| indices[start * 3..end * 3].iter().copied())
+        .collect::<Vec<_>>();
+    if acmr(&sorted) > acmr(indices) * threshold {
+        return indices.to_vec();
+    }
+    sorted
+}
+
+/// Reorders `vertices` by first use in `indices` and rewrites `indices` to match.
+/// Vertices no triangle uses are dropped.
+pub fn optimize_vertex_fetch<V: Copy>(vertices: &[V], indices: &mut [u32]) -> Vec<V> {
+    let mut remap = vec![None; vertices.len()];
+    let mut result = Vec::with_capacity(vertices.len());
+    for index in indices.iter_mut() {
+        let new_index = *remap[*index as usize].get_or_insert_with(|| {
+            result.push(vertices[*index as usize]);
+            result.len() as u32 - 1
+        });
+        *index = new_index;
+    }
+    result
+}
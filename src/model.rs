@@ -1,10 +1,23 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::io::{BufReader, Cursor};
 use std::ops::Range;
+use anyhow::Context;
 use cgmath::{Matrix4, Quaternion};
 use wgpu::util::DeviceExt;
+use crate::asset_manager::{asset_path, texture_path, AssetManager, Handle, ModelAssets};
+use crate::bounds::{Aabb, BoundingSphere};
+use crate::camera::Camera;
+use crate::instance::InstanceSet;
+use crate::constants::{NORMAL_SMOOTHING_ANGLE, OPTIMIZE_MESHES, UV_FALLBACK};
+use crate::lod::{generate_lods, LodData, MeshLod, screen_size, select_lod};
+use crate::mesh_cache::{cache_file_name, LodBlob, MeshBlob, MeshCache, SourceHasher};
+use crate::mesh_optimizer::optimize_mesh;
 use crate::model_matrix::ModelMatrix;
+use crate::obj_import::import_mesh;
 use crate::texture;
-use crate::texture::{load_texture, load_texture_model};
+use crate::texture::load_texture;
+use crate::vfs::vfs;
 
 pub trait Vertex {
     fn desc<'a>() -> wgpu::VertexBufferLayout<'a>;
@@ -16,6 +29,8 @@ pub struct ModelVertex {
     pub position: [f32; 3],
     pub tex_coords: [f32; 2],
     pub normal: [f32; 3],
+    // direction of increasing u, w is the sign of the bitangent, see `generate_tangents`
+    pub tangent: [f32; 4],
 }
 
 impl Vertex for ModelVertex {
@@ -40,56 +55,425 @@ impl Vertex for ModelVertex {
                     shader_location: 2,
                     format: wgpu::VertexFormat::Float32x3,
                 },
+                // 3 to 9 are taken by the instance matrices
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
+                    shader_location: 10,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
             ],
         }
     }
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum AlphaMode {
+    Opaque,
+    // fragments with alpha below the cutoff are discarded, the rest is drawn opaque
+    Mask(f32),
+    // drawn in the sorted transparent pass with alpha blending
+    Blend,
+}
+
+impl AlphaMode {
+    pub const DEFAULT_CUTOFF: f32 = 0.5;
+
+    /// Picks the mode for an MTL material. `alpha_mode` (opaque, mask, blend) and
+    /// `alpha_cutoff` can be set explicitly as extra MTL parameters, otherwise a
+    /// dissolve below 1 blends and a dissolve map (`map_d`) cuts out.
+    pub fn from_mtl(material: &tobj::Material) -> Self {
+        let cutoff = material.unknown_param.get("alpha_cutoff")
+            .and_then(|c| c.parse().ok())
+            .unwrap_or(Self::DEFAULT_CUTOFF);
+
+        match material.unknown_param.get("alpha_mode").map(String::as_str) {
+            Some("opaque") => AlphaMode::Opaque,
+            Some("mask") => AlphaMode::Mask(cutoff),
+            Some("blend") => AlphaMode::Blend,
+            _ if material.dissolve < 1.0 => AlphaMode::Blend,
+            _ if !material.dissolve_texture.is_empty() => AlphaMode::Mask(cutoff),
+            _ => AlphaMode::Opaque,
+        }
+    }
+
+    pub fn cutoff(&self) -> f32 {
+        match self {
+            AlphaMode::Mask(cutoff) => *cutoff,
+            _ => 0.0,
+        }
+    }
+}
+
+/// Parallax occlusion mapping settings of a material with a height map, see `shader.wgsl`.
+/// Extra MTL parameters `parallax_scale`, `parallax_steps` and `parallax_shadows` set them
+/// for a `disp` map.
+#[derive(Debug, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Parallax {
+    // depth of black below white in texture coordinates, 0 turns the effect off
+    pub height_scale: f32,
+    // most layers the view ray is marched through, looking straight at the surface takes fewer
+    pub steps: u32,
+    // marches from the hit point toward the light to darken texels behind bumps
+    pub self_shadowing: bool,
+}
+
+impl Parallax {
+    pub const DEFAULT_HEIGHT_SCALE: f32 = 0.05;
+    pub const DEFAULT_STEPS: u32 = 32;
+    // keeps a typo in a file from stalling the GPU
+    pub const MAX_STEPS: u32 = 256;
+
+    pub fn from_mtl(material: &tobj::Material) -> Self {
+        let param = |name: &str| material.unknown_param.get(name).map(|value| value.trim());
+        Self {
+            height_scale: param("parallax_scale").and_then(|s| s.parse().ok()).unwrap_or(Self::DEFAULT_HEIGHT_SCALE),
+            steps: param("parallax_steps").and_then(|s| s.parse().ok()).unwrap_or(Self::DEFAULT_STEPS),
+            self_shadowing: !matches!(param("parallax_shadows"), Some("0" | "off" | "false")),
+        }
+    }
+}
+
+impl Default for Parallax {
+    fn default() -> Self {
+        Self {
+            height_scale: Self::DEFAULT_HEIGHT_SCALE,
+            steps: Self::DEFAULT_STEPS,
+            self_shadowing: true,
+        }
+    }
+}
+
+/// Grayscale height map of a material, white is the surface itself.
+#[derive(Clone)]
+pub struct HeightMap {
+    // relative to res/, written back when saving a scene
+    pub file: String,
+    pub texture: Handle<texture::Texture>,
+    pub parallax: Parallax,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MaterialUniform {
+    pub opacity: f32,
+    pub alpha_cutoff: f32,
+    // 0 without a height map
+    pub height_scale: f32,
+    pub parallax_steps: f32,
+    // 1 or 0
+    pub parallax_shadows: f32,
+    _padding: [f32; 3],
+}
+
+impl MaterialUniform {
+    pub fn new(opacity: f32, alpha_mode: AlphaMode, height_map: Option<&HeightMap>) -> Self {
+        let parallax = height_map.map(|height_map| height_map.parallax);
+        Self {
+            opacity,
+            alpha_cutoff: alpha_mode.cutoff(),
+            height_scale: parallax.map_or(0.0, |p| p.height_scale.max(0.0)),
+            parallax_steps: parallax.map_or(0, |p| p.steps.clamp(1, Parallax::MAX_STEPS)) as f32,
+            parallax_shadows: if parallax.is_some_and(|p| p.self_shadowing) { 1.0 } else { 0.0 },
+            _padding: [0.0; 3],
+        }
+    }
+}
+
+// name of the material given to meshes that don't have one
+pub const DEFAULT_MATERIAL: &str = "<default>";
+
 pub struct Material {
     pub name: String,
-    pub diffuse_texture: texture::Texture,
+    pub diffuse_texture: Handle<texture::Texture>,
+    pub alpha_mode: AlphaMode,
+    // MTL dissolve, multiplied with the diffuse texture alpha
+    pub opacity: f32,
+    // turns on parallax occlusion mapping in the forward shader
+    pub height_map: Option<HeightMap>,
+    pub buffer: wgpu::Buffer,
     pub bind_group: wgpu::BindGroup,
 }
 
-pub struct Mesh {
+impl Material {
+    pub fn new(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        name: String,
+        diffuse_texture: Handle<texture::Texture>,
+        opacity: f32,
+        alpha_mode: AlphaMode,
+        height_map: Option<HeightMap>,
+    ) -> Self {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{:?} Material Buffer", name)),
+            contents: bytemuck::cast_slice(&[MaterialUniform::new(opacity, alpha_mode, height_map.as_ref())]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        // the shader doesn't sample the slot without a height map, so the diffuse texture fills it
+        let height_texture = height_map.as_ref().map_or(&diffuse_texture, |height_map| &height_map.texture);
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&height_texture.view),
+                },
+            ],
+            label: None,
+        });
+
+        Self {
+            name,
+            diffuse_texture,
+            alpha_mode,
+            opacity,
+            height_map,
+            buffer,
+            bind_group,
+        }
+    }
+
+    pub fn is_transparent(&self) -> bool {
+        self.alpha_mode == AlphaMode::Blend
+    }
+
+    pub fn uses_texture(&self, texture: &Handle<texture::Texture>) -> bool {
+        self.diffuse_texture == *texture
+            || self.height_map.as_ref().is_some_and(|height_map| height_map.texture == *texture)
+    }
+
+    /// A copy sharing the textures, for changing the alpha of one model without
+    /// affecting the others using this material.
+    pub fn with_alpha(
+        &self,
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        opacity: f32,
+        alpha_mode: AlphaMode,
+    ) -> Self {
+        Self::new(
+            device,
+            layout,
+            self.name.clone(),
+            self.diffuse_texture.clone(),
+            opacity,
+            alpha_mode,
+            self.height_map.clone(),
+        )
+    }
+
+    /// A copy with another height map, or none to turn parallax mapping off.
+    pub fn with_height_map(
+        &self,
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        height_map: Option<HeightMap>,
+    ) -> Self {
+        Self::new(
+            device,
+            layout,
+            self.name.clone(),
+            self.diffuse_texture.clone(),
+            self.opacity,
+            self.alpha_mode,
+            height_map,
+        )
+    }
+
+    /// A copy using `new` wherever this material uses `old`, for when the texture file is reloaded.
+    pub fn with_texture(
+        &self,
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        old: &Handle<texture::Texture>,
+        new: Handle<texture::Texture>,
+    ) -> Self {
+        let swap = |texture: &Handle<texture::Texture>| if texture == old { new.clone() } else { texture.clone() };
+        let height_map = self.height_map.as_ref().map(|height_map| HeightMap {
+            file: height_map.file.clone(),
+            texture: swap(&height_map.texture),
+            parallax: height_map.parallax,
+        });
+        Self::new(
+            device,
+            layout,
+            self.name.clone(),
+            swap(&self.diffuse_texture),
+            self.opacity,
+            self.alpha_mode,
+            height_map,
+        )
+    }
+}
+
+/// GPU data of one OBJ mesh, shared by every model loaded from the file.
+pub struct MeshGeometry {
     pub name: String,
     pub vertex_buffer: wgpu::Buffer,
     pub index_buffer: wgpu::Buffer,
     pub num_elements: u32,
     pub material: usize,
+    // model space center of the vertices, used to depth sort transparent meshes
+    pub center: [f32; 3],
+    // model space bounds, used for frustum culling
+    pub bounds: Aabb,
+    pub bounding_sphere: BoundingSphere,
+    // coarser index buffers from most to least detailed, may be empty
+    pub lods: Vec<MeshLod>,
+}
+
+pub struct Mesh {
+    pub geometry: Handle<MeshGeometry>,
+    // 0 draws the full index buffer, n draws lods[n - 1]
+    pub lod: usize,
+}
+
+impl Mesh {
+    pub fn new(geometry: Handle<MeshGeometry>) -> Self {
+        Self { geometry, lod: 0 }
+    }
+
+    /// Index buffer and index count of the active level of detail.
+    pub fn lod_indices(&self) -> (&wgpu::Buffer, u32) {
+        let geometry = &*self.geometry;
+        match self.lod.checked_sub(1) {
+            Some(i) => (&geometry.lods[i].index_buffer, geometry.lods[i].num_elements),
+            None => (&geometry.index_buffer, geometry.num_elements),
+        }
+    }
+
+    pub fn update_lod(&mut self, world: &Matrix4<f32>, camera: &Camera) {
+        let size = screen_size(&self.geometry.bounding_sphere.transform(world), camera);
+        self.lod = select_lod(&self.geometry.lods, self.lod, size);
+    }
 }
 
 pub struct Model {
     pub meshes: Vec<Mesh>,
-    pub materials: Vec<Material>,
+    pub materials: Vec<Handle<Material>>,
     pub model_matrix: ModelMatrix,
+    // drawn in place of the single model matrix when present
+    pub instances: Option<InstanceSet>,
+    // where the model was loaded from, relative to res/
+    pub folder: String,
+    pub file_name: String,
+    // drawn with placeholder meshes and materials until `AssetLoader` has read the file
+    pub loading: bool,
 }
 
+/// Reads `res/<path_to_folder>/<file_name>` through the `Vfs`, see `vfs::asset_root`
+/// for where `res/` is.
 pub fn load_string(
     path_to_folder: &str,
     file_name: &str
 ) -> anyhow::Result<String> {
+    vfs().read_string(&asset_path(path_to_folder, file_name))
+}
+
+pub fn load_binary(
+    path_to_folder: &str,
+    file_name: &str
+) -> anyhow::Result<Vec<u8>> {
+    vfs().read(&asset_path(path_to_folder, file_name))
+}
+
+/// CPU side of a `Mesh` as the OBJ loader produces it.
+pub struct MeshData {
+    pub material: usize,
+    pub vertices: Vec<ModelVertex>,
+    pub indices: Vec<u32>,
+    pub lods: Vec<LodData>,
+    pub center: [f32; 3],
+    pub bounds: Aabb,
+    pub bounding_sphere: BoundingSphere,
+}
 
-    let path = std::path::Path::new(env!("OUT_DIR"))
-        .join("res")
-        .join(path_to_folder)
-        .join(file_name);
-    let txt = std::fs::read_to_string(path)?;
+impl MeshData {
+    /// A mesh without coarser levels of detail, for geometry made in code.
+    pub fn new(material: usize, vertices: Vec<ModelVertex>, indices: Vec<u32>) -> Self {
+        let points = vertices.iter()
+            .map(|v| cgmath::Point3::from(v.position))
+            .collect::<Vec<_>>();
+        let center = points.iter()
+            .fold(cgmath::Vector3::new(0.0, 0.0, 0.0), |sum, p| sum + cgmath::Vector3::new(p.x, p.y, p.z))
+            / points.len().max(1) as f32;
+        Self {
+            material,
+            lods: Vec::new(),
+            center: center.into(),
+            bounds: Aabb::from_points(points.iter().copied()),
+            bounding_sphere: BoundingSphere::from_points(&points),
+            vertices,
+            indices,
+        }
+    }
 
-    Ok(txt)
+    pub fn blob(&self) -> MeshBlob<'_> {
+        MeshBlob {
+            material: self.material as u32,
+            center: self.center,
+            bounds: self.bounds,
+            bounding_sphere: self.bounding_sphere,
+            vertices: bytemuck::cast_slice(&self.vertices),
+            indices: bytemuck::cast_slice(&self.indices),
+            lods: self.lods.iter()
+                .map(|lod| LodBlob {
+                    indices: bytemuck::cast_slice(&lod.indices),
+                    screen_size: lod.screen_size,
+                    error: lod.error,
+                })
+                .collect(),
+        }
+    }
 }
 
-pub async fn load_model(
+/// Everything `read_model_source` takes from an OBJ file before touching the GPU.
+pub struct ObjData {
+    pub meshes: Vec<MeshData>,
+    pub materials: Vec<tobj::Material>,
+    // MTL files the OBJ referenced, in order
+    pub material_libraries: Vec<String>,
+    pub source_hash: u64,
+    // problems that didn't stop the load, like a missing MTL file
+    pub warnings: Vec<String>,
+}
+
+impl ObjData {
+    pub fn cache(&self) -> MeshCache<'_> {
+        MeshCache {
+            source_hash: self.source_hash,
+            optimized: OPTIMIZE_MESHES,
+            material_libraries: self.material_libraries.iter().map(String::as_str).collect(),
+            meshes: self.meshes.iter().map(MeshData::blob).collect(),
+        }
+    }
+}
+
+/// Parses and processes an OBJ file, the slow path the mesh cache exists to skip.
+pub async fn load_obj(
     path_to_folder_in_res: &str,
     file_name: &str,
-    device: &wgpu::Device,
-    queue: &wgpu::Queue,
-    layout: &wgpu::BindGroupLayout,
-) -> anyhow::Result<Model> {
-    let obj_text = load_string(path_to_folder_in_res, file_name)?;
-    let obj_cursor = Cursor::new(obj_text);
+) -> anyhow::Result<ObjData> {
+    let obj_text = load_string(path_to_folder_in_res, file_name)
+        .with_context(|| format!("reading {}{}", path_to_folder_in_res, file_name))?;
+    let obj_cursor = Cursor::new(obj_text.as_bytes());
     let mut obj_reader = BufReader::new(obj_cursor);
 
+    let material_libraries = RefCell::new(Vec::new());
+    let warnings = RefCell::new(Vec::new());
     let (models, obj_materials) = tobj::load_obj_buf_async(
         &mut obj_reader,
         &tobj::LoadOptions {
@@ -97,80 +481,371 @@ pub async fn load_model(
             single_index: true,
             ..Default::default()
         },
-        |p| async move {
-            let mat_text = load_string(path_to_folder_in_res, &p).unwrap();
-            tobj::load_mtl_buf(&mut BufReader::new(Cursor::new(mat_text)))
+        |p| {
+            material_libraries.borrow_mut().push(p.clone());
+            let warnings = &warnings;
+            async move {
+                let mat_text = load_string(path_to_folder_in_res, &p).map_err(|e| {
+                    warnings.borrow_mut().push(format!("{}: can't read material library {}: {:#}", file_name, p, e));
+                    tobj::LoadError::OpenFileFailed
+                })?;
+                tobj::load_mtl_buf(&mut BufReader::new(Cursor::new(mat_text)))
+            }
         },
-    ).await?;
-
-    let mut materials = Vec::new();
-    for m in obj_materials? {
-        let diffuse_texture = load_texture_model(path_to_folder_in_res, &m.diffuse_texture, device, queue, false)?;
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-                },
-            ],
-            label: None,
-        });
-
-        materials.push(Material {
-            name: m.name,
-            diffuse_texture,
-            bind_group,
-        })
-    }
+    ).await.with_context(|| format!("parsing {}", file_name))?;
+    let material_libraries = material_libraries.into_inner();
+    let mut warnings = warnings.into_inner();
+    // meshes without a material get a default one in `upload_model_source`
+    let materials = obj_materials.unwrap_or_else(|e| {
+        if warnings.is_empty() {
+            warnings.push(format!("{}: can't load its materials: {}", file_name, e));
+        }
+        Vec::new()
+    });
 
     let meshes = models
         .into_iter()
         .map(|m| {
-            let vertices = (0..m.mesh.positions.len() / 3)
-                .map(|i| ModelVertex {
-                    position: [
-                        m.mesh.positions[i * 3],
-                        m.mesh.positions[i * 3 + 1],
-                        m.mesh.positions[i * 3 + 2],
-                    ],
-                    tex_coords: [m.mesh.texcoords[i * 2], 1.0 - m.mesh.texcoords[i * 2 + 1]],
-                    normal: [
-                        m.mesh.normals[i * 3],
-                        m.mesh.normals[i * 3 + 1],
-                        m.mesh.normals[i * 3 + 2],
-                    ],
-                })
+            let (mut vertices, mut indices) = import_mesh(file_name, &m, NORMAL_SMOOTHING_ANGLE, UV_FALLBACK)?;
+
+            if OPTIMIZE_MESHES {
+                let stats = optimize_mesh(&mut vertices, &mut indices, |v| v.position);
+                log::info!(
+                    "{} {}: ACMR {:.3} -> {:.3}, ATVR {:.3} -> {:.3}",
+                    file_name,
+                    m.name,
+                    stats.acmr_before,
+                    stats.acmr_after,
+                    stats.atvr_before,
+                    stats.atvr_after,
+                );
+            }
+
+            let center = vertices.iter()
+                .fold([0.0; 3], |acc, v| [
+                    acc[0] + v.position[0],
+                    acc[1] + v.position[1],
+                    acc[2] + v.position[2],
+                ])
+                .map(|c| c / vertices.len().max(1) as f32);
+
+            let points = vertices.iter()
+                .map(|v| cgmath::Point3::from(v.position))
                 .collect::<Vec<_>>();
 
-            let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some(&format!("{:?} Vertex Buffer", file_name)),
-                contents: bytemuck::cast_slice(&vertices),
-                usage: wgpu::BufferUsages::VERTEX,
-            });
-            let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some(&format!("{:?} Index Buffer", file_name)),
-                contents: bytemuck::cast_slice(&m.mesh.indices),
-                usage: wgpu::BufferUsages::INDEX,
-            });
-
-            Mesh {
-                name: file_name.to_string(),
-                vertex_buffer,
-                index_buffer,
-                num_elements: m.mesh.indices.len() as u32,
+            let positions = vertices.iter().map(|v| v.position).collect::<Vec<_>>();
+            let lods = generate_lods(file_name, &positions, &indices, OPTIMIZE_MESHES);
+
+            Ok(MeshData {
                 material: m.mesh.material_id.unwrap_or(0),
-            }
+                center,
+                bounds: Aabb::from_points(points.iter().copied()),
+                bounding_sphere: BoundingSphere::from_points(&points),
+                vertices,
+                indices,
+                lods,
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    Ok(ObjData {
+        meshes,
+        materials,
+        source_hash: source_hash(path_to_folder_in_res, &obj_text, &material_libraries),
+        material_libraries,
+        warnings,
+    })
+}
+
+// covers everything the processed meshes depend on, a missing material library
+// counts as empty so the hash changes once it shows up
+fn source_hash(path_to_folder_in_res: &str, obj_text: &str, material_libraries: &[impl AsRef<str>]) -> u64 {
+    let mut hasher = SourceHasher::new();
+    hasher.update(obj_text.as_bytes());
+    for library in material_libraries {
+        hasher.update(load_string(path_to_folder_in_res, library.as_ref()).unwrap_or_default().as_bytes());
+    }
+    hasher.update(&[UV_FALLBACK as u8]);
+    hasher.update(&NORMAL_SMOOTHING_ANGLE.to_le_bytes());
+    hasher.finish()
+}
+
+// the cache is used as is when its source isn't shipped, but never with meshes
+// processed differently than `OPTIMIZE_MESHES` asks for
+fn read_mesh_cache<'a>(
+    path_to_folder_in_res: &str,
+    file_name: &str,
+    bytes: &'a [u8],
+) -> anyhow::Result<MeshCache<'a>> {
+    let cache = MeshCache::decode(bytes)?;
+    if cache.optimized != OPTIMIZE_MESHES {
+        anyhow::bail!(
+            "the cache was written with OPTIMIZE_MESHES {} but it is {}, run convert_meshes again",
+            cache.optimized,
+            OPTIMIZE_MESHES,
+        );
+    }
+    if let Ok(obj_text) = load_string(path_to_folder_in_res, file_name) {
+        if source_hash(path_to_folder_in_res, &obj_text, &cache.material_libraries) != cache.source_hash {
+            anyhow::bail!("{} changed since the cache was written", file_name);
+        }
+    }
+    Ok(cache)
+}
+
+fn load_material_libraries(
+    path_to_folder_in_res: &str,
+    material_libraries: &[&str],
+) -> anyhow::Result<Vec<tobj::Material>> {
+    let mut materials = Vec::new();
+    for library in material_libraries {
+        let mat_text = load_string(path_to_folder_in_res, library)?;
+        let (library_materials, _) = tobj::load_mtl_buf(&mut BufReader::new(Cursor::new(mat_text)))?;
+        materials.extend(library_materials);
+    }
+    Ok(materials)
+}
+
+fn create_mesh(device: &wgpu::Device, file_name: &str, blob: &MeshBlob) -> MeshGeometry {
+    let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some(&format!("{:?} Vertex Buffer", file_name)),
+        contents: blob.vertices,
+        // storage lets the wireframe fallback read vertices per triangle corner
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+    });
+    let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some(&format!("{:?} Index Buffer", file_name)),
+        contents: blob.indices,
+        usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+    });
+    let lods = blob.lods.iter()
+        .enumerate()
+        .map(|(i, lod)| MeshLod {
+            index_buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("{:?} LOD {} Index Buffer", file_name, i + 1)),
+                contents: lod.indices,
+                usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            }),
+            num_elements: (lod.indices.len() / 4) as u32,
+            screen_size: lod.screen_size,
+            error: lod.error,
         })
-        .collect::<Vec<_>>();
+        .collect();
+
+    MeshGeometry {
+        name: file_name.to_string(),
+        vertex_buffer,
+        index_buffer,
+        num_elements: (blob.indices.len() / 4) as u32,
+        material: blob.material as usize,
+        center: blob.center,
+        bounds: blob.bounds,
+        bounding_sphere: blob.bounding_sphere,
+        lods,
+    }
+}
+
+pub enum SourceMeshes {
+    Parsed(Vec<MeshData>),
+    // the bytes of a `.mesh` file that matched the OBJ, uploaded straight from its
+    // `MeshBlob` slices
+    Cached(Vec<u8>),
+}
 
-    Ok(Model { meshes, materials, model_matrix: ModelMatrix::identity(device) })
+/// Everything a model needs from disk, read and decoded without touching the GPU so it
+/// can happen on a worker thread. See `AssetLoader`.
+pub struct ModelSource {
+    pub meshes: SourceMeshes,
+    pub materials: Vec<tobj::Material>,
+    // decoded diffuse and height map textures by file name
+    pub images: HashMap<String, image::DynamicImage>,
+    // problems that didn't stop the load, reported by `upload_model_source`
+    pub warnings: Vec<String>,
 }
+
+/// Reads a model from its binary mesh cache (`<file>.mesh`, see `convert_meshes`) when
+/// that exists and still matches the OBJ, otherwise from the OBJ. Textures whose key is
+/// in `loaded_textures` are already on the GPU and aren't decoded again.
+pub async fn read_model_source(
+    path_to_folder_in_res: &str,
+    file_name: &str,
+    loaded_textures: &HashSet<String>,
+) -> anyhow::Result<ModelSource> {
+    let cache_bytes = load_binary(path_to_folder_in_res, &cache_file_name(file_name)).ok();
+    let material_libraries = cache_bytes.as_deref().and_then(|bytes| {
+        read_mesh_cache(path_to_folder_in_res, file_name, bytes)
+            .map(|cache| cache.material_libraries.iter().map(|library| library.to_string()).collect::<Vec<_>>())
+            .map_err(|e| log::info!("not using the mesh cache of {}: {}", file_name, e))
+            .ok()
+    });
+
+    let (meshes, materials, warnings) = match (cache_bytes, material_libraries) {
+        (Some(bytes), Some(material_libraries)) => {
+            let mut warnings = Vec::new();
+            let libraries = material_libraries.iter().map(String::as_str).collect::<Vec<_>>();
+            let materials = load_material_libraries(path_to_folder_in_res, &libraries)
+                .unwrap_or_else(|e| {
+                    warnings.push(format!("{}: can't load its materials: {:#}", file_name, e));
+                    Vec::new()
+                });
+            (SourceMeshes::Cached(bytes), materials, warnings)
+        }
+        _ => {
+            let obj = load_obj(path_to_folder_in_res, file_name).await?;
+            (SourceMeshes::Parsed(obj.meshes), obj.materials, obj.warnings)
+        }
+    };
+
+    let mut images = HashMap::new();
+    let textures = materials.iter().flat_map(|m| {
+        let diffuse = Some(m.diffuse_texture.as_str()).filter(|file_name| !file_name.is_empty());
+        [diffuse.map(|file_name| (file_name, false)), height_map_file(m).map(|file_name| (file_name, true))]
+    });
+    for (file_name, is_normal_map) in textures.flatten() {
+        if images.contains_key(file_name)
+            || loaded_textures.contains(&texture_path(path_to_folder_in_res, file_name, is_normal_map)) {
+            continue;
+        }
+        // a texture that doesn't decode is reported when the material is created
+        if let Ok(image) = load_binary(path_to_folder_in_res, file_name)
+            .and_then(|bytes| Ok(image::load_from_memory(&bytes)?)) {
+            images.insert(file_name.to_string(), image);
+        }
+    }
+
+    Ok(ModelSource { meshes, materials, images, warnings })
+}
+
+// the MTL displacement map, used as the height map for parallax occlusion mapping
+fn height_map_file(material: &tobj::Material) -> Option<&str> {
+    material.unknown_param.get("disp")
+        .map(|file_name| file_name.trim())
+        .filter(|file_name| !file_name.is_empty())
+}
+
+/// Creates the GPU side of `source` and registers it with `assets` as the file's meshes and materials.
+pub fn upload_model_source(
+    path_to_folder_in_res: &str,
+    file_name: &str,
+    source: ModelSource,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    layout: &wgpu::BindGroupLayout,
+    assets: &mut AssetManager,
+) -> anyhow::Result<Handle<ModelAssets>> {
+    let path = asset_path(path_to_folder_in_res, file_name);
+    for warning in source.warnings {
+        assets.report.warn(warning);
+    }
+
+    let mut images = source.images;
+    let mut material_texture = |assets: &mut AssetManager, material: &str, file_name: &str, is_normal_map: bool| {
+        let texture_key = texture_path(path_to_folder_in_res, file_name, is_normal_map);
+        let loaded = assets.textures.get_or_try_insert_with(&texture_key, || match images.remove(file_name) {
+            Some(image) => texture::Texture::from_image(device, queue, &image, Some(file_name), is_normal_map),
+            None => load_texture(path_to_folder_in_res, file_name, device, queue, is_normal_map),
+        });
+        loaded.map_err(|e| format!(
+            "{}: texture {:?} of material {:?} failed to load: {:#}",
+            path,
+            file_name,
+            material,
+            e,
+        ))
+    };
+    let mut materials = Vec::new();
+    for m in source.materials {
+        let diffuse_texture = if m.diffuse_texture.is_empty() {
+            assets.white_texture(device, queue)
+        } else {
+            material_texture(assets, &m.name, &m.diffuse_texture, false).unwrap_or_else(|e| {
+                assets.report.warn(format!("{}, drawing the missing texture instead", e));
+                assets.missing_texture(device, queue)
+            })
+        };
+        // height data is linear like a normal map
+        let height_map = match height_map_file(&m) {
+            Some(file_name) => match material_texture(assets, &m.name, file_name, true) {
+                Ok(texture) => Some(HeightMap {
+                    file: asset_path(path_to_folder_in_res, file_name),
+                    texture,
+                    parallax: Parallax::from_mtl(&m),
+                }),
+                Err(e) => {
+                    assets.report.warn(format!("{}, drawing it without parallax", e));
+                    None
+                }
+            },
+            None => None,
+        };
+        let alpha_mode = AlphaMode::from_mtl(&m);
+        let material = Material::new(device, layout, m.name.clone(), diffuse_texture, m.dissolve, alpha_mode, height_map);
+        materials.push(assets.materials.insert(&format!("{}#{}", path, m.name), material));
+    }
+
+    // meshes without a valid material, including every mesh of a file without materials,
+    // share a plain white one
+    // read_model_source already decoded the cache once, so this only fails on a corrupt source
+    let mut blobs = match &source.meshes {
+        SourceMeshes::Parsed(meshes) => meshes.iter().map(MeshData::blob).collect::<Vec<_>>(),
+        SourceMeshes::Cached(bytes) => MeshCache::decode(bytes).context("mesh cache")?.meshes,
+    };
+    let material_count = materials.len();
+    if blobs.iter().any(|blob| blob.material as usize >= material_count) {
+        if material_count > 0 {
+            assets.report.warn(format!("{}: meshes use undefined materials, drawing them white", path));
+        }
+        let white = assets.white_texture(device, queue);
+        let material = Material::new(device, layout, DEFAULT_MATERIAL.to_string(), white, 1.0, AlphaMode::Opaque, None);
+        materials.push(assets.materials.insert(&format!("{}#{}", path, DEFAULT_MATERIAL), material));
+        for blob in blobs.iter_mut().filter(|blob| blob.material as usize >= material_count) {
+            blob.material = material_count as u32;
+        }
+    }
+
+    let meshes = blobs.iter()
+        .enumerate()
+        .map(|(i, blob)| assets.meshes.insert(&format!("{}#{}", path, i), create_mesh(device, file_name, blob)))
+        .collect();
+    Ok(assets.models.insert(&path, ModelAssets { meshes, materials }))
+}
+
 impl Model {
+    pub fn new(device: &wgpu::Device, path_to_folder_in_res: &str, file_name: &str, assets: &ModelAssets) -> Self {
+        Self {
+            meshes: assets.meshes.iter().cloned().map(Mesh::new).collect(),
+            materials: assets.materials.clone(),
+            model_matrix: ModelMatrix::identity(device),
+            instances: None,
+            folder: path_to_folder_in_res.to_string(),
+            file_name: file_name.to_string(),
+            loading: false,
+        }
+    }
+
+    /// Swaps in the meshes and materials of a finished load, keeping the
+    /// transform and instances.
+    pub fn set_assets(&mut self, assets: &ModelAssets) {
+        self.meshes = assets.meshes.iter().cloned().map(Mesh::new).collect();
+        self.materials = assets.materials.clone();
+        self.loading = false;
+    }
+
+    /// Per instance matrices bound to vertex buffer slot 1.
+    pub fn instance_buffer(&self) -> &wgpu::Buffer {
+        match &self.instances {
+            Some(instances) => instances.buffer(),
+            None => &self.model_matrix.buffer,
+        }
+    }
+
+    pub fn instance_range(&self) -> Range<u32> {
+        match &self.instances {
+            Some(instances) => instances.range(),
+            None => 0..1,
+        }
+    }
+
     pub fn translate_local(
         &mut self,
         position: [f32; 3],
@@ -215,6 +890,52 @@ impl Model {
 }
 
 
+pub fn create_material_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            // height map, sampled with the diffuse sampler
+            wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                },
+                count: None,
+            },
+        ],
+        label: Some("material_bind_group_layout"),
+    })
+}
+
+
 pub trait DrawModel<'a> {
     fn draw_mesh(
         &mut self,
@@ -269,12 +990,13 @@ impl<'a, 'b> DrawModel<'b> for wgpu::RenderPass<'a>
         camera_bind_group: &'b wgpu::BindGroup,
         light_bind_group: &'b wgpu::BindGroup,
     ) {
-        self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
-        self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        let (index_buffer, num_elements) = mesh.lod_indices();
+        self.set_vertex_buffer(0, mesh.geometry.vertex_buffer.slice(..));
+        self.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
         self.set_bind_group(0, &material.bind_group, &[]);
         self.set_bind_group(1, camera_bind_group, &[]);
         self.set_bind_group(2, light_bind_group, &[]);
-        self.draw_indexed(0..mesh.num_elements, 0, instances);
+        self.draw_indexed(0..num_elements, 0, instances);
     }
 
     fn draw_model(
@@ -283,7 +1005,9 @@ impl<'a, 'b> DrawModel<'b> for wgpu::RenderPass<'a>
         camera_bind_group: &'b wgpu::BindGroup,
         light_bind_group: &'b wgpu::BindGroup,
     ) {
-        self.draw_model_instanced(model, 0..1, camera_bind_group, light_bind_group);
+        // binds the model's own instances, draw_model_instanced leaves that to the caller
+        self.set_vertex_buffer(1, model.instance_buffer().slice(..));
+        self.draw_model_instanced(model, model.instance_range(), camera_bind_group, light_bind_group);
     }
 
     fn draw_model_instanced(
@@ -294,7 +1018,11 @@ impl<'a, 'b> DrawModel<'b> for wgpu::RenderPass<'a>
         light_bind_group: &'b wgpu::BindGroup,
     ) {
         for mesh in &model.meshes {
-            let material = &model.materials[mesh.material];
+            let material = &model.materials[mesh.geometry.material];
+            // blended meshes are drawn back to front by the transparent pass
+            if material.is_transparent() {
+                continue;
+            }
             self.draw_mesh_instanced(mesh, material, instances.clone(), camera_bind_group, light_bind_group);
         }
     }
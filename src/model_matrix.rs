@@ -1,7 +1,8 @@
-use cgmath::{Matrix, Matrix3, Matrix4, Quaternion, Rad, Rotation3, SquareMatrix, Vector3, Zero};
+use cgmath::{Matrix, Matrix3, Matrix4, Quaternion, SquareMatrix};
 use wgpu::util::DeviceExt;
 use wgpu::VertexBufferLayout;
-use crate::model::{ModelVertex, Vertex};
+use crate::model::Vertex;
+use crate::transform::Transform;
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
@@ -21,7 +22,7 @@ impl RawModelMatrix {
         let normal = normal_matrix;
 
         Self {
-            model: model,
+            model,
             normal: normal.into(),
         }
     }
@@ -54,102 +55,90 @@ impl Vertex for RawModelMatrix {
     }
 }
 
+/// Per model transform uploaded as a single instance of `RawModelMatrix`.
+/// `local` places the mesh relative to its owner and `world` places the owner in the scene.
+/// `world` stays a matrix because a propagated hierarchy can contain shear, which a
+/// `Transform` can't represent.
 pub struct ModelMatrix {
-    pub local: Matrix4<f32>,
+    pub local: Transform,
     pub world: Matrix4<f32>,
     pub buffer: wgpu::Buffer,
 }
 
 impl ModelMatrix {
-    pub fn new(device: &wgpu::Device, local_transform: Matrix4<f32>, world_transform: Matrix4<f32>) -> Self {
-
-        let raw_matrix = RawModelMatrix::new(local_transform * world_transform);
+    pub fn new(device: &wgpu::Device, local: Transform, world: Matrix4<f32>) -> Self {
+        let raw_matrix = RawModelMatrix::new(world * local.to_matrix());
         let data = [raw_matrix];
         let buffer = device.create_buffer_init(
             &wgpu::util::BufferInitDescriptor {
             label: Some("model Buffer"),
             contents: bytemuck::cast_slice(&data),
-            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
         });
         Self {
-            local: local_transform,
-            world: world_transform,
+            local,
+            world,
             buffer,
         }
     }
 
     pub fn identity(device: &wgpu::Device) -> Self {
-        let local = Matrix4::identity();
-        let world = Matrix4::identity();
-        let raw_matrix = RawModelMatrix::new(local*world);
-        let data = [raw_matrix];
-        let buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("model matrix Buffer"),
-                contents: bytemuck::cast_slice(&data),
-                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-            });
-        Self {
-            local,
-            world,
-            buffer,
-        }
+        Self::new(device, Transform::identity(), Matrix4::identity())
     }
 
     pub fn translate_local(
         &mut self,
         position: [f32; 3],
     ) {
-        self.local.x.w += position[0];
-        self.local.y.w += position[1];
-        self.local.z.w += position[2];
+        self.local.translate(position.into());
     }
 
     pub fn translate_world(
         &mut self,
         position: [f32; 3],
     ) {
-        self.world.x.w += position[0];
-        self.world.y.w += position[1];
-        self.world.z.w += position[2];
+        self.world = Matrix4::from_translation(position.into()) * self.world;
     }
 
     pub fn scale_local(
         &mut self,
         scale: [f32; 3],
     ) {
-        self.local.x.x *= scale[0];
-        self.local.y.y *= scale[1];
-        self.local.z.z *= scale[2];
+        self.local.scale_by(scale.into());
     }
 
     pub fn scale_world(
         &mut self,
         scale: [f32; 3],
     ) {
-        self.world.x.x *= scale[0];
-        self.world.y.y *= scale[1];
-        self.world.z.z *= scale[2];
+        self.world = self.world * Matrix4::from_nonuniform_scale(scale[0], scale[1], scale[2]);
     }
 
     pub fn rotate_world(
         &mut self,
         rotation: Quaternion<f32>,
     ) {
-        let rotation_matrix = Matrix4::from(rotation);
-        self.world = self.world * rotation_matrix;
+        // around the model's own origin, like `Transform::rotate`
+        let origin = self.world.w.truncate();
+        self.world = Matrix4::from_translation(origin)
+            * Matrix4::from(rotation)
+            * Matrix4::from_translation(-origin)
+            * self.world;
     }
 
     pub fn rotate_local(
         &mut self,
         rotation: Quaternion<f32>,
     ) {
-        let rotation_matrix = Matrix4::from(rotation);
-        self.local = self.local * rotation_matrix;
+        self.local.rotate(rotation);
+    }
+
+    pub fn matrix(&self) -> Matrix4<f32> {
+        self.world * self.local.to_matrix()
     }
 
     pub fn to_raw(&self) -> RawModelMatrix {
-        RawModelMatrix::new(self.world * self.local)
+        RawModelMatrix::new(self.matrix())
     }
 }
 
@@ -0,0 +1,119 @@
+use cgmath::{Deg, Matrix4, Quaternion, SquareMatrix, Vector3};
+use crate::camera::Camera;
+use crate::light::Light;
+use crate::model::Model;
+use crate::transform::Transform;
+
+/// Index of a node inside its `Scene`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct NodeId(pub(crate) usize);
+
+/// Scene graph node. The local transform is combined with the parent's world matrix by `Scene::update`.
+pub struct Node {
+    pub name: String,
+
+    transform: Transform,
+
+    pub(crate) parent: Option<NodeId>,
+    pub(crate) children: Vec<NodeId>,
+    pub(crate) world: Matrix4<f32>,
+    // set whenever the local transform changes, cleared once the world matrix is rebuilt
+    pub(crate) dirty: bool,
+
+    // axis and angle the node turns by every frame, see `Scene::animate`
+    pub spin: Option<(Vector3<f32>, Deg<f32>)>,
+
+    // things placed in the world by this node
+    pub model: Option<Model>,
+    pub light: Option<Light>,
+    pub camera: Option<Camera>,
+}
+
+impl Node {
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            transform: Transform::identity(),
+            parent: None,
+            children: Vec::new(),
+            world: Matrix4::identity(),
+            dirty: true,
+            spin: None,
+            model: None,
+            light: None,
+            camera: None,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn with_model(mut self, model: Model) -> Self {
+        self.model = Some(model);
+        self
+    }
+
+    pub fn with_light(mut self, light: Light) -> Self {
+        self.light = Some(light);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_camera(mut self, camera: Camera) -> Self {
+        self.camera = Some(camera);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    pub fn children(&self) -> &[NodeId] {
+        &self.children
+    }
+
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
+
+    pub fn set_transform(&mut self, transform: Transform) {
+        self.transform = transform;
+        self.dirty = true;
+    }
+
+    pub fn set_translation(&mut self, translation: Vector3<f32>) {
+        self.transform.translation = translation;
+        self.dirty = true;
+    }
+
+    #[allow(dead_code)]
+    pub fn set_rotation(&mut self, rotation: Quaternion<f32>) {
+        self.transform.rotation = rotation;
+        self.dirty = true;
+    }
+
+    #[allow(dead_code)]
+    pub fn set_scale(&mut self, scale: Vector3<f32>) {
+        self.transform.scale = scale;
+        self.dirty = true;
+    }
+
+    #[allow(dead_code)]
+    pub fn translate(&mut self, offset: Vector3<f32>) {
+        self.transform.translate(offset);
+        self.dirty = true;
+    }
+
+    pub fn rotate(&mut self, rotation: Quaternion<f32>) {
+        self.transform.rotate(rotation);
+        self.dirty = true;
+    }
+
+    pub fn local_matrix(&self) -> Matrix4<f32> {
+        self.transform.to_matrix()
+    }
+
+    /// World matrix as of the last `Scene::update`.
+    pub fn world_matrix(&self) -> Matrix4<f32> {
+        self.world
+    }
+}
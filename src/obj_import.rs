@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+use anyhow::bail;
+use cgmath::{InnerSpace, Vector3};
+use crate::bounds::Aabb;
+use crate::model::ModelVertex;
+use crate::tangents::generate_tangents;
+
+/// How texture coordinates are made up for meshes that have none.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum UvProjection {
+    // one projection along the axis the mesh is thinnest in
+    #[allow(dead_code)]
+    Planar,
+    // every triangle projected along the axis its normal points the most
+    Box,
+}
+
+/// Turns a triangulated, single indexed `tobj` mesh into vertices and indices, generating
+/// whatever normals or texture coordinates the file left out. OBJ has no tangents, they
+/// are always generated.
+///
+/// Missing normals are averaged from the triangles around a position whose normals are
+/// within `smoothing_angle` degrees of each other, so 0 gives flat and 180 smooth shading.
+pub fn import_mesh(
+    file_name: &str,
+    model: &tobj::Model,
+    smoothing_angle: f32,
+    uv_projection: UvProjection,
+) -> anyhow::Result<(Vec<ModelVertex>, Vec<u32>)> {
+    let mesh = &model.mesh;
+    let context = || format!("{} mesh {:?}", file_name, model.name);
+
+    if !mesh.positions.len().is_multiple_of(3) {
+        bail!("{}: {} position floats is not a multiple of 3", context(), mesh.positions.len());
+    }
+    let vertex_count = mesh.positions.len() / 3;
+    if !mesh.indices.len().is_multiple_of(3) {
+        bail!("{}: {} indices don't make whole triangles", context(), mesh.indices.len());
+    }
+    if let Some(&index) = mesh.indices.iter().find(|&&index| index as usize >= vertex_count) {
+        bail!("{}: index {} is out of range for {} vertices", context(), index, vertex_count);
+    }
+    let has_normals = match mesh.normals.len() {
+        0 => false,
+        len if len == vertex_count * 3 => true,
+        len => bail!("{}: {} normal floats for {} vertices", context(), len, vertex_count),
+    };
+    let has_tex_coords = match mesh.texcoords.len() {
+        0 => false,
+        len if len == vertex_count * 2 => true,
+        len => bail!("{}: {} texture coordinate floats for {} vertices", context(), len, vertex_count),
+    };
+
+    let position = |i: usize| [mesh.positions[i * 3], mesh.positions[i * 3 + 1], mesh.positions[i * 3 + 2]];
+
+    if has_normals && has_tex_coords {
+        let mut vertices = (0..vertex_count)
+            .map(|i| ModelVertex {
+                position: position(i),
+                tex_coords: [mesh.texcoords[i * 2], 1.0 - mesh.texcoords[i * 2 + 1]],
+                normal: [mesh.normals[i * 3], mesh.normals[i * 3 + 1], mesh.normals[i * 3 + 2]],
+                tangent: [0.0; 4],
+            })
+            .collect::<Vec<_>>();
+        generate_tangents(&mut vertices, &mesh.indices);
+        return Ok((vertices, mesh.indices.clone()));
+    }
+
+    if !has_normals {
+        log::info!("{}: no normals, generating them", context());
+    }
+    if !has_tex_coords {
+        log::info!("{}: no texture coordinates, using a {:?} projection", context(), uv_projection);
+    }
+
+    let positions = (0..vertex_count).map(position).collect::<Vec<_>>();
+    let face_normals = mesh.indices.chunks_exact(3)
+        .map(|triangle| {
+            let [a, b, c] = [0, 1, 2].map(|i| Vector3::from(positions[triangle[i] as usize]));
+            (b - a).cross(c - a)
+        })
+        .collect::<Vec<_>>();
+    let corner_normals = if has_normals {
+        mesh.indices.iter()
+            .map(|&i| {
+                let i = i as usize;
+                Vector3::new(mesh.normals[i * 3], mesh.normals[i * 3 + 1], mesh.normals[i * 3 + 2])
+            })
+            .collect()
+    } else {
+        generate_normals(&positions, &mesh.indices, &face_normals, smoothing_angle)
+    };
+    let corner_tex_coords = if has_tex_coords {
+        mesh.indices.iter()
+            .map(|&i| {
+                let i = i as usize;
+                [mesh.texcoords[i * 2], 1.0 - mesh.texcoords[i * 2 + 1]]
+            })
+            .collect()
+    } else {
+        project_tex_coords(&positions, &mesh.indices, &face_normals, uv_projection)
+    };
+
+    // corners that ended up with the same attributes share a vertex again
+    let mut vertices = Vec::new();
+    let mut unique = HashMap::new();
+    let indices = mesh.indices.iter()
+        .enumerate()
+        .map(|(corner, &i)| {
+            let normal: [f32; 3] = corner_normals[corner].into();
+            let tex_coords = corner_tex_coords[corner];
+            let key = (i, normal.map(f32::to_bits), tex_coords.map(f32::to_bits));
+            *unique.entry(key).or_insert_with(|| {
+                vertices.push(ModelVertex {
+                    position: positions[i as usize],
+                    tex_coords,
+                    normal,
+                    tangent: [0.0; 4],
+                });
+                vertices.len() as u32 - 1
+            })
+        })
+        .collect::<Vec<_>>();
+    generate_tangents(&mut vertices, &indices);
+    Ok((vertices, indices))
+}
+
+// one normal per triangle corner
+fn generate_normals(
+    positions: &[[f32; 3]],
+    indices: &[u32],
+    face_normals: &[Vector3<f32>],
+    smoothing_angle: f32,
+) -> Vec<Vector3<f32>> {
+    // vertices split for texture coordinates still smooth across the seam
+    let mut corners_at = HashMap::<[u32; 3], Vec<usize>>::new();
+    for (corner, &i) in indices.iter().enumerate() {
+        corners_at.entry(positions[i as usize].map(f32::to_bits)).or_default().push(corner);
+    }
+
+    let unit = face_normals.iter()
+        .map(|n| if n.magnitude2() > 0.0 { n.normalize() } else { *n })
+        .collect::<Vec<_>>();
+    let min_cos = smoothing_angle.to_radians().cos();
+
+    // weighting by the angle at the corner keeps the result independent of how faces are triangulated
+    let corner_angles = indices.iter()
+        .enumerate()
+        .map(|(corner, &i)| {
+            let triangle = corner / 3 * 3;
+            let next = indices[triangle + (corner + 1) % 3];
+            let previous = indices[triangle + (corner + 2) % 3];
+            let p = Vector3::from(positions[i as usize]);
+            let a = Vector3::from(positions[next as usize]) - p;
+            let b = Vector3::from(positions[previous as usize]) - p;
+            if a.magnitude2() > 0.0 && b.magnitude2() > 0.0 {
+                a.angle(b).0
+            } else {
+                0.0
+            }
+        })
+        .collect::<Vec<_>>();
+
+    indices.iter()
+        .enumerate()
+        .map(|(corner, &i)| {
+            let triangle = corner / 3;
+            let sum = corners_at[&positions[i as usize].map(f32::to_bits)].iter()
+                .filter(|&&other| other / 3 == triangle || unit[triangle].dot(unit[other / 3]) >= min_cos)
+                .fold(Vector3::new(0.0, 0.0, 0.0), |sum, &other| sum + unit[other / 3] * corner_angles[other]);
+            if sum.magnitude2() > 0.0 {
+                sum.normalize()
+            } else {
+                Vector3::unit_y()
+            }
+        })
+        .collect()
+}
+
+// one set of texture coordinates per triangle corner, one unit spans the whole mesh
+fn project_tex_coords(
+    positions: &[[f32; 3]],
+    indices: &[u32],
+    face_normals: &[Vector3<f32>],
+    projection: UvProjection,
+) -> Vec<[f32; 2]> {
+    let bounds = Aabb::from_points(positions.iter().map(|&p| p.into()));
+    let size = bounds.max - bounds.min;
+    let scale = 1.0 / size.x.max(size.y).max(size.z).max(f32::EPSILON);
+
+    // the axis dropped by the projection
+    let thinnest = if size.x <= size.y && size.x <= size.z {
+        0
+    } else if size.y <= size.z {
+        1
+    } else {
+        2
+    };
+    let dominant = |n: Vector3<f32>| {
+        let n = Vector3::new(n.x.abs(), n.y.abs(), n.z.abs());
+        if n.x >= n.y && n.x >= n.z {
+            0
+        } else if n.y >= n.z {
+            1
+        } else {
+            2
+        }
+    };
+
+    indices.iter()
+        .enumerate()
+        .map(|(corner, &i)| {
+            let axis = match projection {
+                UvProjection::Planar => thinnest,
+                UvProjection::Box => dominant(face_normals[corner / 3]),
+            };
+            let p = positions[i as usize];
+            let local = [
+                (p[0] - bounds.min.x) * scale,
+                (p[1] - bounds.min.y) * scale,
+                (p[2] - bounds.min.z) * scale,
+            ];
+            match axis {
+                0 => [local[2], 1.0 - local[1]],
+                1 => [local[0], local[2]],
+                _ => [local[0], 1.0 - local[1]],
+            }
+        })
+        .collect()
+}
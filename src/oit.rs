@@ -0,0 +1,258 @@
+use wgpu::{FragmentState, VertexState};
+use crate::camera::create_camera_bind_group_layout;
+use crate::light::create_light_bind_group_layout;
+use crate::model::{create_material_bind_group_layout, ModelVertex, Vertex};
+use crate::model_matrix::RawModelMatrix;
+use crate::texture::Texture;
+use crate::shader::{create_shader_module, try_create};
+
+pub const ACCUM_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+pub const REVEALAGE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+
+/// Weighted blended order independent transparency.
+/// Transparent meshes are accumulated unsorted into `accum` (weighted premultiplied color)
+/// and `revealage` (product of 1 - alpha), then resolved over the opaque image.
+pub struct OitTargets {
+    pub accum: Texture,
+    pub revealage: Texture,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl OitTargets {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+    ) -> Self {
+        let accum = Texture::create_render_target(device, config, ACCUM_FORMAT, "oit accum");
+        let revealage = Texture::create_render_target(device, config, REVEALAGE_FORMAT, "oit revealage");
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("OIT Bind Group"),
+            layout: &create_oit_bind_group_layout(device),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&accum.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&revealage.view),
+                },
+            ],
+        });
+
+        Self {
+            accum,
+            revealage,
+            bind_group,
+        }
+    }
+
+    pub fn color_attachments(&self) -> [Option<wgpu::RenderPassColorAttachment<'_>>; 2] {
+        [
+            Some(wgpu::RenderPassColorAttachment {
+                view: &self.accum.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: true,
+                },
+            }),
+            // revealage starts fully revealed and is multiplied down by every fragment
+            Some(wgpu::RenderPassColorAttachment {
+                view: &self.revealage.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                    store: true,
+                },
+            }),
+        ]
+    }
+}
+
+pub fn create_oit_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    let entry = |binding| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            multisampled: false,
+            view_dimension: wgpu::TextureViewDimension::D2,
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+        },
+        count: None,
+    };
+
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("OIT Bind Group Layout"),
+        entries: &[entry(0), entry(1)],
+    })
+}
+
+pub struct OitRenderer {
+    pub accumulate_pipeline: wgpu::RenderPipeline,
+    pub composite_pipeline: wgpu::RenderPipeline,
+    pub targets: OitTargets,
+}
+
+impl OitRenderer {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            accumulate_pipeline: create_accumulate_pipeline(device)?,
+            composite_pipeline: create_composite_pipeline(device, config)?,
+            targets: OitTargets::new(device, config),
+        })
+    }
+
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+    ) {
+        self.targets = OitTargets::new(device, config);
+    }
+
+    // shader files the pipelines are built from, see `reload_pipelines`
+    pub const SHADERS: &'static [&'static str] = &["shader.wgsl", "oit_composite.wgsl"];
+
+    /// Rebuilds the pipelines from the current shader files, the old ones stay if that fails.
+    pub fn reload_pipelines(
+        &mut self,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+    ) {
+        let pipelines = try_create(device, "OIT pipelines", || {
+            Ok((create_accumulate_pipeline(device)?, create_composite_pipeline(device, config)?))
+        });
+        if let Some((accumulate_pipeline, composite_pipeline)) = pipelines {
+            self.accumulate_pipeline = accumulate_pipeline;
+            self.composite_pipeline = composite_pipeline;
+        }
+    }
+}
+
+fn create_accumulate_pipeline(device: &wgpu::Device) -> anyhow::Result<wgpu::RenderPipeline> {
+    // same layouts as SimplePipeline so meshes can be drawn with DrawModel
+    let layouts = &[
+        &create_material_bind_group_layout(device),
+        &create_camera_bind_group_layout(device),
+        &create_light_bind_group_layout(device),
+    ];
+
+    let layout = device.create_pipeline_layout(
+        &wgpu::PipelineLayoutDescriptor {
+            label: Some("OIT Accumulate Pipeline Layout"),
+            bind_group_layouts: layouts,
+            push_constant_ranges: &[],
+        }
+    );
+
+    let shader = create_shader_module(device, "shader.wgsl")?;
+
+    let additive = wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::One,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+    };
+    let multiplicative = wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::Zero,
+        dst_factor: wgpu::BlendFactor::OneMinusSrc,
+        operation: wgpu::BlendOperation::Add,
+    };
+
+    Ok(device.create_render_pipeline(
+        &wgpu::RenderPipelineDescriptor {
+            label: Some("OIT Accumulate Pipeline"),
+            layout: Some(&layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[ModelVertex::desc(), RawModelMatrix::desc()],
+            },
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: "fs_oit",
+                targets: &[
+                    Some(wgpu::ColorTargetState {
+                        format: ACCUM_FORMAT,
+                        blend: Some(wgpu::BlendState {
+                            color: additive,
+                            alpha: additive,
+                        }),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }),
+                    Some(wgpu::ColorTargetState {
+                        format: REVEALAGE_FORMAT,
+                        blend: Some(wgpu::BlendState {
+                            color: multiplicative,
+                            alpha: multiplicative,
+                        }),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }),
+                ],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            // tested against opaque depth, never written so order doesn't matter
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: Default::default(),
+            multiview: None,
+        }
+    ))
+}
+
+fn create_composite_pipeline(
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+) -> anyhow::Result<wgpu::RenderPipeline> {
+    let layout = device.create_pipeline_layout(
+        &wgpu::PipelineLayoutDescriptor {
+            label: Some("OIT Composite Pipeline Layout"),
+            bind_group_layouts: &[&create_oit_bind_group_layout(device)],
+            push_constant_ranges: &[],
+        }
+    );
+
+    let shader = create_shader_module(device, "oit_composite.wgsl")?;
+
+    Ok(device.create_render_pipeline(
+        &wgpu::RenderPipelineDescriptor {
+            label: Some("OIT Composite Pipeline"),
+            layout: Some(&layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: Default::default(),
+            multiview: None,
+        }
+    ))
+}
@@ -0,0 +1,421 @@
+use std::collections::HashMap;
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+use cgmath::{InnerSpace, Vector3};
+use serde::{Deserialize, Serialize};
+use crate::asset_manager::{asset_path, AssetManager};
+use crate::model::{upload_model_source, MeshData, Model, ModelSource, SourceMeshes, ModelVertex};
+use crate::tangents::generate_tangents;
+
+// folder generated models are registered under in the `AssetManager`, nothing is read from it
+pub const PRIMITIVE_FOLDER: &str = "<primitives>";
+// each one splits every triangle into four
+const MAX_ICOSPHERE_SUBDIVISIONS: u32 = 8;
+
+/// Shapes made in code, for tests and blockouts. They are centered on the origin with Y up
+/// and have counter clockwise front faces, normals, tangents and texture coordinates
+/// covering the texture once. Counts below what makes the shape are raised to the minimum.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Primitive {
+    // square in the XZ plane facing +Y, split into `subdivisions` quads along each side
+    Plane { size: f32, subdivisions: u32 },
+    // every face is a subdivided plane showing the whole texture
+    Cube { size: f32, subdivisions: u32 },
+    // `segments` around Y, `rings` from pole to pole
+    UvSphere { radius: f32, segments: u32, rings: u32 },
+    // subdivided icosahedron, evenly sized triangles and no pinching at the poles
+    Icosphere { radius: f32, subdivisions: u32 },
+    // along Y with flat caps, `rings` splits the side
+    Cylinder { radius: f32, height: f32, segments: u32, rings: u32 },
+    // base at -height / 2, tip at height / 2
+    Cone { radius: f32, height: f32, segments: u32, rings: u32 },
+    // ring around Y, `segments` around the ring and `sides` around the tube
+    Torus { radius: f32, tube_radius: f32, segments: u32, sides: u32 },
+    // cylinder of `height` between two hemispheres of `rings` rings each
+    Capsule { radius: f32, height: f32, segments: u32, rings: u32 },
+}
+
+impl Primitive {
+    /// Name of the generated model, primitives with the same parameters share their mesh.
+    /// It's the primitive as RON, so `from_file_name` can tell what a model was made from.
+    pub fn file_name(&self) -> String {
+        ron::to_string(self).expect("primitives are plain data")
+    }
+
+    pub fn from_file_name(file_name: &str) -> Option<Self> {
+        ron::from_str(file_name).ok()
+    }
+
+    pub fn geometry(&self) -> (Vec<ModelVertex>, Vec<u32>) {
+        let mut builder = MeshBuilder::default();
+        match *self {
+            Self::Plane { size, subdivisions } => {
+                builder.face(Vector3::new(0.0, 0.0, 0.0), Vector3::unit_x() * size, Vector3::unit_z() * size, subdivisions.max(1));
+            }
+            Self::Cube { size, subdivisions } => {
+                // right and down as seen from outside, so no face is mirrored
+                let (x, y, z) = (Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z());
+                for (normal, right, down) in [(x, -z, -y), (-x, z, -y), (y, x, z), (-y, x, -z), (z, x, -y), (-z, -x, -y)] {
+                    builder.face(normal * size * 0.5, right * size, down * size, subdivisions.max(1));
+                }
+            }
+            Self::UvSphere { radius, segments, rings } => {
+                let rings = rings.max(2);
+                let profile = (0..=rings)
+                    .map(|ring| {
+                        let phi = ring as f32 / rings as f32 * PI;
+                        ProfilePoint::on_circle(radius, 0.0, phi, ring as f32 / rings as f32)
+                    })
+                    .collect::<Vec<_>>();
+                builder.lathe(segments.max(3), &profile);
+            }
+            Self::Icosphere { radius, subdivisions } => {
+                builder.icosphere(radius, subdivisions.min(MAX_ICOSPHERE_SUBDIVISIONS));
+            }
+            Self::Cylinder { radius, height, segments, rings } => {
+                let rings = rings.max(1);
+                let profile = (0..=rings)
+                    .map(|ring| {
+                        let v = ring as f32 / rings as f32;
+                        ProfilePoint { radius, y: height * (0.5 - v), normal: [1.0, 0.0], v }
+                    })
+                    .collect::<Vec<_>>();
+                builder.lathe(segments.max(3), &profile);
+                builder.disk(radius, height * 0.5, segments.max(3));
+                builder.disk(radius, -height * 0.5, segments.max(3));
+            }
+            Self::Cone { radius, height, segments, rings } => {
+                let rings = rings.max(1);
+                let slope = cgmath::Vector2::new(height, radius).normalize();
+                let profile = (0..=rings)
+                    .map(|ring| {
+                        let v = ring as f32 / rings as f32;
+                        ProfilePoint { radius: radius * v, y: height * (0.5 - v), normal: [slope.x, slope.y], v }
+                    })
+                    .collect::<Vec<_>>();
+                builder.lathe(segments.max(3), &profile);
+                builder.disk(radius, -height * 0.5, segments.max(3));
+            }
+            Self::Torus { radius, tube_radius, segments, sides } => {
+                let sides = sides.max(3);
+                let profile = (0..=sides)
+                    .map(|side| {
+                        // starts on the outside and goes down first, like v on the other shapes
+                        let phi = FRAC_PI_2 + side as f32 / sides as f32 * TAU;
+                        ProfilePoint::on_circle(tube_radius, radius, phi, side as f32 / sides as f32)
+                    })
+                    .collect::<Vec<_>>();
+                builder.lathe(segments.max(3), &profile);
+            }
+            Self::Capsule { radius, height, segments, rings } => {
+                let rings = rings.max(1);
+                // texture coordinates follow the length along the outline
+                let length = PI * radius + height;
+                let mut profile = Vec::new();
+                for (center, first_phi, first_v) in [(height * 0.5, 0.0, 0.0), (-height * 0.5, FRAC_PI_2, FRAC_PI_2 * radius + height)] {
+                    for ring in 0..=rings {
+                        let angle = ring as f32 / rings as f32 * FRAC_PI_2;
+                        let mut point = ProfilePoint::on_circle(radius, 0.0, first_phi + angle, (first_v + angle * radius) / length);
+                        point.y += center;
+                        profile.push(point);
+                    }
+                }
+                builder.lathe(segments.max(3), &profile);
+            }
+        }
+        builder.finish()
+    }
+}
+
+/// Creates a model of `primitive` drawn with the white default material, see
+/// `DEFAULT_MATERIAL`. Models of the same primitive share their mesh.
+pub fn create_primitive(
+    primitive: Primitive,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    layout: &wgpu::BindGroupLayout,
+    assets: &mut AssetManager,
+) -> anyhow::Result<Model> {
+    let file_name = primitive.file_name();
+    let model_assets = match assets.models.get(&asset_path(PRIMITIVE_FOLDER, &file_name)) {
+        Some(model_assets) => model_assets,
+        None => {
+            let (vertices, indices) = primitive.geometry();
+            let source = ModelSource {
+                meshes: SourceMeshes::Parsed(vec![MeshData::new(0, vertices, indices)]),
+                materials: Vec::new(),
+                images: HashMap::new(),
+                warnings: Vec::new(),
+            };
+            upload_model_source(PRIMITIVE_FOLDER, &file_name, source, device, queue, layout, assets)?
+        }
+    };
+    Ok(Model::new(device, PRIMITIVE_FOLDER, &file_name, &model_assets))
+}
+
+// a point on the outline `MeshBuilder::lathe` turns around the Y axis
+struct ProfilePoint {
+    radius: f32,
+    y: f32,
+    // away from the axis and along Y
+    normal: [f32; 2],
+    v: f32,
+}
+
+impl ProfilePoint {
+    // on a circle of `circle_radius` around (`center_radius`, 0), `phi` 0 being its top
+    fn on_circle(circle_radius: f32, center_radius: f32, phi: f32, v: f32) -> Self {
+        let normal = [phi.sin(), phi.cos()];
+        Self {
+            radius: center_radius + circle_radius * normal[0],
+            y: circle_radius * normal[1],
+            normal,
+            v,
+        }
+    }
+}
+
+#[derive(Default)]
+struct MeshBuilder {
+    vertices: Vec<ModelVertex>,
+    indices: Vec<u32>,
+}
+
+impl MeshBuilder {
+    fn vertex(&mut self, position: Vector3<f32>, normal: Vector3<f32>, tex_coords: [f32; 2]) -> u32 {
+        self.vertices.push(ModelVertex {
+            position: position.into(),
+            tex_coords,
+            normal: normal.normalize().into(),
+            tangent: [0.0; 4],
+        });
+        self.vertices.len() as u32 - 1
+    }
+
+    // skips triangles without area, which shapes get where rings meet in a point, and
+    // turns the others to face the way their normals point
+    fn triangle(&mut self, a: u32, b: u32, c: u32) {
+        let [pa, pb, pc] = [a, b, c].map(|i| Vector3::from(self.vertices[i as usize].position));
+        let (e1, e2) = (pb - pa, pc - pa);
+        let face = e1.cross(e2);
+        if face.magnitude2() <= f32::EPSILON * e1.magnitude2() * e2.magnitude2() {
+            return;
+        }
+        let normals = [a, b, c].map(|i| Vector3::from(self.vertices[i as usize].normal));
+        if face.dot(normals[0] + normals[1] + normals[2]) < 0.0 {
+            self.indices.extend([a, c, b]);
+        } else {
+            self.indices.extend([a, b, c]);
+        }
+    }
+
+    // (columns + 1) x (rows + 1) vertices joined into quads, `point` gets the column and row
+    fn grid(
+        &mut self,
+        columns: u32,
+        rows: u32,
+        point: impl Fn(u32, u32) -> (Vector3<f32>, Vector3<f32>, [f32; 2]),
+    ) {
+        let first = self.vertices.len() as u32;
+        for row in 0..=rows {
+            for column in 0..=columns {
+                let (position, normal, tex_coords) = point(column, row);
+                self.vertex(position, normal, tex_coords);
+            }
+        }
+        let index = |column: u32, row: u32| first + row * (columns + 1) + column;
+        for row in 0..rows {
+            for column in 0..columns {
+                let [a, b, c, d] = [
+                    index(column, row),
+                    index(column + 1, row),
+                    index(column + 1, row + 1),
+                    index(column, row + 1),
+                ];
+                self.triangle(a, b, c);
+                self.triangle(a, c, d);
+            }
+        }
+    }
+
+    // square around `center` facing away from the origin, or +Y for a plane through it
+    fn face(&mut self, center: Vector3<f32>, right: Vector3<f32>, down: Vector3<f32>, subdivisions: u32) {
+        let normal = down.cross(right).normalize();
+        let n = subdivisions as f32;
+        self.grid(subdivisions, subdivisions, |column, row| {
+            let (u, v) = (column as f32 / n, row as f32 / n);
+            (center + right * (u - 0.5) + down * (v - 0.5), normal, [u, v])
+        });
+    }
+
+    // `profile` turned around the Y axis, the texture's u goes around and v along the profile
+    fn lathe(&mut self, segments: u32, profile: &[ProfilePoint]) {
+        self.grid(segments, profile.len() as u32 - 1, |column, row| {
+            let u = column as f32 / segments as f32;
+            // u grows to the right when looking at the shape from outside
+            let theta = u * TAU;
+            let out = Vector3::new(theta.sin(), 0.0, theta.cos());
+            let point = &profile[row as usize];
+            let position = out * point.radius + Vector3::unit_y() * point.y;
+            let normal = out * point.normal[0] + Vector3::unit_y() * point.normal[1];
+            (position, normal, [u, point.v])
+        });
+    }
+
+    // cap at height `y` facing away from the origin, oriented like the cube's top or bottom
+    fn disk(&mut self, radius: f32, y: f32, segments: u32) {
+        let normal = Vector3::unit_y() * y.signum();
+        let down = y.signum();
+        let center = self.vertex(Vector3::unit_y() * y, normal, [0.5, 0.5]);
+        let first = self.vertices.len() as u32;
+        for segment in 0..=segments {
+            let theta = segment as f32 / segments as f32 * TAU;
+            let (x, z) = (theta.sin(), theta.cos());
+            self.vertex(Vector3::new(x * radius, y, z * radius), normal, [0.5 + x * 0.5, 0.5 + z * down * 0.5]);
+        }
+        for segment in 0..segments {
+            self.triangle(center, first + segment, first + segment + 1);
+        }
+    }
+
+    fn icosphere(&mut self, radius: f32, subdivisions: u32) {
+        let t = (1.0 + 5.0f32.sqrt()) * 0.5;
+        let mut points = [
+            [-1.0, t, 0.0], [1.0, t, 0.0], [-1.0, -t, 0.0], [1.0, -t, 0.0],
+            [0.0, -1.0, t], [0.0, 1.0, t], [0.0, -1.0, -t], [0.0, 1.0, -t],
+            [t, 0.0, -1.0], [t, 0.0, 1.0], [-t, 0.0, -1.0], [-t, 0.0, 1.0],
+        ].map(|p| Vector3::from(p).normalize()).to_vec();
+        let mut triangles = vec![
+            [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+            [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+            [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+            [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+        ];
+        for _ in 0..subdivisions {
+            let mut midpoints = HashMap::new();
+            let mut midpoint = |a: u32, b: u32| {
+                *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                    points.push((points[a as usize] + points[b as usize]).normalize());
+                    points.len() as u32 - 1
+                })
+            };
+            triangles = triangles.iter()
+                .flat_map(|&[a, b, c]| {
+                    let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                    [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+                })
+                .collect();
+        }
+
+        // the same mapping as the UV sphere, points on the seam or at a pole are split
+        // per triangle so no triangle stretches across the whole texture
+        let mut vertices = HashMap::new();
+        for triangle in triangles {
+            let corners = triangle.map(|i| points[i as usize]);
+            let mut uvs = corners.map(|p| [p.x.atan2(p.z).rem_euclid(TAU) / TAU, p.y.clamp(-1.0, 1.0).acos() / PI]);
+            let is_pole = corners.map(|p| p.x * p.x + p.z * p.z < 1e-8);
+            let us = (0..3).filter(|&i| !is_pole[i]).map(|i| uvs[i][0]).collect::<Vec<_>>();
+            let wraps = us.iter().cloned().fold(f32::MIN, f32::max) - us.iter().cloned().fold(f32::MAX, f32::min) > 0.5;
+            for (i, uv) in uvs.iter_mut().enumerate() {
+                if wraps && !is_pole[i] && uv[0] < 0.5 {
+                    uv[0] += 1.0;
+                }
+            }
+            let around = (0..3).filter(|&i| !is_pole[i]).map(|i| uvs[i][0]).sum::<f32>() / us.len().max(1) as f32;
+            for (i, uv) in uvs.iter_mut().enumerate() {
+                if is_pole[i] {
+                    uv[0] = around;
+                }
+            }
+
+            let [a, b, c] = [0, 1, 2].map(|i| {
+                let key = (triangle[i], uvs[i].map(f32::to_bits));
+                *vertices.entry(key).or_insert_with(|| self.vertex(corners[i] * radius, corners[i], uvs[i]))
+            });
+            self.triangle(a, b, c);
+        }
+    }
+
+    fn finish(mut self) -> (Vec<ModelVertex>, Vec<u32>) {
+        generate_tangents(&mut self.vertices, &self.indices);
+        (self.vertices, self.indices)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shapes() -> [Primitive; 8] {
+        [
+            Primitive::Plane { size: 2.0, subdivisions: 3 },
+            Primitive::Cube { size: 1.5, subdivisions: 2 },
+            Primitive::UvSphere { radius: 1.0, segments: 16, rings: 8 },
+            Primitive::Icosphere { radius: 0.8, subdivisions: 2 },
+            Primitive::Cylinder { radius: 0.5, height: 2.0, segments: 12, rings: 2 },
+            Primitive::Cone { radius: 1.0, height: 1.5, segments: 12, rings: 3 },
+            Primitive::Torus { radius: 1.0, tube_radius: 0.25, segments: 16, sides: 8 },
+            Primitive::Capsule { radius: 0.5, height: 1.0, segments: 12, rings: 4 },
+        ]
+    }
+
+    // where a normal at `position` has to point away from, the shapes are convex around
+    // the origin except the plane, which faces +Y, and the torus, which is convex around its tube
+    fn inside(primitive: Primitive, position: Vector3<f32>) -> Vector3<f32> {
+        match primitive {
+            Primitive::Plane { .. } => position - Vector3::unit_y(),
+            Primitive::Torus { radius, .. } => Vector3::new(position.x, 0.0, position.z).normalize() * radius,
+            _ => Vector3::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    #[test]
+    fn indices_are_in_range() {
+        for primitive in shapes() {
+            let (vertices, indices) = primitive.geometry();
+            assert!(!indices.is_empty() && indices.len() % 3 == 0, "{:?}", primitive);
+            assert!(indices.iter().all(|&i| (i as usize) < vertices.len()), "{:?}", primitive);
+        }
+    }
+
+    #[test]
+    fn normals_are_unit_and_point_outward() {
+        for primitive in shapes() {
+            let (vertices, indices) = primitive.geometry();
+            for vertex in &vertices {
+                let position = Vector3::from(vertex.position);
+                let normal = Vector3::from(vertex.normal);
+                assert!((normal.magnitude() - 1.0).abs() < 1e-4, "{:?} {:?}", primitive, vertex);
+                assert!(normal.dot(position - inside(primitive, position)) > 0.0, "{:?} {:?}", primitive, vertex);
+            }
+            // counter clockwise front faces agree with the normals
+            for triangle in indices.chunks_exact(3) {
+                let [a, b, c] = [0, 1, 2].map(|i| &vertices[triangle[i] as usize]);
+                let [pa, pb, pc] = [a, b, c].map(|v| Vector3::from(v.position));
+                let face = (pb - pa).cross(pc - pa);
+                assert!(face.dot(Vector3::from(a.normal)) > 0.0, "{:?} {:?}", primitive, triangle);
+            }
+        }
+    }
+
+    #[test]
+    fn tangents_are_orthogonal_with_handedness() {
+        for primitive in shapes() {
+            let (vertices, _) = primitive.geometry();
+            for vertex in &vertices {
+                let [x, y, z, w] = vertex.tangent;
+                let tangent = Vector3::new(x, y, z);
+                assert!((tangent.magnitude() - 1.0).abs() < 1e-3, "{:?} {:?}", primitive, vertex);
+                assert!(tangent.dot(Vector3::from(vertex.normal)).abs() < 1e-3, "{:?} {:?}", primitive, vertex);
+                assert!(w == 1.0 || w == -1.0, "{:?} {:?}", primitive, vertex);
+            }
+        }
+    }
+
+    #[test]
+    fn file_name_round_trip() {
+        for primitive in shapes() {
+            assert_eq!(Primitive::from_file_name(&primitive.file_name()), Some(primitive));
+        }
+        assert_eq!(Primitive::from_file_name("d20.obj"), None);
+    }
+}
@@ -0,0 +1,20 @@
+/// Which renderer draws the scene. Picked once at startup.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum RenderPath {
+    #[default]
+    Forward,
+    Deferred,
+    // forward shading with lights culled per view frustum cluster
+    Clustered,
+}
+
+impl RenderPath {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "forward" => Some(RenderPath::Forward),
+            "deferred" => Some(RenderPath::Deferred),
+            "clustered" => Some(RenderPath::Clustered),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, Quaternion, Rotation3, SquareMatrix, Transform as _, Vector3};
+use crate::camera::Camera;
+use crate::light::Light;
+use crate::model::Model;
+use crate::node::{Node, NodeId};
+
+/// Hierarchy of nodes stored in a flat list and addressed by `NodeId`.
+pub struct Scene {
+    nodes: Vec<Node>,
+    roots: Vec<NodeId>,
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Self {
+            nodes: Vec::new(),
+            roots: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, node: Node, parent: Option<NodeId>) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        self.nodes.push(node);
+        self.attach(id, parent);
+        id
+    }
+
+    /// Moves `id` under `parent`, or to the top level when `parent` is `None`.
+    /// Requests that would make a node its own ancestor are ignored.
+    #[allow(dead_code)]
+    pub fn set_parent(&mut self, id: NodeId, parent: Option<NodeId>) {
+        if let Some(parent) = parent {
+            if self.is_ancestor(id, parent) {
+                log::warn!(
+                    "can't parent {} to its descendant {}",
+                    self.nodes[id.0].name,
+                    self.nodes[parent.0].name
+                );
+                return;
+            }
+        }
+        match self.nodes[id.0].parent {
+            Some(old) => self.nodes[old.0].children.retain(|&child| child != id),
+            None => self.roots.retain(|&root| root != id),
+        }
+        self.attach(id, parent);
+    }
+
+    fn attach(&mut self, id: NodeId, parent: Option<NodeId>) {
+        match parent {
+            Some(parent) => self.nodes[parent.0].children.push(id),
+            None => self.roots.push(id),
+        }
+        let node = &mut self.nodes[id.0];
+        node.parent = parent;
+        node.dirty = true;
+    }
+
+    // true if `ancestor` is `id` or one of its parents
+    fn is_ancestor(&self, ancestor: NodeId, mut id: NodeId) -> bool {
+        loop {
+            if id == ancestor {
+                return true;
+            }
+            match self.nodes[id.0].parent {
+                Some(parent) => id = parent,
+                None => return false,
+            }
+        }
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id.0]
+    }
+
+    pub fn node_mut(&mut self, id: NodeId) -> &mut Node {
+        &mut self.nodes[id.0]
+    }
+
+    #[allow(dead_code)]
+    pub fn find(&self, name: &str) -> Option<NodeId> {
+        self.nodes.iter().position(|node| node.name == name).map(NodeId)
+    }
+
+    pub fn roots(&self) -> &[NodeId] {
+        &self.roots
+    }
+
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Advances every spinning node by one frame.
+    pub fn animate(&mut self) {
+        for node in &mut self.nodes {
+            if let Some((axis, angle)) = node.spin {
+                node.rotate(Quaternion::from_axis_angle(axis.normalize(), angle));
+            }
+        }
+    }
+
+    /// Rebuilds the world matrix of every node whose own or inherited transform changed
+    /// and pushes the result to the attached models, lights and cameras.
+    pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let mut stack: Vec<(NodeId, Matrix4<f32>, bool)> = self.roots.iter()
+            .rev()
+            .map(|&root| (root, Matrix4::identity(), false))
+            .collect();
+
+        while let Some((id, parent_world, parent_changed)) = stack.pop() {
+            let node = &mut self.nodes[id.0];
+            let changed = node.dirty || parent_changed;
+            if changed {
+                node.world = parent_world * node.local_matrix();
+                node.dirty = false;
+                sync_attachments(node, queue);
+            }
+            // instances can also change on their own, upload skips unchanged sets
+            if let Some(instances) = node.model.as_mut().and_then(|model| model.instances.as_mut()) {
+                instances.upload(device, queue);
+            }
+            let world = node.world;
+            stack.extend(node.children.iter().rev().map(|&child| (child, world, changed)));
+        }
+    }
+
+    /// Picks the level of detail of every mesh from its size on screen. Instanced models
+    /// keep full detail, their copies can be at any distance. Call after `update`.
+    pub fn update_lods(&mut self, camera: &Camera) {
+        for model in self.nodes.iter_mut().filter_map(|node| node.model.as_mut()) {
+            if model.instances.is_some() {
+                continue;
+            }
+            let world = model.model_matrix.matrix();
+            for mesh in &mut model.meshes {
+                mesh.update_lod(&world, camera);
+            }
+        }
+    }
+
+    /// Visits every node depth first, parents before their children.
+    pub fn traverse(&self, mut visit: impl FnMut(NodeId, &Node)) {
+        let mut stack: Vec<NodeId> = self.roots.iter().rev().copied().collect();
+        while let Some(id) = stack.pop() {
+            let node = &self.nodes[id.0];
+            visit(id, node);
+            stack.extend(node.children.iter().rev());
+        }
+    }
+
+    /// First node with a light attached, in traversal order.
+    pub fn first_light(&self) -> Option<NodeId> {
+        let mut found = None;
+        self.traverse(|id, node| {
+            if found.is_none() && node.light.is_some() {
+                found = Some(id);
+            }
+        });
+        found
+    }
+
+    /// Every attached light in traversal order.
+    pub fn lights(&self) -> Vec<(NodeId, &Light)> {
+        let mut lights = Vec::new();
+        self.traverse(|id, _| {
+            if let Some(light) = &self.nodes[id.0].light {
+                lights.push((id, light));
+            }
+        });
+        lights
+    }
+
+    /// Every attached model in traversal order.
+    pub fn models(&self) -> Vec<&Model> {
+        let mut models = Vec::new();
+        self.traverse(|id, _| {
+            if let Some(model) = &self.nodes[id.0].model {
+                models.push(model);
+            }
+        });
+        models
+    }
+
+    /// Every attached model, in no particular order.
+    pub fn models_mut(&mut self) -> Vec<&mut Model> {
+        self.nodes.iter_mut().filter_map(|node| node.model.as_mut()).collect()
+    }
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn sync_attachments(node: &mut Node, queue: &wgpu::Queue) {
+    let world = node.world;
+    if let Some(model) = &mut node.model {
+        model.model_matrix.world = world;
+        queue.write_buffer(
+            &model.model_matrix.buffer,
+            0,
+            bytemuck::cast_slice(&[model.model_matrix.to_raw()]),
+        );
+        let matrix = model.model_matrix.matrix();
+        if let Some(instances) = &mut model.instances {
+            instances.set_parent(matrix);
+        }
+    }
+    let position = world.transform_point(Point3::origin());
+    if let Some(light) = &mut node.light {
+        light.uniform.position = position.into();
+        queue.write_buffer(&light.buffer, 0, bytemuck::cast_slice(&[light.uniform]));
+    }
+    // cameras look down the node's -z axis
+    if let Some(camera) = &mut node.camera {
+        let forward = world.transform_vector(-Vector3::unit_z());
+        let up = world.transform_vector(Vector3::unit_y());
+        camera.set_view(position, position + forward, up);
+    }
+}
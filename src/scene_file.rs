@@ -0,0 +1,487 @@
+use std::path::Path;
+use anyhow::Context;
+use cgmath::{Deg, InnerSpace, Point3, Quaternion, Vector3};
+use serde::{Deserialize, Serialize};
+use crate::asset_loader::{AssetLoader, LoadedModel, LoadRequest};
+use crate::asset_manager::AssetManager;
+use crate::camera::Camera;
+use crate::constants::{TERRAIN_CHUNK_SIZE, TERRAIN_RESOLUTION};
+use crate::instance::InstanceSet;
+use crate::light::Light;
+use crate::model::{AlphaMode, HeightMap, Material, Model, Parallax};
+use crate::node::{Node, NodeId};
+use crate::primitives::{create_primitive, Primitive, PRIMITIVE_FOLDER};
+use crate::scene::Scene;
+use crate::transform::Transform;
+
+/// Declarative scene description, stored as RON.
+///
+/// ```ron
+/// SceneDesc(
+///     camera: Some(CameraDesc(position: (0.0, 0.0, 2.0), target: (0.0, 0.0, -1.0), fovy: 90.0, znear: 0.1, zfar: 100.0)),
+///     nodes: [
+///         NodeDesc(
+///             name: "blob",
+///             model: Some(ModelDesc(folder: "models/blob/", file: "blob.obj")),
+///             spin: Some(SpinDesc(axis: (0.0, 1.0, 0.0), degrees: 0.2)),
+///         ),
+///         NodeDesc(
+///             name: "floor",
+///             primitive: Some(PrimitiveDesc(shape: Plane(size: 10.0, subdivisions: 1))),
+///         ),
+///     ],
+/// )
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SceneDesc {
+    #[serde(default)]
+    pub camera: Option<CameraDesc>,
+    #[serde(default)]
+    pub nodes: Vec<NodeDesc>,
+    #[serde(default)]
+    pub terrain: Option<TerrainDesc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CameraDesc {
+    pub position: [f32; 3],
+    pub target: [f32; 3],
+    pub fovy: f32,
+    pub znear: f32,
+    pub zfar: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeDesc {
+    pub name: String,
+    #[serde(default)]
+    pub transform: TransformDesc,
+    #[serde(default)]
+    pub model: Option<ModelDesc>,
+    // generated model for blockouts, ignored when `model` is set
+    #[serde(default)]
+    pub primitive: Option<PrimitiveDesc>,
+    #[serde(default)]
+    pub light: Option<LightDesc>,
+    #[serde(default)]
+    pub spin: Option<SpinDesc>,
+    #[serde(default)]
+    pub children: Vec<NodeDesc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TransformDesc {
+    pub translation: [f32; 3],
+    // quaternion as (x, y, z, w)
+    pub rotation: [f32; 4],
+    pub scale: [f32; 3],
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelDesc {
+    // relative to res/
+    pub folder: String,
+    pub file: String,
+    // overrides for the materials read from the MTL file
+    #[serde(default)]
+    pub materials: Vec<MaterialDesc>,
+    // draws the model once per instance instead of once at the node
+    #[serde(default)]
+    pub instances: Option<InstancesDesc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrimitiveDesc {
+    pub shape: Primitive,
+    #[serde(default)]
+    pub instances: Option<InstancesDesc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum InstancesDesc {
+    List(Vec<TransformDesc>),
+    // count.x * count.y * count.z copies centered on the node
+    Grid {
+        count: [u32; 3],
+        spacing: [f32; 3],
+    },
+}
+
+impl InstancesDesc {
+    pub fn transforms(&self) -> Vec<Transform> {
+        match self {
+            InstancesDesc::List(list) => list.iter().map(Transform::from).collect(),
+            InstancesDesc::Grid { count, spacing } => {
+                let offset = |axis: usize, i: u32| (i as f32 - (count[axis] as f32 - 1.0) * 0.5) * spacing[axis];
+                let mut transforms = Vec::new();
+                for x in 0..count[0] {
+                    for y in 0..count[1] {
+                        for z in 0..count[2] {
+                            let translation = Vector3::new(offset(0, x), offset(1, y), offset(2, z));
+                            transforms.push(Transform::from_translation(translation));
+                        }
+                    }
+                }
+                transforms
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaterialDesc {
+    pub name: String,
+    #[serde(default)]
+    pub opacity: Option<f32>,
+    #[serde(default)]
+    pub alpha_mode: Option<AlphaMode>,
+    // grayscale image relative to res/ turning on parallax occlusion mapping, replaces
+    // the MTL `disp` map
+    #[serde(default)]
+    pub height_map: Option<String>,
+    // replaces the MTL parallax settings, needs a height map from here or the MTL file
+    #[serde(default)]
+    pub parallax: Option<Parallax>,
+}
+
+/// Heightmap terrain centered on the origin, see `Terrain`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TerrainDesc {
+    // grayscale image relative to res/, black is the lowest point
+    pub heightmap: String,
+    // along X and Z
+    pub size: f32,
+    // of white
+    pub height: f32,
+    // quads along each side, rounded up to whole chunks
+    #[serde(default = "default_terrain_resolution")]
+    pub resolution: u32,
+    // quads along each side of a chunk, rounded up to a power of two
+    #[serde(default = "default_terrain_chunk_size")]
+    pub chunk_size: u32,
+    // RGBA image relative to res/ whose channels weight the layers, generated from
+    // the slope and height when missing
+    #[serde(default)]
+    pub splat_map: Option<String>,
+    // up to four
+    #[serde(default)]
+    pub layers: Vec<TerrainLayerDesc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TerrainLayerDesc {
+    // relative to res/
+    pub texture: String,
+    // repeats across the whole terrain
+    pub tiling: f32,
+}
+
+fn default_terrain_resolution() -> u32 {
+    TERRAIN_RESOLUTION
+}
+
+fn default_terrain_chunk_size() -> u32 {
+    TERRAIN_CHUNK_SIZE
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LightDesc {
+    pub color: [f32; 3],
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpinDesc {
+    pub axis: [f32; 3],
+    // per frame
+    pub degrees: f32,
+}
+
+impl Default for TransformDesc {
+    fn default() -> Self {
+        Transform::identity().into()
+    }
+}
+
+impl From<Transform> for TransformDesc {
+    fn from(transform: Transform) -> Self {
+        let q = transform.rotation;
+        Self {
+            translation: transform.translation.into(),
+            rotation: [q.v.x, q.v.y, q.v.z, q.s],
+            scale: transform.scale.into(),
+        }
+    }
+}
+
+impl From<&TransformDesc> for Transform {
+    fn from(desc: &TransformDesc) -> Self {
+        let [x, y, z, w] = desc.rotation;
+        Self {
+            translation: desc.translation.into(),
+            rotation: Quaternion::new(w, x, y, z).normalize(),
+            scale: desc.scale.into(),
+        }
+    }
+}
+
+impl CameraDesc {
+    pub fn from_camera(camera: &Camera) -> Self {
+        Self {
+            position: camera.position().into(),
+            target: camera.target().into(),
+            fovy: camera.fovy,
+            znear: camera.znear,
+            zfar: camera.zfar,
+        }
+    }
+
+    pub fn apply(&self, camera: &mut Camera) {
+        camera.set_view(Point3::from(self.position), Point3::from(self.target), Vector3::unit_y());
+        camera.fovy = self.fovy;
+        camera.znear = self.znear;
+        camera.zfar = self.zfar;
+    }
+}
+
+impl SceneDesc {
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        Ok(ron::from_str(text)?)
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read scene {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("failed to parse scene {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default().struct_names(true))?;
+        std::fs::write(path, text)
+            .with_context(|| format!("failed to write scene {}", path.display()))
+    }
+
+    /// Captures the current state of `scene` and `camera`.
+    pub fn from_scene(scene: &Scene, camera: &Camera) -> Self {
+        Self {
+            camera: Some(CameraDesc::from_camera(camera)),
+            nodes: scene.roots().iter().map(|&id| describe_node(scene, id)).collect(),
+            terrain: None,
+        }
+    }
+
+    /// Builds the node hierarchy right away with placeholder models and requests the
+    /// real ones from `loader`, to be swapped in by `PendingModels::update`. Primitives
+    /// are generated right away.
+    pub fn build_in_background(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        material_layout: &wgpu::BindGroupLayout,
+        loader: &mut AssetLoader,
+        assets: &mut AssetManager,
+    ) -> (Scene, PendingModels) {
+        let mut pending = PendingModels::default();
+        let scene = self.build_nodes(device, |id, desc| {
+            if let Some(model_desc) = &desc.model {
+                let request = loader.request_model(&model_desc.folder, &model_desc.file, assets);
+                pending.models.push((request, id, model_desc.clone()));
+                return Some(loader.placeholder(device, &model_desc.folder, &model_desc.file));
+            }
+            let primitive_desc = desc.primitive.as_ref()?;
+            match create_primitive(primitive_desc.shape, device, queue, material_layout, assets) {
+                Ok(model) => Some(model),
+                Err(e) => {
+                    assets.report.error(format!("failed to create the primitive of node {:?}: {:#}", desc.name, e));
+                    None
+                }
+            }
+        });
+        (scene, pending)
+    }
+
+    fn build_nodes(
+        &self,
+        device: &wgpu::Device,
+        mut create_model: impl FnMut(NodeId, &NodeDesc) -> Option<Model>,
+    ) -> Scene {
+        let mut scene = Scene::new();
+        // parents are added before their children so they can be referenced by id
+        let mut pending: Vec<(&NodeDesc, Option<NodeId>)> = self.nodes.iter().rev().map(|desc| (desc, None)).collect();
+
+        while let Some((desc, parent)) = pending.pop() {
+            let mut node = Node::new(desc.name.clone());
+            node.set_transform(Transform::from(&desc.transform));
+            node.spin = desc.spin.as_ref().map(|spin| (Vector3::from(spin.axis), Deg(spin.degrees)));
+
+            if let Some(light_desc) = &desc.light {
+                // positioned from the node by the first Scene::update
+                node.light = Some(Light::new(device, Vector3::new(0.0, 0.0, 0.0), light_desc.color.into()));
+            }
+
+            let id = scene.add(node, parent);
+            if let Some(mut model) = create_model(id, desc) {
+                if let Some(instances) = desc.instances() {
+                    model.instances = Some(InstanceSet::with_transforms(device, instances.transforms()));
+                }
+                scene.node_mut(id).model = Some(model);
+            }
+            pending.extend(desc.children.iter().rev().map(|child| (child, Some(id))));
+        }
+
+        scene
+    }
+}
+
+impl NodeDesc {
+    fn instances(&self) -> Option<&InstancesDesc> {
+        match (&self.model, &self.primitive) {
+            (Some(model_desc), _) => model_desc.instances.as_ref(),
+            (None, Some(primitive_desc)) => primitive_desc.instances.as_ref(),
+            (None, None) => None,
+        }
+    }
+}
+
+impl ModelDesc {
+    fn apply_overrides(
+        &self,
+        model: &mut Model,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        material_layout: &wgpu::BindGroupLayout,
+        assets: &mut AssetManager,
+    ) {
+        for override_desc in &self.materials {
+            // the other models loaded from the file keep the shared material
+            let Some(material) = model.materials.iter_mut().find(|m| m.name == override_desc.name) else {
+                log::warn!("{} has no material named {:?}", self.file, override_desc.name);
+                continue;
+            };
+            let mut height_map = material.height_map.clone();
+            if let Some(file_name) = &override_desc.height_map {
+                match assets.load_texture("", file_name, device, queue, true) {
+                    Ok(texture) => {
+                        let parallax = height_map.map(|height_map| height_map.parallax).unwrap_or_default();
+                        height_map = Some(HeightMap { file: file_name.clone(), texture, parallax });
+                    }
+                    Err(e) => assets.report.warn(format!(
+                        "height map {:?} of material {:?} in {} failed to load: {:#}",
+                        file_name,
+                        override_desc.name,
+                        self.file,
+                        e,
+                    )),
+                }
+            }
+            if let Some(parallax) = override_desc.parallax {
+                match &mut height_map {
+                    Some(height_map) => height_map.parallax = parallax,
+                    None => assets.report.warn(format!(
+                        "material {:?} in {} has parallax settings but no height map",
+                        override_desc.name,
+                        self.file,
+                    )),
+                }
+            }
+            *material = assets.materials.add(Material::new(
+                device,
+                material_layout,
+                material.name.clone(),
+                material.diffuse_texture.clone(),
+                override_desc.opacity.unwrap_or(material.opacity),
+                override_desc.alpha_mode.unwrap_or(material.alpha_mode),
+                height_map,
+            ));
+        }
+    }
+}
+
+/// Models of a scene built by `SceneDesc::build_in_background` that are still loading.
+#[derive(Default)]
+pub struct PendingModels {
+    models: Vec<(LoadRequest, NodeId, ModelDesc)>,
+}
+
+impl PendingModels {
+    /// Swaps finished models into their nodes. Models that failed to load
+    /// keep their placeholder.
+    pub fn update(
+        &mut self,
+        loaded: &[LoadedModel],
+        scene: &mut Scene,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        material_layout: &wgpu::BindGroupLayout,
+        assets: &mut AssetManager,
+    ) {
+        for loaded_model in loaded {
+            let Some(index) = self.models.iter().position(|(request, _, _)| *request == loaded_model.request) else {
+                continue;
+            };
+            let (_, id, model_desc) = self.models.swap_remove(index);
+            let node = scene.node_mut(id);
+            let name = node.name.clone();
+            let Some(model) = &mut node.model else {
+                continue;
+            };
+            match &loaded_model.result {
+                Ok(model_assets) => {
+                    model.set_assets(model_assets);
+                    model_desc.apply_overrides(model, device, queue, material_layout, assets);
+                }
+                Err(e) => assets.report.error(format!("failed to load model of node {:?}: {:#}", name, e)),
+            }
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.models.is_empty()
+    }
+}
+
+fn describe_node(scene: &Scene, id: NodeId) -> NodeDesc {
+    let node = scene.node(id);
+    let instances = |model: &Model| model.instances.as_ref().map(|instances| InstancesDesc::List(
+        instances.iter().map(|(_, transform)| (*transform).into()).collect()
+    ));
+    let primitive = node.model.as_ref()
+        .filter(|model| model.folder == PRIMITIVE_FOLDER)
+        .and_then(|model| Some(PrimitiveDesc {
+            shape: Primitive::from_file_name(&model.file_name)?,
+            instances: instances(model),
+        }));
+    NodeDesc {
+        name: node.name.clone(),
+        transform: (*node.transform()).into(),
+        model: node.model.as_ref().filter(|_| primitive.is_none()).map(|model| ModelDesc {
+            folder: model.folder.clone(),
+            file: model.file_name.clone(),
+            // a placeholder's materials aren't the file's
+            materials: if model.loading {
+                Vec::new()
+            } else {
+                model.materials.iter()
+                    .map(|material| MaterialDesc {
+                        name: material.name.clone(),
+                        opacity: Some(material.opacity),
+                        alpha_mode: Some(material.alpha_mode),
+                        height_map: material.height_map.as_ref().map(|height_map| height_map.file.clone()),
+                        parallax: material.height_map.as_ref().map(|height_map| height_map.parallax),
+                    })
+                    .collect()
+            },
+            instances: instances(model),
+        }),
+        primitive,
+        light: node.light.as_ref().map(|light| LightDesc {
+            color: light.uniform.color,
+        }),
+        spin: node.spin.map(|(axis, angle)| SpinDesc {
+            axis: axis.into(),
+            degrees: angle.0,
+        }),
+        children: node.children().iter().map(|&child| describe_node(scene, child)).collect(),
+    }
+}
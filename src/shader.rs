@@ -0,0 +1,32 @@
+use anyhow::Context;
+use crate::model::load_string;
+
+/// Compiles `res/shaders/<file_name>`. Shaders are read at runtime instead of being
+/// embedded so edits can be hot reloaded, see `HotReload`.
+pub fn create_shader_module(device: &wgpu::Device, file_name: &str) -> anyhow::Result<wgpu::ShaderModule> {
+    let source = load_string("shaders", file_name)
+        .with_context(|| format!("reading shader {}", file_name))?;
+    Ok(device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some(file_name),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+    }))
+}
+
+/// Runs `create` in a validation error scope, so a shader that can't be read, doesn't compile
+/// or no longer matches its pipeline is logged and `None` returned instead of the usual panic.
+pub fn try_create<T>(device: &wgpu::Device, what: &str, create: impl FnOnce() -> anyhow::Result<T>) -> Option<T> {
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let value = create();
+    let error = pollster::block_on(device.pop_error_scope());
+    match (value, error) {
+        (Err(e), _) => {
+            log::error!("keeping the old {}: {:#}", what, e);
+            None
+        }
+        (Ok(_), Some(error)) => {
+            log::error!("keeping the old {}: {}", what, error);
+            None
+        }
+        (Ok(value), None) => Some(value),
+    }
+}
@@ -1,10 +1,9 @@
 use wgpu::{FragmentState, VertexState};
 use crate::camera::create_camera_bind_group_layout;
-use crate::graphics_context::GraphicsContext;
 use crate::light::create_light_bind_group_layout;
-use crate::model::{ModelVertex, Vertex};
-use crate::model_matrix::{ModelMatrix, RawModelMatrix};
-use crate::texture::{create_texture_bind_group_layout};
+use crate::model::{create_material_bind_group_layout, ModelVertex, Vertex};
+use crate::model_matrix::RawModelMatrix;
+use crate::shader::create_shader_module;
 pub struct SimplePipeline {
     pub render_pipeline: wgpu::RenderPipeline,
 }
@@ -13,11 +12,33 @@ impl SimplePipeline {
     pub fn new(
         device: &wgpu::Device,
         config: &wgpu::SurfaceConfiguration,
-    ) -> Self {
+    ) -> anyhow::Result<Self> {
+        Self::create(device, config, "Render Pipeline", wgpu::BlendState::REPLACE, true)
+    }
+
+    // alpha blended variant for the transparent pass, it tests against
+    // the opaque depth but does not write it so sorted meshes don't occlude each other
+    pub fn transparent(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+    ) -> anyhow::Result<Self> {
+        Self::create(device, config, "Transparent Render Pipeline", wgpu::BlendState::ALPHA_BLENDING, false)
+    }
+
+    // shader files the pipelines are built from
+    pub const SHADERS: &'static [&'static str] = &["shader.wgsl"];
+
+    fn create(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        label: &str,
+        blend: wgpu::BlendState,
+        depth_write_enabled: bool,
+    ) -> anyhow::Result<Self> {
 
         // all the bind groups layouts used by this pipeline
         let layouts = &[
-            &create_texture_bind_group_layout(device),
+            &create_material_bind_group_layout(device),
             &create_camera_bind_group_layout(device),
             &create_light_bind_group_layout(device),
         ];
@@ -30,13 +51,11 @@ impl SimplePipeline {
             }
         );
 
-        let shader = device.create_shader_module(
-            wgpu::include_wgsl!("../res/shaders/shader.wgsl")
-        );
+        let shader = create_shader_module(device, "shader.wgsl")?;
 
         let render_pipeline = device.create_render_pipeline(
             &wgpu::RenderPipelineDescriptor {
-                label: Some("Render Pipeline"),
+                label: Some(label),
                 layout: Some(&layout),
                 vertex: VertexState {
                     module: &shader,
@@ -48,7 +67,7 @@ impl SimplePipeline {
                     entry_point: "fs_main",
                     targets: &[Some(wgpu::ColorTargetState {
                         format: config.format,
-                        blend: Some(wgpu::BlendState::REPLACE),
+                        blend: Some(blend),
                         write_mask: wgpu::ColorWrites::ALL,
                     })],
                 }),
@@ -63,7 +82,7 @@ impl SimplePipeline {
                 },
                 depth_stencil: Some(wgpu::DepthStencilState {
                     format: wgpu::TextureFormat::Depth32Float,
-                    depth_write_enabled: true,
+                    depth_write_enabled,
                     depth_compare: wgpu::CompareFunction::Less,
                     stencil: wgpu::StencilState::default(),
                     bias: wgpu::DepthBiasState::default(),
@@ -74,8 +93,8 @@ impl SimplePipeline {
             }
         );
 
-        Self {
+        Ok(Self {
             render_pipeline,
-        }
+        })
     }
-}
\ No newline at end of file
+}
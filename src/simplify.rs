@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use cgmath::{InnerSpace, Vector3};
+
+/// Symmetric 4x4 matrix summing the squared distances to a set of planes
+/// (Garland and Heckbert, "Surface Simplification Using Quadric Error Metrics").
+#[derive(Debug, Copy, Clone, Default)]
+struct Quadric {
+    // upper triangle: xx xy xz xw yy yz yw zz zw ww
+    m: [f64; 10],
+}
+
+impl Quadric {
+    fn from_plane(normal: Vector3<f64>, d: f64) -> Self {
+        let (a, b, c) = (normal.x, normal.y, normal.z);
+        Self {
+            m: [
+                a * a, a * b, a * c, a * d,
+                b * b, b * c, b * d,
+                c * c, c * d,
+                d * d,
+            ],
+        }
+    }
+
+    fn add(&mut self, other: &Quadric) {
+        for (a, b) in self.m.iter_mut().zip(other.m) {
+            *a += b;
+        }
+    }
+
+    fn error(&self, p: Vector3<f64>) -> f64 {
+        let m = &self.m;
+        let (x, y, z) = (p.x, p.y, p.z);
+        let error = m[0] * x * x + 2.0 * m[1] * x * y + 2.0 * m[2] * x * z + 2.0 * m[3] * x
+            + m[4] * y * y + 2.0 * m[5] * y * z + 2.0 * m[6] * y
+            + m[7] * z * z + 2.0 * m[8] * z
+            + m[9];
+        // rounding can push an exact fit slightly below zero
+        error.max(0.0)
+    }
+}
+
+/// Indices of the simplified mesh and the largest distance a surface point moved.
+pub struct Simplified {
+    pub indices: Vec<u32>,
+    pub error: f32,
+}
+
+/// Reduces `indices` towards `target_index_count` by collapsing edges onto one of their
+/// vertices, cheapest quadric error first. The result indexes the original vertices so
+/// every level of detail can share one vertex buffer.
+///
+/// Open borders and vertices split by UV or normal seams never move, which keeps the
+/// outline and the texture mapping intact at the cost of stopping early on seam heavy meshes.
+pub fn simplify(positions: &[[f32; 3]], indices: &[u32], target_index_count: usize) -> Simplified {
+    let position = |v: u32| {
+        let p = positions[v as usize];
+        Vector3::new(p[0] as f64, p[1] as f64, p[2] as f64)
+    };
+
+    let locked = locked_vertices(positions, indices);
+
+    let mut quadrics = vec![Quadric::default(); positions.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [position(triangle[0]), position(triangle[1]), position(triangle[2])];
+        let normal = (b - a).cross(c - a);
+        if normal.magnitude2() == 0.0 {
+            continue;
+        }
+        let normal = normal.normalize();
+        let quadric = Quadric::from_plane(normal, -normal.dot(a));
+        for &v in triangle {
+            quadrics[v as usize].add(&quadric);
+        }
+    }
+
+    let mut indices = indices.to_vec();
+    let mut max_error = 0.0f64;
+
+    // every pass collapses a set of edges that don't share any triangles
+    while indices.len() > target_index_count {
+        let triangles_to_remove = (indices.len() - target_index_count) / 3;
+        let adjacency = Adjacency::new(positions.len(), &indices);
+
+        let mut candidates = Vec::new();
+        for triangle in indices.chunks_exact(3) {
+            for i in 0..3 {
+                let (from, to) = (triangle[i], triangle[(i + 1) % 3]);
+                for (from, to) in [(from, to), (to, from)] {
+                    if locked[from as usize] {
+                        continue;
+                    }
+                    let mut quadric = quadrics[from as usize];
+                    quadric.add(&quadrics[to as usize]);
+                    candidates.push((quadric.error(position(to)), from, to));
+                }
+            }
+        }
+        candidates.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut remap = (0..positions.len() as u32).collect::<Vec<_>>();
+        let mut touched = vec![false; positions.len()];
+        let mut removed = 0;
+        for (error, from, to) in candidates {
+            if removed >= triangles_to_remove {
+                break;
+            }
+            if touched[from as usize] || touched[to as usize] {
+                continue;
+            }
+            if flips_triangle(&adjacency, &indices, from, to, &position) {
+                continue;
+            }
+
+            remap[from as usize] = to;
+            let quadric = quadrics[from as usize];
+            quadrics[to as usize].add(&quadric);
+            max_error = max_error.max(error);
+            for &triangle in adjacency.triangles(from) {
+                let corners = &indices[triangle * 3..triangle * 3 + 3];
+                if corners.contains(&to) {
+                    removed += 1;
+                }
+                for &v in corners {
+                    touched[v as usize] = true;
+                }
+            }
+        }
+
+        if removed == 0 {
+            break;
+        }
+
+        indices = indices.chunks_exact(3)
+            .map(|triangle| [
+                remap[triangle[0] as usize],
+                remap[triangle[1] as usize],
+                remap[triangle[2] as usize],
+            ])
+            .filter(|[a, b, c]| a != b && b != c && c != a)
+            .flatten()
+            .collect();
+    }
+
+    Simplified {
+        indices,
+        error: max_error.sqrt() as f32,
+    }
+}
+
+// triangles around every vertex, rebuilt each pass
+struct Adjacency {
+    offsets: Vec<usize>,
+    triangles: Vec<usize>,
+}
+
+impl Adjacency {
+    fn new(vertex_count: usize, indices: &[u32]) -> Self {
+        let mut offsets = vec![0; vertex_count + 1];
+        for &v in indices {
+            offsets[v as usize + 1] += 1;
+        }
+        for i in 0..vertex_count {
+            offsets[i + 1] += offsets[i];
+        }
+        let mut fill = offsets.clone();
+        let mut triangles = vec![0; indices.len()];
+        for (i, &v) in indices.iter().enumerate() {
+            triangles[fill[v as usize]] = i / 3;
+            fill[v as usize] += 1;
+        }
+        Self { offsets, triangles }
+    }
+
+    fn triangles(&self, vertex: u32) -> &[usize] {
+        &self.triangles[self.offsets[vertex as usize]..self.offsets[vertex as usize + 1]]
+    }
+}
+
+// moving `from` onto `to` must not turn any remaining triangle around
+fn flips_triangle(
+    adjacency: &Adjacency,
+    indices: &[u32],
+    from: u32,
+    to: u32,
+    position: &impl Fn(u32) -> Vector3<f64>,
+) -> bool {
+    adjacency.triangles(from).iter().any(|&triangle| {
+        let corners = [indices[triangle * 3], indices[triangle * 3 + 1], indices[triangle * 3 + 2]];
+        if corners.contains(&to) {
+            return false;
+        }
+        let [a, b, c] = corners.map(position);
+        let moved = corners.map(|v| if v == from { position(to) } else { position(v) });
+        let before = (b - a).cross(c - a);
+        let after = (moved[1] - moved[0]).cross(moved[2] - moved[0]);
+        before.dot(after) <= 0.0
+    })
+}
+
+// border vertices and vertices sharing their position with another vertex
+fn locked_vertices(positions: &[[f32; 3]], indices: &[u32]) -> Vec<bool> {
+    // weld by exact position so seams aren't mistaken for borders
+    let mut first_at = HashMap::new();
+    let mut welded = Vec::with_capacity(positions.len());
+    let mut locked = vec![false; positions.len()];
+    for (i, p) in positions.iter().enumerate() {
+        let key = p.map(f32::to_bits);
+        let first = *first_at.entry(key).or_insert(i as u32);
+        if first != i as u32 {
+            locked[i] = true;
+            locked[first as usize] = true;
+        }
+        welded.push(first);
+    }
+
+    let mut edges = HashMap::<(u32, u32), u32>::new();
+    for triangle in indices.chunks_exact(3) {
+        for i in 0..3 {
+            let (a, b) = (welded[triangle[i] as usize], welded[triangle[(i + 1) % 3] as usize]);
+            *edges.entry((a.min(b), a.max(b))).or_default() += 1;
+        }
+    }
+    for triangle in indices.chunks_exact(3) {
+        for i in 0..3 {
+            let (va, vb) = (triangle[i], triangle[(i + 1) % 3]);
+            let (a, b) = (welded[va as usize], welded[vb as usize]);
+            if edges[&(a.min(b), a.max(b))] == 1 {
+                locked[va as usize] = true;
+                locked[vb as usize] = true;
+            }
+        }
+    }
+    locked
+}
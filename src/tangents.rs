@@ -0,0 +1,52 @@
+use cgmath::{InnerSpace, Vector2, Vector3};
+use crate::model::ModelVertex;
+
+/// Fills in `ModelVertex::tangent` from the normals and texture coordinates of
+/// an indexed triangle list.
+///
+/// Tangents of the triangles around a vertex are summed, weighted by their area in
+/// texture space, and made perpendicular to the normal. The bitangent `cross(normal, tangent) * w`
+/// points up the texture, where `v` decreases since it is flipped on import, so `w` is -1
+/// where the texture is mirrored.
+pub fn generate_tangents(vertices: &mut [ModelVertex], indices: &[u32]) {
+    let zero = Vector3::new(0.0, 0.0, 0.0);
+    let mut tangents = vec![zero; vertices.len()];
+    let mut bitangents = vec![zero; vertices.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| &vertices[triangle[i] as usize]);
+        let e1 = Vector3::from(b.position) - Vector3::from(a.position);
+        let e2 = Vector3::from(c.position) - Vector3::from(a.position);
+        let d1 = Vector2::from(b.tex_coords) - Vector2::from(a.tex_coords);
+        let d2 = Vector2::from(c.tex_coords) - Vector2::from(a.tex_coords);
+        let det = d1.x * d2.y - d2.x * d1.y;
+        if det.abs() <= f32::EPSILON {
+            continue;
+        }
+        // not divided by the determinant's magnitude, so large triangles count more
+        let sign = det.signum();
+        let tangent = (e1 * d2.y - e2 * d1.y) * sign;
+        let bitangent = (e1 * d2.x - e2 * d1.x) * sign;
+        for &i in triangle {
+            tangents[i as usize] += tangent;
+            bitangents[i as usize] += bitangent;
+        }
+    }
+
+    for (i, vertex) in vertices.iter_mut().enumerate() {
+        let normal = Vector3::from(vertex.normal);
+        let mut tangent = tangents[i] - normal * normal.dot(tangents[i]);
+        if tangent.magnitude2() <= f32::EPSILON * f32::EPSILON {
+            // no usable texture coordinates, any direction along the surface will do
+            tangent = any_perpendicular(normal);
+        }
+        let tangent = tangent.normalize();
+        let handedness = if normal.cross(tangent).dot(bitangents[i]) < 0.0 { -1.0 } else { 1.0 };
+        vertex.tangent = [tangent.x, tangent.y, tangent.z, handedness];
+    }
+}
+
+fn any_perpendicular(normal: Vector3<f32>) -> Vector3<f32> {
+    let axis = if normal.x.abs() < 0.9 { Vector3::unit_x() } else { Vector3::unit_y() };
+    let tangent = axis - normal * normal.dot(axis);
+    if tangent.magnitude2() > 0.0 { tangent } else { Vector3::unit_x() }
+}
@@ -0,0 +1,579 @@
+use anyhow::Context;
+use cgmath::{InnerSpace, Point3, Vector3};
+use wgpu::util::DeviceExt;
+use wgpu::{FragmentState, VertexState};
+use crate::asset_manager::{AssetManager, Handle};
+use crate::bounds::{Aabb, BoundingSphere};
+use crate::camera::{create_camera_bind_group_layout, Camera};
+use crate::constants::{TERRAIN_LOD_LEVELS, TERRAIN_LOD_SCREEN_SIZE};
+use crate::culling::Frustum;
+use crate::light::create_light_bind_group_layout;
+use crate::lod::{screen_size, select_lod, MeshLod};
+use crate::model::{ModelVertex, Vertex};
+use crate::scene_file::TerrainDesc;
+use crate::shader::{create_shader_module, try_create};
+use crate::texture::Texture;
+use crate::vfs::vfs;
+
+// one per splat map channel
+pub const MAX_TERRAIN_LAYERS: usize = 4;
+// slope, 1 - normal.y, over which the generated splat map blends from the first layer to the second
+const STEEP_SLOPE: (f32, f32) = (0.1, 0.3);
+// fraction of the terrain height over which it blends to the third layer
+const HIGH_GROUND: (f32, f32) = (0.6, 0.8);
+
+/// Heights on a square grid `size` world units wide, centered on the origin. This is
+/// the surface a `Terrain` draws at full detail.
+pub struct HeightField {
+    // along each side
+    samples: u32,
+    size: f32,
+    heights: Vec<f32>,
+}
+
+impl HeightField {
+    /// Resamples the brightness of `image` onto `quads` x `quads` cells,
+    /// black at 0 and white at `height`.
+    pub fn from_image(image: &image::DynamicImage, quads: u32, size: f32, height: f32) -> Self {
+        let image = image.to_luma32f();
+        let (width, depth) = image.dimensions();
+        let pixel = |x: u32, y: u32| image.get_pixel(x.min(width - 1), y.min(depth - 1))[0];
+        let samples = quads + 1;
+        let mut heights = Vec::with_capacity((samples * samples) as usize);
+        for j in 0..samples {
+            for i in 0..samples {
+                // the outermost pixels land on the edges of the terrain
+                let x = i as f32 / quads as f32 * (width - 1) as f32;
+                let y = j as f32 / quads as f32 * (depth - 1) as f32;
+                let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+                let (fx, fy) = (x.fract(), y.fract());
+                let top = pixel(x0, y0) * (1.0 - fx) + pixel(x0 + 1, y0) * fx;
+                let bottom = pixel(x0, y0 + 1) * (1.0 - fx) + pixel(x0 + 1, y0 + 1) * fx;
+                heights.push((top * (1.0 - fy) + bottom * fy) * height);
+            }
+        }
+        Self { samples, size, heights }
+    }
+
+    #[allow(dead_code)]
+    pub fn size(&self) -> f32 {
+        self.size
+    }
+
+    pub fn quads(&self) -> u32 {
+        self.samples - 1
+    }
+
+    fn spacing(&self) -> f32 {
+        self.size / self.quads() as f32
+    }
+
+    /// Height of the sample in column `i` and row `j`, clamped to the grid.
+    pub fn sample(&self, i: i64, j: i64) -> f32 {
+        let last = self.samples as i64 - 1;
+        self.heights[(j.clamp(0, last) * self.samples as i64 + i.clamp(0, last)) as usize]
+    }
+
+    pub fn position(&self, i: u32, j: u32) -> Point3<f32> {
+        let half = self.size * 0.5;
+        let spacing = self.spacing();
+        Point3::new(i as f32 * spacing - half, self.sample(i as i64, j as i64), j as f32 * spacing - half)
+    }
+
+    // from the neighbouring samples, so chunks agree along their borders
+    fn sample_normal(&self, i: u32, j: u32) -> Vector3<f32> {
+        let (i, j) = (i as i64, j as i64);
+        let dx = (self.sample(i + 1, j) - self.sample(i - 1, j)) / (2.0 * self.spacing());
+        let dz = (self.sample(i, j + 1) - self.sample(i, j - 1)) / (2.0 * self.spacing());
+        Vector3::new(-dx, 1.0, -dz).normalize()
+    }
+
+    /// Height of the surface as drawn at full detail, `None` outside the terrain.
+    #[allow(dead_code)]
+    pub fn height_at(&self, x: f32, z: f32) -> Option<f32> {
+        let (i, j, u, v) = self.cell(x, z)?;
+        Some(self.triangle_height(i, j, 1, u, v))
+    }
+
+    /// Normal of the triangle drawn at full detail, `None` outside the terrain.
+    #[allow(dead_code)]
+    pub fn normal_at(&self, x: f32, z: f32) -> Option<Vector3<f32>> {
+        let (i, j, u, v) = self.cell(x, z)?;
+        let h = |di: i64, dj: i64| self.sample(i + di, j + dj);
+        // the same split as `triangle_height`
+        let (du, dv) = if u >= v {
+            (h(1, 0) - h(0, 0), h(1, 1) - h(1, 0))
+        } else {
+            (h(1, 1) - h(0, 1), h(0, 1) - h(0, 0))
+        };
+        Some(Vector3::new(-du / self.spacing(), 1.0, -dv / self.spacing()).normalize())
+    }
+
+    /// Point on the surface straight above or below (`x`, `z`), for placing objects.
+    #[allow(dead_code)]
+    pub fn surface_point(&self, x: f32, z: f32) -> Option<Point3<f32>> {
+        self.height_at(x, z).map(|y| Point3::new(x, y, z))
+    }
+
+    // cell containing the point and the position inside it from 0 to 1
+    fn cell(&self, x: f32, z: f32) -> Option<(i64, i64, f32, f32)> {
+        let quads = self.quads() as f32;
+        let fx = (x / self.size + 0.5) * quads;
+        let fz = (z / self.size + 0.5) * quads;
+        if !(0.0..=quads).contains(&fx) || !(0.0..=quads).contains(&fz) {
+            return None;
+        }
+        let (i, j) = (fx.floor().min(quads - 1.0), fz.floor().min(quads - 1.0));
+        Some((i as i64, j as i64, fx - i, fz - j))
+    }
+
+    // height inside the cell of `step` x `step` quads at (i, j), split from
+    // corner (0, 0) to (1, 1) like `chunk_indices`
+    fn triangle_height(&self, i: i64, j: i64, step: i64, u: f32, v: f32) -> f32 {
+        let h = |di: i64, dj: i64| self.sample(i + di * step, j + dj * step);
+        if u >= v {
+            h(0, 0) + (h(1, 0) - h(0, 0)) * u + (h(1, 1) - h(1, 0)) * v
+        } else {
+            h(0, 0) + (h(1, 1) - h(0, 1)) * u + (h(0, 1) - h(0, 0)) * v
+        }
+    }
+
+    // largest height difference between the full grid and every `step`th sample of it
+    fn level_error(&self, step: u32) -> f32 {
+        let step = step as i64;
+        let mut error = 0.0f32;
+        for j in 0..self.samples as i64 {
+            for i in 0..self.samples as i64 {
+                let (ci, cj) = ((i / step * step).min(self.quads() as i64 - step), (j / step * step).min(self.quads() as i64 - step));
+                let (u, v) = ((i - ci) as f32 / step as f32, (j - cj) as f32 / step as f32);
+                error = error.max((self.triangle_height(ci, cj, step, u, v) - self.sample(i, j)).abs());
+            }
+        }
+        error
+    }
+
+    /// Layer weights from the slope and height, the first layer on flat low ground, the second
+    /// on steep slopes and the third on high ground. One texel per sample.
+    pub fn generate_splat_map(&self, height: f32) -> image::RgbaImage {
+        image::RgbaImage::from_fn(self.samples, self.samples, |i, j| {
+            let slope = 1.0 - self.sample_normal(i, j).y;
+            let steep = smoothstep(STEEP_SLOPE.0, STEEP_SLOPE.1, slope);
+            let high = smoothstep(HIGH_GROUND.0, HIGH_GROUND.1, self.sample(i as i64, j as i64) / height.max(f32::EPSILON));
+            let weights = [(1.0 - steep) * (1.0 - high), steep, (1.0 - steep) * high, 0.0];
+            image::Rgba(weights.map(|w| (w * 255.0).round() as u8))
+        })
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// A square piece of the terrain, culled and given a level of detail on its own.
+pub struct TerrainChunk {
+    vertex_buffer: wgpu::Buffer,
+    pub bounds: Aabb,
+    pub bounding_sphere: BoundingSphere,
+    // 0 draws the full index buffer, n draws lods[n - 1]
+    pub lod: usize,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct TerrainUniform {
+    // repeats of each layer's texture across the terrain
+    tiling: [f32; 4],
+    // 1 for the layers that have a texture
+    layer_mask: [f32; 4],
+}
+
+/// Heightmap terrain, see `TerrainDesc`. Split into chunks that share their index buffers,
+/// each level of detail drops every other row and column of the previous one and skirts
+/// hanging down from the chunk borders hide the cracks between levels.
+///
+/// Drawn with its own pipeline, blending up to four textures by the weights in a splat map.
+pub struct Terrain {
+    pub desc: TerrainDesc,
+    pub height_field: HeightField,
+    chunks: Vec<TerrainChunk>,
+    index_buffer: wgpu::Buffer,
+    num_elements: u32,
+    lods: Vec<MeshLod>,
+
+    // kept for the bind group
+    _splat_map: Texture,
+    _layers: Vec<Handle<Texture>>,
+    _uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl Terrain {
+    /// Textures that fail to load are replaced and reported to `assets.report`,
+    /// only a missing heightmap fails.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        config: &wgpu::SurfaceConfiguration,
+        desc: &TerrainDesc,
+        assets: &mut AssetManager,
+    ) -> anyhow::Result<Self> {
+        let heightmap = vfs().read(&desc.heightmap)
+            .and_then(|bytes| Ok(image::load_from_memory(&bytes)?))
+            .with_context(|| format!("loading heightmap {}", desc.heightmap))?;
+        let chunk_size = desc.chunk_size.clamp(2, 256).next_power_of_two();
+        let chunks_per_side = desc.resolution.div_ceil(chunk_size).max(1);
+        let height_field = HeightField::from_image(&heightmap, chunks_per_side * chunk_size, desc.size, desc.height);
+
+        // level n uses every 2^n-th sample, the last one leaves two quads per chunk
+        let levels = (TERRAIN_LOD_LEVELS as u32).min(chunk_size.trailing_zeros());
+        let errors = (1..=levels).map(|level| height_field.level_error(1 << level)).collect::<Vec<_>>();
+        let skirt_depth = errors.iter().cloned().fold(height_field.spacing(), f32::max);
+
+        let indices = chunk_indices(chunk_size, 1);
+        let index_buffer = create_index_buffer(device, "terrain index buffer", &indices);
+        let lods = (1..=levels)
+            .map(|level| {
+                let indices = chunk_indices(chunk_size, 1 << level);
+                MeshLod {
+                    index_buffer: create_index_buffer(device, "terrain lod index buffer", &indices),
+                    num_elements: indices.len() as u32,
+                    screen_size: TERRAIN_LOD_SCREEN_SIZE / (1 << (level - 1)) as f32,
+                    error: errors[level as usize - 1],
+                }
+            })
+            .collect();
+
+        let mut chunks = Vec::new();
+        for chunk_z in 0..chunks_per_side {
+            for chunk_x in 0..chunks_per_side {
+                let vertices = chunk_vertices(&height_field, chunk_x * chunk_size, chunk_z * chunk_size, chunk_size, skirt_depth);
+                let points = vertices.iter().map(|v| Point3::from(v.position)).collect::<Vec<_>>();
+                chunks.push(TerrainChunk {
+                    vertex_buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some("terrain chunk vertex buffer"),
+                        contents: bytemuck::cast_slice(&vertices),
+                        usage: wgpu::BufferUsages::VERTEX,
+                    }),
+                    bounds: Aabb::from_points(points.iter().copied()),
+                    bounding_sphere: BoundingSphere::from_points(&points),
+                    lod: 0,
+                });
+            }
+        }
+
+        let splat_image = match &desc.splat_map {
+            Some(path) => match vfs().read(path).and_then(|bytes| Ok(image::load_from_memory(&bytes)?)) {
+                Ok(image) => image,
+                Err(e) => {
+                    assets.report.warn(format!("terrain splat map {} failed to load, generating one: {:#}", path, e));
+                    image::DynamicImage::ImageRgba8(height_field.generate_splat_map(desc.height))
+                }
+            },
+            None => image::DynamicImage::ImageRgba8(height_field.generate_splat_map(desc.height)),
+        };
+        // weights, not colors, so the linear format normal maps use
+        let splat_map = Texture::from_image(device, queue, &splat_image, Some("terrain splat map"), true)?;
+
+        if desc.layers.len() > MAX_TERRAIN_LAYERS {
+            assets.report.warn(format!("the terrain has {} layers, only the first {} are drawn", desc.layers.len(), MAX_TERRAIN_LAYERS));
+        }
+        let mut layers = Vec::new();
+        let mut uniform = TerrainUniform { tiling: [1.0; 4], layer_mask: [0.0; 4] };
+        for (i, layer) in desc.layers.iter().take(MAX_TERRAIN_LAYERS).enumerate() {
+            let (folder, file_name) = layer.texture.rsplit_once('/').unwrap_or(("", &layer.texture));
+            let texture = assets.load_texture(folder, file_name, device, queue, false).unwrap_or_else(|e| {
+                assets.report.warn(format!("terrain layer {} failed to load, drawing the missing texture instead: {:#}", layer.texture, e));
+                assets.missing_texture(device, queue)
+            });
+            layers.push(texture);
+            uniform.tiling[i] = layer.tiling;
+            uniform.layer_mask[i] = 1.0;
+        }
+        while layers.len() < MAX_TERRAIN_LAYERS {
+            layers.push(assets.white_texture(device, queue));
+        }
+
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("terrain uniform buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        // the layers repeat, the splat map doesn't
+        let layer_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let splat_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("terrain bind group"),
+            layout: &create_terrain_bind_group_layout(device),
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(&splat_map.view) },
+                wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::Sampler(&splat_sampler) },
+                wgpu::BindGroupEntry { binding: 2, resource: wgpu::BindingResource::TextureView(&layers[0].view) },
+                wgpu::BindGroupEntry { binding: 3, resource: wgpu::BindingResource::TextureView(&layers[1].view) },
+                wgpu::BindGroupEntry { binding: 4, resource: wgpu::BindingResource::TextureView(&layers[2].view) },
+                wgpu::BindGroupEntry { binding: 5, resource: wgpu::BindingResource::TextureView(&layers[3].view) },
+                wgpu::BindGroupEntry { binding: 6, resource: wgpu::BindingResource::Sampler(&layer_sampler) },
+                wgpu::BindGroupEntry { binding: 7, resource: uniform_buffer.as_entire_binding() },
+            ],
+        });
+
+        log::info!(
+            "terrain from {}: {} chunks of {} quads, lod errors {:?}",
+            desc.heightmap,
+            chunks.len(),
+            chunk_size * chunk_size,
+            errors,
+        );
+        Ok(Self {
+            desc: desc.clone(),
+            height_field,
+            chunks,
+            index_buffer,
+            num_elements: indices.len() as u32,
+            lods,
+            _splat_map: splat_map,
+            _layers: layers,
+            _uniform_buffer: uniform_buffer,
+            bind_group,
+            pipeline: create_terrain_pipeline(device, config)?,
+        })
+    }
+
+    // shader files the pipeline is built from, see `reload_pipelines`
+    pub const SHADERS: &'static [&'static str] = &["terrain.wgsl"];
+
+    /// Rebuilds the pipeline from the current shader file, the old one stays if that fails.
+    pub fn reload_pipelines(
+        &mut self,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+    ) {
+        if let Some(pipeline) = try_create(device, "terrain pipeline", || create_terrain_pipeline(device, config)) {
+            self.pipeline = pipeline;
+        }
+    }
+
+    pub fn chunks(&self) -> &[TerrainChunk] {
+        &self.chunks
+    }
+
+    #[allow(dead_code)]
+    pub fn height_at(&self, x: f32, z: f32) -> Option<f32> {
+        self.height_field.height_at(x, z)
+    }
+
+    pub fn update_lods(&mut self, camera: &Camera) {
+        for chunk in &mut self.chunks {
+            chunk.lod = select_lod(&self.lods, chunk.lod, screen_size(&chunk.bounding_sphere, camera));
+        }
+    }
+
+    /// Draws the chunks inside `frustum` and returns how many that were.
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        frustum: &Frustum,
+        camera_bind_group: &'a wgpu::BindGroup,
+        light_bind_group: &'a wgpu::BindGroup,
+    ) -> usize {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_bind_group(1, camera_bind_group, &[]);
+        render_pass.set_bind_group(2, light_bind_group, &[]);
+        let mut drawn = 0;
+        for chunk in self.chunks.iter().filter(|chunk| frustum.intersects_aabb(&chunk.bounds)) {
+            let (index_buffer, num_elements) = match chunk.lod.checked_sub(1) {
+                Some(i) => (&self.lods[i].index_buffer, self.lods[i].num_elements),
+                None => (&self.index_buffer, self.num_elements),
+            };
+            render_pass.set_vertex_buffer(0, chunk.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+            render_pass.draw_indexed(0..num_elements, 0, 0..1);
+            drawn += 1;
+        }
+        drawn
+    }
+}
+
+// the chunk's (size + 1)^2 grid samples row by row from sample (first_i, first_j), followed
+// by a copy of each of its four borders lowered by `skirt_depth`
+fn chunk_vertices(field: &HeightField, first_i: u32, first_j: u32, size: u32, skirt_depth: f32) -> Vec<ModelVertex> {
+    let quads = field.quads() as f32;
+    let vertex = |i: u32, j: u32, drop: f32| {
+        let (i, j) = (first_i + i, first_j + j);
+        let normal = field.sample_normal(i, j);
+        // along +x, where u grows, and perpendicular to the normal
+        let tangent = (Vector3::unit_x() - normal * normal.x).normalize();
+        let position = field.position(i, j);
+        ModelVertex {
+            position: [position.x, position.y - drop, position.z],
+            tex_coords: [i as f32 / quads, j as f32 / quads],
+            normal: normal.into(),
+            tangent: [tangent.x, tangent.y, tangent.z, 1.0],
+        }
+    };
+    let mut vertices = Vec::new();
+    for j in 0..=size {
+        for i in 0..=size {
+            vertices.push(vertex(i, j, 0.0));
+        }
+    }
+    for border in 0..4 {
+        for k in 0..=size {
+            let (i, j) = border_sample(border, k, size);
+            vertices.push(vertex(i, j, skirt_depth));
+        }
+    }
+    vertices
+}
+
+// sample `k` along border 0 (top), 1 (bottom), 2 (left) or 3 (right) of a chunk
+fn border_sample(border: u32, k: u32, size: u32) -> (u32, u32) {
+    match border {
+        0 => (k, 0),
+        1 => (k, size),
+        2 => (0, k),
+        _ => (size, k),
+    }
+}
+
+// triangles over every `step`th sample of a chunk laid out by `chunk_vertices`, and the skirts
+fn chunk_indices(size: u32, step: u32) -> Vec<u32> {
+    let grid = |i: u32, j: u32| j * (size + 1) + i;
+    let mut indices = Vec::new();
+    for j in (0..size).step_by(step as usize) {
+        for i in (0..size).step_by(step as usize) {
+            let [a, b, c, d] = [grid(i, j), grid(i + step, j), grid(i + step, j + step), grid(i, j + step)];
+            // counter clockwise seen from above
+            indices.extend([a, c, b, a, d, c]);
+        }
+    }
+    let skirt = |border: u32, k: u32| (size + 1) * (size + 1) + border * (size + 1) + k;
+    for border in 0..4 {
+        for k in (0..size).step_by(step as usize) {
+            let (i0, j0) = border_sample(border, k, size);
+            let (i1, j1) = border_sample(border, k + step, size);
+            let (top0, top1) = (grid(i0, j0), grid(i1, j1));
+            let (low0, low1) = (skirt(border, k), skirt(border, k + step));
+            // facing out of the chunk, the top and right borders run the other way around
+            match border {
+                0 | 3 => indices.extend([top0, low1, low0, top0, top1, low1]),
+                _ => indices.extend([top0, low0, low1, top0, low1, top1]),
+            }
+        }
+    }
+    indices
+}
+
+fn create_index_buffer(device: &wgpu::Device, label: &str, indices: &[u32]) -> wgpu::Buffer {
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some(label),
+        contents: bytemuck::cast_slice(indices),
+        usage: wgpu::BufferUsages::INDEX,
+    })
+}
+
+fn create_terrain_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    let texture = |binding| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            multisampled: false,
+            view_dimension: wgpu::TextureViewDimension::D2,
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+        },
+        count: None,
+    };
+    let sampler = |binding| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+        count: None,
+    };
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &[
+            texture(0),
+            sampler(1),
+            texture(2),
+            texture(3),
+            texture(4),
+            texture(5),
+            sampler(6),
+            wgpu::BindGroupLayoutEntry {
+                binding: 7,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ],
+        label: Some("terrain_bind_group_layout"),
+    })
+}
+
+fn create_terrain_pipeline(
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+) -> anyhow::Result<wgpu::RenderPipeline> {
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Terrain Pipeline Layout"),
+        bind_group_layouts: &[
+            &create_terrain_bind_group_layout(device),
+            &create_camera_bind_group_layout(device),
+            &create_light_bind_group_layout(device),
+        ],
+        push_constant_ranges: &[],
+    });
+    let shader = create_shader_module(device, "terrain.wgsl")?;
+
+    Ok(device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Terrain Pipeline"),
+        layout: Some(&layout),
+        vertex: VertexState {
+            module: &shader,
+            entry_point: "vs_main",
+            buffers: &[ModelVertex::desc()],
+        },
+        fragment: Some(FragmentState {
+            module: &shader,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format: config.format,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(wgpu::Face::Back),
+            polygon_mode: wgpu::PolygonMode::Fill,
+            unclipped_depth: false,
+            conservative: false,
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: wgpu::TextureFormat::Depth32Float,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: Default::default(),
+        multiview: None,
+    }))
+}
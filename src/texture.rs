@@ -1,8 +1,12 @@
-use std::fs::File;
 use image::{EncodableLayout, GenericImageView};
 use anyhow::*;
+use crate::model::load_binary;
 use crate::texture;
 
+pub const WHITE_TEXEL: [u8; 4] = [255, 255, 255, 255];
+const CHECKER_SIZE: u32 = 64;
+const CHECKER_CELL: u32 = 8;
+
 pub struct Texture {
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
@@ -125,6 +129,29 @@ impl Texture {
         })
     }
 
+    /// Texture of a single texel, for material slots without a texture file.
+    pub fn solid(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        color: [u8; 4],
+        label: &str,
+        is_normal_map: bool,
+    ) -> Result<Self> {
+        let img = image::RgbaImage::from_pixel(1, 1, image::Rgba(color));
+        Self::from_image(device, queue, &image::DynamicImage::ImageRgba8(img), Some(label), is_normal_map)
+    }
+
+    /// Magenta and black checkerboard, drawn where a texture failed to load so it stands out.
+    pub fn checkerboard(device: &wgpu::Device, queue: &wgpu::Queue, label: &str) -> Result<Self> {
+        let img = image::RgbaImage::from_fn(CHECKER_SIZE, CHECKER_SIZE, |x, y| {
+            match (x / CHECKER_CELL + y / CHECKER_CELL) % 2 {
+                0 => image::Rgba([255, 0, 255, 255]),
+                _ => image::Rgba([0, 0, 0, 255]),
+            }
+        });
+        Self::from_image(device, queue, &image::DynamicImage::ImageRgba8(img), Some(label), false)
+    }
+
     pub fn create_depth_texture(
         device: &wgpu::Device,
         config: &wgpu::SurfaceConfiguration,
@@ -171,6 +198,49 @@ impl Texture {
         }
     }
 
+    pub fn create_render_target(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        format: wgpu::TextureFormat,
+        label: &str) -> Self {
+        let size = wgpu::Extent3d {
+            width: config.width,
+            height: config.height,
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(
+            &wgpu::SamplerDescriptor {
+                address_mode_u: wgpu::AddressMode::ClampToEdge,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                address_mode_w: wgpu::AddressMode::ClampToEdge,
+                mag_filter: wgpu::FilterMode::Nearest,
+                min_filter: wgpu::FilterMode::Nearest,
+                mipmap_filter: wgpu::FilterMode::Nearest,
+                ..Default::default()
+            }
+        );
+
+        Self {
+            texture,
+            view,
+            sampler,
+            layout: None,
+            bind_group: None,
+        }
+    }
+
     pub fn get_layout(&self) -> &wgpu::BindGroupLayout {
         self.layout.as_ref().unwrap()
     }
@@ -182,7 +252,7 @@ impl Texture {
     pub fn create_bind_group(&self, device: &wgpu::Device) -> wgpu::BindGroup {
         let texture_bind_group_layout = create_texture_bind_group_layout(device);
         device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &create_texture_bind_group_layout(device),
+            layout: &texture_bind_group_layout,
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
@@ -205,26 +275,7 @@ pub fn load_texture(
     queue: &wgpu::Queue,
     is_normal_map: bool,
 ) -> anyhow::Result<texture::Texture> {
-    let path = std::path::Path::new(env!("OUT_DIR"))
-        .join("res")
-        .join(path_to_folder_in_res)
-        .join(file_name);
-    let data = std::fs::read(path)?;
-    texture::Texture::from_bytes(device, queue, &data, file_name, is_normal_map)
-}
-
-pub fn load_texture_model(
-    path_to_folder: &str,
-    file_name: &str,
-    device: &wgpu::Device,
-    queue: &wgpu::Queue,
-    is_normal_map: bool,
-) -> anyhow::Result<texture::Texture> {
-    let path = std::path::Path::new(env!("OUT_DIR"))
-        .join("res")
-        .join(path_to_folder)
-        .join(file_name);
-    let data = std::fs::read(path)?;
+    let data = load_binary(path_to_folder_in_res, file_name)?;
     texture::Texture::from_bytes(device, queue, &data, file_name, is_normal_map)
 }
 
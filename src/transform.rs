@@ -0,0 +1,282 @@
+use std::ops::Mul;
+use cgmath::{
+    ElementWise, EuclideanSpace, InnerSpace, Matrix3, Matrix4, One, Point3, Quaternion, Rotation,
+    SquareMatrix, Vector3, VectorSpace,
+};
+
+/// Translation, rotation and scale, applied to points in the order scale, rotate, translate.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Transform {
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+
+impl Transform {
+    pub fn identity() -> Self {
+        Self {
+            translation: Vector3::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    pub fn from_translation(translation: Vector3<f32>) -> Self {
+        Self {
+            translation,
+            ..Self::identity()
+        }
+    }
+
+    pub fn from_rotation(rotation: Quaternion<f32>) -> Self {
+        Self {
+            rotation,
+            ..Self::identity()
+        }
+    }
+
+    pub fn from_scale(scale: Vector3<f32>) -> Self {
+        Self {
+            scale,
+            ..Self::identity()
+        }
+    }
+
+    /// Placed at `eye` with its -z axis pointing at `target`, the same convention as the camera.
+    pub fn look_at(eye: Point3<f32>, target: Point3<f32>, up: Vector3<f32>) -> Self {
+        let back = (eye - target).normalize();
+        let right = up.cross(back).normalize();
+        let up = back.cross(right);
+        Self {
+            translation: Vector3::new(eye.x, eye.y, eye.z),
+            rotation: Matrix3::from_cols(right, up, back).into(),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    /// Splits an affine matrix back into translation, rotation and scale.
+    /// Shear can't be represented and is dropped; a mirrored matrix gets a negative x scale.
+    pub fn from_matrix(matrix: Matrix4<f32>) -> Self {
+        let translation = matrix.w.truncate();
+        let mut x = matrix.x.truncate();
+        let y = matrix.y.truncate();
+        let z = matrix.z.truncate();
+
+        let mut scale = Vector3::new(x.magnitude(), y.magnitude(), z.magnitude());
+        if x.cross(y).dot(z) < 0.0 {
+            scale.x = -scale.x;
+            x = -x;
+        }
+
+        let rotation = if scale.x == 0.0 || scale.y == 0.0 || scale.z == 0.0 {
+            Quaternion::one()
+        } else {
+            // re-orthogonalize so leftover shear doesn't skew the quaternion
+            let x = x.normalize();
+            let z = x.cross(y).normalize();
+            let y = z.cross(x);
+            Matrix3::from_cols(x, y, z).into()
+        };
+
+        Self {
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
+    pub fn to_matrix(self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.translation)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+
+    /// Matrix for transforming normals, the inverse transpose of the upper 3x3.
+    pub fn normal_matrix(&self) -> Matrix3<f32> {
+        let rotation = Matrix3::from(self.rotation);
+        let inverse_scale = Matrix3::from_diagonal(Vector3::new(1.0, 1.0, 1.0).div_element_wise(self.scale));
+        rotation * inverse_scale
+    }
+
+    /// Exact for uniform scale. With non-uniform scale the inverse contains shear,
+    /// which is dropped as in `from_matrix`.
+    pub fn inverse(&self) -> Self {
+        let uniform = self.scale.x == self.scale.y && self.scale.y == self.scale.z;
+        if !uniform {
+            return match self.to_matrix().invert() {
+                Some(inverse) => Self::from_matrix(inverse),
+                None => Self::identity(),
+            };
+        }
+
+        let rotation = self.rotation.invert();
+        let scale = 1.0 / self.scale.x;
+        Self {
+            translation: rotation.rotate_vector(-self.translation) * scale,
+            rotation,
+            scale: Vector3::new(scale, scale, scale),
+        }
+    }
+
+    /// Linear translation and scale, spherical rotation along the shorter arc.
+    pub fn lerp(&self, other: &Self, t: f32) -> Self {
+        let mut target = other.rotation;
+        if self.rotation.dot(target) < 0.0 {
+            target = -target;
+        }
+        Self {
+            translation: self.translation.lerp(other.translation, t),
+            rotation: self.rotation.slerp(target, t).normalize(),
+            scale: self.scale.lerp(other.scale, t),
+        }
+    }
+
+    pub fn transform_point(&self, point: Point3<f32>) -> Point3<f32> {
+        let scaled = Vector3::new(point.x, point.y, point.z).mul_element_wise(self.scale);
+        Point3::from_vec(self.rotation.rotate_vector(scaled) + self.translation)
+    }
+
+    pub fn transform_vector(&self, vector: Vector3<f32>) -> Vector3<f32> {
+        self.rotation.rotate_vector(vector.mul_element_wise(self.scale))
+    }
+
+    pub fn translate(&mut self, offset: Vector3<f32>) {
+        self.translation += offset;
+    }
+
+    /// Rotates around the transform's own origin, in its parent's axes.
+    pub fn rotate(&mut self, rotation: Quaternion<f32>) {
+        self.rotation = (rotation * self.rotation).normalize();
+    }
+
+    pub fn scale_by(&mut self, scale: Vector3<f32>) {
+        self.scale = self.scale.mul_element_wise(scale);
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl From<Matrix4<f32>> for Transform {
+    fn from(matrix: Matrix4<f32>) -> Self {
+        Self::from_matrix(matrix)
+    }
+}
+
+impl From<Transform> for Matrix4<f32> {
+    fn from(transform: Transform) -> Self {
+        transform.to_matrix()
+    }
+}
+
+/// `parent * child` applies `child` first, like matrix multiplication.
+impl Mul for Transform {
+    type Output = Transform;
+
+    fn mul(self, child: Transform) -> Transform {
+        Transform::from_matrix(self.to_matrix() * child.to_matrix())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Deg, Rotation3, Transform as _};
+    use super::*;
+
+    fn assert_matrix_eq(a: Matrix4<f32>, b: Matrix4<f32>) {
+        let a: [[f32; 4]; 4] = a.into();
+        let b: [[f32; 4]; 4] = b.into();
+        for (x, y) in a.iter().flatten().zip(b.iter().flatten()) {
+            assert!((x - y).abs() < 1e-4, "{:?} != {:?}", a, b);
+        }
+    }
+
+    fn assert_point_eq(a: Point3<f32>, b: Point3<f32>) {
+        assert!((a - b).magnitude() < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    fn sample() -> Transform {
+        Transform {
+            translation: Vector3::new(1.0, -2.0, 3.5),
+            rotation: Quaternion::from_axis_angle(Vector3::new(1.0, 2.0, -0.5).normalize(), Deg(70.0)),
+            scale: Vector3::new(2.0, 0.5, 3.0),
+        }
+    }
+
+    #[test]
+    fn matrix_round_trip() {
+        let transform = sample();
+        let decomposed = Transform::from_matrix(transform.to_matrix());
+        assert_matrix_eq(decomposed.to_matrix(), transform.to_matrix());
+        assert!((decomposed.scale - transform.scale).magnitude() < 1e-4);
+    }
+
+    #[test]
+    fn mirrored_matrix_round_trip() {
+        let mut transform = sample();
+        transform.scale.y = -transform.scale.y;
+        let decomposed = Transform::from_matrix(transform.to_matrix());
+        assert!(decomposed.scale.x < 0.0);
+        assert_matrix_eq(decomposed.to_matrix(), transform.to_matrix());
+    }
+
+    #[test]
+    fn matches_matrix_on_points() {
+        let transform = sample();
+        let point = Point3::new(0.3, -1.0, 2.0);
+        assert_point_eq(transform.transform_point(point), transform.to_matrix().transform_point(point));
+    }
+
+    #[test]
+    fn inverse_undoes_transform() {
+        let uniform = Transform {
+            scale: Vector3::new(2.0, 2.0, 2.0),
+            ..sample()
+        };
+        // non-uniform scale only inverts exactly without rotation, otherwise the inverse has shear
+        let unrotated = Transform {
+            rotation: Quaternion::one(),
+            ..sample()
+        };
+        for transform in [uniform, unrotated] {
+            let point = Point3::new(0.3, -1.0, 2.0);
+            assert_point_eq(transform.inverse().transform_point(transform.transform_point(point)), point);
+        }
+        assert_matrix_eq(uniform.inverse().to_matrix(), uniform.to_matrix().invert().unwrap());
+    }
+
+    #[test]
+    fn lerp_ends_and_shortest_arc() {
+        let a = Transform::identity();
+        let b = sample();
+        assert_matrix_eq(a.lerp(&b, 0.0).to_matrix(), a.to_matrix());
+        assert_matrix_eq(a.lerp(&b, 1.0).to_matrix(), b.to_matrix());
+
+        let halfway = a.lerp(&b, 0.5);
+        assert!((halfway.translation - b.translation * 0.5).magnitude() < 1e-4);
+        assert!((halfway.rotation.magnitude() - 1.0).abs() < 1e-4);
+
+        // the negated quaternion is the same rotation, so it must not take the long way round
+        let negated = Transform {
+            rotation: -b.rotation,
+            ..b
+        };
+        assert_matrix_eq(a.lerp(&negated, 0.5).to_matrix(), halfway.to_matrix());
+    }
+
+    #[test]
+    fn look_at_points_minus_z_at_target() {
+        let eye = Point3::new(1.0, 2.0, 3.0);
+        let target = Point3::new(-2.0, 0.5, -1.0);
+        let transform = Transform::look_at(eye, target, Vector3::unit_y());
+        assert_point_eq(transform.transform_point(Point3::origin()), eye);
+
+        let forward = transform.transform_vector(-Vector3::unit_z());
+        assert!((forward - (target - eye).normalize()).magnitude() < 1e-4);
+        assert!(transform.transform_vector(Vector3::unit_y()).y > 0.0);
+        assert_matrix_eq(transform.to_matrix(), Transform::from_matrix(transform.to_matrix()).to_matrix());
+    }
+}
@@ -0,0 +1,92 @@
+use cgmath::{Matrix4, Point3, Transform};
+use crate::culling::VisibleModel;
+use crate::model::{DrawModel, Material, Mesh, Model};
+
+/// How alpha blended meshes are composited. Picked once at startup.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum TransparencyMode {
+    // per mesh back to front sorting, breaks for intersecting meshes
+    #[default]
+    Sorted,
+    // weighted blended order independent transparency
+    WeightedBlended,
+}
+
+impl TransparencyMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sorted" => Some(TransparencyMode::Sorted),
+            "oit" => Some(TransparencyMode::WeightedBlended),
+            _ => None,
+        }
+    }
+}
+
+/// A blended mesh queued for the transparent pass.
+pub struct TransparentDraw<'a> {
+    pub model: &'a Model,
+    pub mesh: &'a Mesh,
+    pub material: &'a Material,
+    // view space z of the mesh center, more negative is further away
+    pub view_depth: f32,
+}
+
+/// Gathers every visible alpha blended mesh and sorts them back to front
+/// so they can be blended over each other and the opaque scene.
+/// Instances of a mesh are sorted as one draw at the model's position.
+pub fn sort_transparent<'a>(
+    visible: &[VisibleModel<'a>],
+    view: Matrix4<f32>,
+) -> Vec<TransparentDraw<'a>> {
+    let mut draws = Vec::new();
+    for &VisibleModel { model, ref meshes } in visible {
+        let model_view = view * model.model_matrix.matrix();
+        for &mesh in meshes {
+            let material = &model.materials[mesh.geometry.material];
+            if !material.is_transparent() {
+                continue;
+            }
+            let center = model_view.transform_point(Point3::from(mesh.geometry.center));
+            draws.push(TransparentDraw {
+                model,
+                mesh,
+                material,
+                view_depth: center.z,
+            });
+        }
+    }
+    draws.sort_by(|a, b| a.view_depth.total_cmp(&b.view_depth));
+    draws
+}
+
+pub trait DrawTransparent<'a> {
+    fn draw_transparent(
+        &mut self,
+        draws: &[TransparentDraw<'a>],
+        camera_bind_group: &'a wgpu::BindGroup,
+        light_bind_group: &'a wgpu::BindGroup,
+    );
+}
+
+impl<'a, 'b> DrawTransparent<'b> for wgpu::RenderPass<'a>
+    where
+        'b: 'a,
+{
+    fn draw_transparent(
+        &mut self,
+        draws: &[TransparentDraw<'b>],
+        camera_bind_group: &'b wgpu::BindGroup,
+        light_bind_group: &'b wgpu::BindGroup,
+    ) {
+        for draw in draws {
+            self.set_vertex_buffer(1, draw.model.instance_buffer().slice(..));
+            self.draw_mesh_instanced(
+                draw.mesh,
+                draw.material,
+                draw.model.instance_range(),
+                camera_bind_group,
+                light_bind_group,
+            );
+        }
+    }
+}
@@ -0,0 +1,226 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use anyhow::Context;
+use crate::archive::{Archive, ARCHIVE_EXTENSION};
+use crate::constants::ASSET_ROOT_ENV;
+
+/// Somewhere asset files can be read from, like a directory or an archive.
+/// Paths are relative to the asset root, `/` separated and normalized by `normalize_path`.
+pub trait AssetSource: Send + Sync {
+    /// Fails with `io::ErrorKind::NotFound` for files the source doesn't have.
+    fn read(&self, path: &str) -> io::Result<Vec<u8>>;
+
+    /// The directory the files are read from, if they are plain files.
+    fn dir(&self) -> Option<&Path> {
+        None
+    }
+
+    // for logs and errors
+    fn describe(&self) -> String;
+}
+
+/// Files in a directory on disk.
+pub struct DirSource {
+    root: PathBuf,
+}
+
+impl DirSource {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+impl AssetSource for DirSource {
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        // normalized paths only start with .. when they leave the root
+        if path.starts_with("..") {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "path leaves the asset root"));
+        }
+        let full_path = path.split('/').fold(self.root.clone(), |full_path, part| full_path.join(part));
+        std::fs::read(full_path)
+    }
+
+    fn dir(&self) -> Option<&Path> {
+        Some(&self.root)
+    }
+
+    fn describe(&self) -> String {
+        self.root.display().to_string()
+    }
+}
+
+impl AssetSource for Archive {
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        Archive::read(self, path)
+    }
+
+    fn describe(&self) -> String {
+        self.name().to_string()
+    }
+}
+
+/// The archive the build script packed from `res/`, see the `embed-assets` feature.
+#[cfg(feature = "embed-assets")]
+pub fn embedded_archive() -> anyhow::Result<Archive> {
+    static EMBEDDED: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/res.pak"));
+    Archive::from_bytes("embedded assets", std::borrow::Cow::Borrowed(EMBEDDED))
+}
+
+/// Every mounted `AssetSource` seen as one tree. Sources mounted first win when
+/// more than one has a file.
+#[derive(Default)]
+pub struct Vfs {
+    sources: Vec<Box<dyn AssetSource>>,
+}
+
+impl Vfs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn mount(mut self, source: impl AssetSource + 'static) -> Self {
+        log::info!("reading assets from {}", source.describe());
+        self.sources.push(Box::new(source));
+        self
+    }
+
+    /// Mounts a directory, or an archive if `path` is a file.
+    pub fn mount_path(self, path: &Path) -> anyhow::Result<Self> {
+        if path.is_file() {
+            Ok(self.mount(Archive::open(path)?))
+        } else {
+            Ok(self.mount(DirSource::new(path)))
+        }
+    }
+
+    /// `root` as picked by `asset_root`, followed by the embedded archive when there is one.
+    pub fn with_root(root: &Path) -> anyhow::Result<Self> {
+        let vfs = Self::new().mount_path(root)?;
+        #[cfg(feature = "embed-assets")]
+        let vfs = vfs.mount(embedded_archive()?);
+        Ok(vfs)
+    }
+
+    /// `with_root`, or just the build script's copy of the assets when `root` can't be opened,
+    /// a corrupt archive for example.
+    pub fn open(root: &Path) -> Self {
+        Self::with_root(root).unwrap_or_else(|e| {
+            let fallback = build_res_dir();
+            log::error!("can't open the assets at {}, reading them from {} instead: {:#}", root.display(), fallback.display(), e);
+            Self::new().mount(DirSource::new(fallback))
+        })
+    }
+
+    pub fn read(&self, path: &str) -> anyhow::Result<Vec<u8>> {
+        let path = normalize_path(path);
+        for source in &self.sources {
+            match source.read(&path) {
+                Ok(data) => return Ok(data),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e).with_context(|| format!("reading {} from {}", path, source.describe())),
+            }
+        }
+        anyhow::bail!("{} not found in {}", path, self.describe())
+    }
+
+    pub fn read_string(&self, path: &str) -> anyhow::Result<String> {
+        let data = self.read(path)?;
+        String::from_utf8(data).with_context(|| format!("{} is not UTF-8", path))
+    }
+
+    /// The first mounted directory, where edited files can be watched.
+    pub fn dir(&self) -> Option<&Path> {
+        self.sources.iter().find_map(|source| source.dir())
+    }
+
+    fn describe(&self) -> String {
+        match self.sources.len() {
+            0 => "no asset sources".to_string(),
+            _ => self.sources.iter().map(|source| source.describe()).collect::<Vec<_>>().join(", "),
+        }
+    }
+}
+
+static VFS: OnceLock<Vfs> = OnceLock::new();
+
+/// What `load_string` and `load_binary` read from. Unless `set_vfs` was called first
+/// this is the directory or archive picked by `asset_root`.
+pub fn vfs() -> &'static Vfs {
+    VFS.get_or_init(|| Vfs::open(&asset_root(None)))
+}
+
+/// Replaces the default file system, has to be called before the first asset is read.
+pub fn set_vfs(vfs: Vfs) -> anyhow::Result<()> {
+    VFS.set(vfs).map_err(|_| anyhow::anyhow!("assets were already read from {}", self::vfs().describe()))
+}
+
+/// Picks the asset directory or archive: `dir` if given, then the path in the environment
+/// variable `ASSET_ROOT_ENV`, then `res` or `res.pak` next to the executable and finally
+/// the copy the build script made, which only exists on the machine that built the binary.
+pub fn asset_root(dir: Option<&Path>) -> PathBuf {
+    if let Some(dir) = dir {
+        return dir.to_path_buf();
+    }
+    if let Some(dir) = std::env::var_os(ASSET_ROOT_ENV) {
+        return PathBuf::from(dir);
+    }
+    let next_to_exe = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+        .and_then(|exe_dir| {
+            let archive = exe_dir.join("res").with_extension(ARCHIVE_EXTENSION);
+            [exe_dir.join("res"), archive].into_iter().find(|path| path.exists())
+        });
+    next_to_exe.unwrap_or_else(build_res_dir)
+}
+
+fn build_res_dir() -> PathBuf {
+    Path::new(env!("OUT_DIR")).join("res")
+}
+
+/// `/` separated path without empty, `.` or `..` parts, whichever separators `path` used.
+/// `..` parts only remain at the start when the path leaves its root.
+pub fn normalize_path(path: &str) -> String {
+    let mut parts = Vec::new();
+    for part in path.split(['/', '\\']) {
+        match part {
+            "" | "." => {}
+            ".." if parts.last().is_some_and(|last| *last != "..") => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_path_resolves_parent_parts() {
+        assert_eq!(normalize_path("models/blob/../d20/d20.obj"), "models/d20/d20.obj");
+        assert_eq!(normalize_path("models/./blob/./blob.obj"), "models/blob/blob.obj");
+        assert_eq!(normalize_path("a/b/../../c"), "c");
+        // leaving the root keeps the `..` parts
+        assert_eq!(normalize_path("../a"), "../a");
+        assert_eq!(normalize_path("a/../../b"), "../b");
+        assert_eq!(normalize_path("../../a/.."), "../..");
+    }
+
+    #[test]
+    fn normalize_path_accepts_backslashes() {
+        assert_eq!(normalize_path("models\\blob\\blob.obj"), "models/blob/blob.obj");
+        assert_eq!(normalize_path("models\\blob/..\\d20\\d20.obj"), "models/d20/d20.obj");
+    }
+
+    #[test]
+    fn normalize_path_drops_leading_and_repeated_separators() {
+        assert_eq!(normalize_path("/shaders/shader.wgsl"), "shaders/shader.wgsl");
+        assert_eq!(normalize_path("\\shaders\\\\shader.wgsl"), "shaders/shader.wgsl");
+        assert_eq!(normalize_path("shaders//shader.wgsl/"), "shaders/shader.wgsl");
+        assert_eq!(normalize_path("/"), "");
+    }
+}